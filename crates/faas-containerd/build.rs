@@ -0,0 +1,31 @@
+use std::process::Command;
+
+// 构建时记录 git 提交信息, 环境变量中已经给出时 (例如 nix 构建) 以环境变量为准
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT_MESSAGE");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs/heads");
+
+    for (name, args) in [
+        ("GIT_SHA", &["rev-parse", "HEAD"][..]),
+        ("GIT_COMMIT_MESSAGE", &["log", "-1", "--format=%s"][..]),
+    ] {
+        if std::env::var_os(name).is_some() {
+            continue;
+        }
+        if let Some(value) = git(args) {
+            println!("cargo:rustc-env={}={}", name, value);
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
@@ -0,0 +1,448 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use derive_more::Display;
+use gateway::types::config::{FaaSConfig, duration_secs};
+use serde::Deserialize;
+
+use crate::{consts, provider::idle::IdleMode};
+
+/// Configuration file read when `FAASRS_CONFIG` is not set, it is fine for it to be missing
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/faasd-rs/config.toml";
+
+#[derive(Debug, Display)]
+pub enum ConfigError {
+    #[display("failed to read {}: {}", _0.display(), _1)]
+    Read(PathBuf, std::io::Error),
+    #[display("failed to parse {}: {}", _0.display(), _1)]
+    Parse(PathBuf, toml::de::Error),
+    #[display("invalid value {:?} for {}: {}", _1, _0, _2)]
+    Env(&'static str, String, String),
+    #[display("invalid configuration: {}", _0)]
+    Invalid(String),
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Daemon configuration, read from a TOML file then overridden by environment variables
+///
+/// ```toml
+/// [gateway]
+/// tcp_port = 8081
+/// read_timeout = 30
+///
+/// [containerd]
+/// data_dir = "/srv/faasdrs"
+///
+/// [containerd.cni]
+/// subnet = "10.70.0.0/16"
+///
+/// [containerd.scale_to_zero]
+/// idle_timeout = 600
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub gateway: FaaSConfig,
+    pub containerd: ContainerdConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerdConfig {
+    /// containerd GRPC socket
+    pub socket_path: PathBuf,
+    /// Database, secrets and logs of the provider
+    pub data_dir: PathBuf,
+    pub snapshotter: String,
+    pub cni: CniConfig,
+    pub scale_to_zero: ScaleToZeroConfig,
+    pub gc: GcConfig,
+    pub logs: LogsConfig,
+}
+
+impl Default for ContainerdConfig {
+    fn default() -> Self {
+        Self {
+            socket_path: PathBuf::from(consts::DEFAULT_CTRD_SOCK),
+            data_dir: PathBuf::from(consts::DEFAULT_FAASDRS_DATA_DIR),
+            snapshotter: consts::DEFAULT_SNAPSHOTTER.to_string(),
+            cni: CniConfig::default(),
+            scale_to_zero: ScaleToZeroConfig::default(),
+            gc: GcConfig::default(),
+            logs: LogsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CniConfig {
+    /// Where the network config list of the bridge is written
+    pub conf_dir: PathBuf,
+    /// CNI plugins
+    pub bin_dir: PathBuf,
+    /// `cni-tool` executable, looked up in `PATH` if not absolute
+    pub tool: String,
+    /// IPv4 range the function instances get their address from
+    pub subnet: String,
+}
+
+impl Default for CniConfig {
+    fn default() -> Self {
+        Self {
+            conf_dir: PathBuf::from(consts::DEFAULT_CNI_CONF_DIR),
+            bin_dir: PathBuf::from(consts::DEFAULT_CNI_BIN_DIR),
+            tool: consts::DEFAULT_CNI_TOOL.to_string(),
+            subnet: consts::DEFAULT_SUBNET.to_string(),
+        }
+    }
+}
+
+/// Idle controller of the functions labelled `com.openfaas.scale.zero=true`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScaleToZeroConfig {
+    /// Time without invocations after which the tasks of a function are stopped,
+    /// unless the function sets its own through `com.openfaas.scale.zero-duration`
+    #[serde(deserialize_with = "duration_secs")]
+    pub idle_timeout: Duration,
+    /// How often the functions are checked
+    #[serde(deserialize_with = "duration_secs")]
+    pub interval: Duration,
+    /// How long an invocation waits for a stopped function to listen again
+    #[serde(deserialize_with = "duration_secs")]
+    pub cold_start_timeout: Duration,
+    /// What idling does to the tasks, unless the function sets `com.faasrs.scale.zero-mode`
+    pub mode: IdleMode,
+}
+
+impl Default for ScaleToZeroConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: consts::DEFAULT_IDLE_TIMEOUT,
+            interval: consts::DEFAULT_IDLE_CHECK_INTERVAL,
+            cold_start_timeout: consts::DEFAULT_COLD_START_TIMEOUT,
+            mode: IdleMode::default(),
+        }
+    }
+}
+
+/// Garbage collection of what deleted functions and replicas left behind
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GcConfig {
+    /// How often the collector runs in the background
+    #[serde(deserialize_with = "duration_secs")]
+    pub interval: Duration,
+    /// Age under which nothing is collected, a deployment may still be creating it
+    #[serde(deserialize_with = "duration_secs")]
+    pub grace_period: Duration,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            interval: consts::DEFAULT_GC_INTERVAL,
+            grace_period: consts::DEFAULT_GC_GRACE_PERIOD,
+        }
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(name: &'static str, value: String) -> Result<T, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| ConfigError::Env(name, value, e.to_string()))
+}
+
+/// Seconds like [`duration_secs`] in the file, `10` or `0.5`
+fn parse_env_secs(name: &'static str, value: String) -> Result<Duration, ConfigError> {
+    let secs = parse_env::<f64>(name, value.clone())?;
+    Duration::try_from_secs_f64(secs).map_err(|e| ConfigError::Env(name, value, e.to_string()))
+}
+
+/// Limits of the function output kept under `<data dir>/logs`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogsConfig {
+    /// Size in bytes a log file of one instance grows to before it is rotated
+    pub max_size: u64,
+    /// Rotated files kept beside the current one of every instance
+    pub max_files: u32,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            max_size: consts::DEFAULT_LOG_MAX_SIZE,
+            max_files: consts::DEFAULT_LOG_MAX_FILES,
+        }
+    }
+}
+
+impl Config {
+    /// Read the file named by `FAASRS_CONFIG`, or [`DEFAULT_CONFIG_FILE`] if it exists,
+    /// apply the environment overrides and validate the result
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var_os("FAASRS_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Environment variables take precedence over the file
+    pub fn apply_env(
+        &mut self,
+        var: impl Fn(&'static str) -> Option<String>,
+    ) -> Result<(), ConfigError> {
+        let containerd = &mut self.containerd;
+        if let Some(value) = var("SOCKET_PATH") {
+            containerd.socket_path = value.into();
+        }
+        if let Some(value) = var("FAASRS_DATA_DIR") {
+            containerd.data_dir = value.into();
+        }
+        if let Some(value) = var("FAASRS_SNAPSHOTTER") {
+            containerd.snapshotter = value;
+        }
+        if let Some(value) = var("CNI_CONF_DIR") {
+            containerd.cni.conf_dir = value.into();
+        }
+        if let Some(value) = var("CNI_BIN_DIR") {
+            containerd.cni.bin_dir = value.into();
+        }
+        if let Some(value) = var("CNI_TOOL") {
+            containerd.cni.tool = value;
+        }
+        if let Some(value) = var("FAASRS_SUBNET") {
+            containerd.cni.subnet = value;
+        }
+        if let Some(value) = var("FAASRS_SCALE_ZERO_IDLE") {
+            containerd.scale_to_zero.idle_timeout =
+                parse_env_secs("FAASRS_SCALE_ZERO_IDLE", value)?;
+        }
+
+        let gateway = &mut self.gateway;
+        if let Some(value) = var("FAASRS_PORT") {
+            gateway.tcp_port = Some(parse_env("FAASRS_PORT", value)?);
+        }
+        if let Some(value) = var("FAASRS_READ_TIMEOUT") {
+            gateway.read_timeout = parse_env_secs("FAASRS_READ_TIMEOUT", value)?;
+        }
+        if let Some(value) = var("FAASRS_WRITE_TIMEOUT") {
+            gateway.write_timeout = parse_env_secs("FAASRS_WRITE_TIMEOUT", value)?;
+        }
+        if let Some(value) = var("FAASRS_HEALTH") {
+            gateway.enable_health = parse_env("FAASRS_HEALTH", value)?;
+        }
+        if let Some(value) = var("FAASRS_BASIC_AUTH") {
+            gateway.enable_basic_auth = parse_env("FAASRS_BASIC_AUTH", value)?;
+        }
+        if let Some(value) = var("FAASRS_SECRET_MOUNT_PATH") {
+            gateway.secret_mount_path = value;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.gateway.validate().map_err(ConfigError::Invalid)?;
+
+        let containerd = &self.containerd;
+        if !containerd.data_dir.is_absolute() {
+            return Err(ConfigError::Invalid(format!(
+                "data_dir {} must be an absolute path",
+                containerd.data_dir.display()
+            )));
+        }
+        if containerd.snapshotter.is_empty() {
+            return Err(ConfigError::Invalid(
+                "snapshotter must not be empty".to_string(),
+            ));
+        }
+        if containerd.cni.tool.is_empty() {
+            return Err(ConfigError::Invalid(
+                "cni.tool must not be empty".to_string(),
+            ));
+        }
+        let scale_to_zero = &containerd.scale_to_zero;
+        if scale_to_zero.idle_timeout.is_zero()
+            || scale_to_zero.interval.is_zero()
+            || scale_to_zero.cold_start_timeout.is_zero()
+        {
+            return Err(ConfigError::Invalid(
+                "scale_to_zero durations must be positive".to_string(),
+            ));
+        }
+        if containerd.gc.interval.is_zero() {
+            return Err(ConfigError::Invalid(
+                "gc.interval must be positive".to_string(),
+            ));
+        }
+        if containerd.logs.max_size == 0 {
+            return Err(ConfigError::Invalid(
+                "logs.max_size must be positive".to_string(),
+            ));
+        }
+        let subnet: cidr::Ipv4Cidr = containerd.cni.subnet.parse().map_err(|e| {
+            ConfigError::Invalid(format!("cni.subnet {}: {}", containerd.cni.subnet, e))
+        })?;
+        // the bridge takes the first address, leave room for at least a few functions
+        if subnet.network_length() > 29 {
+            return Err(ConfigError::Invalid(format!(
+                "cni.subnet {} is too small",
+                containerd.cni.subnet
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_file_and_env() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [gateway]
+            tcp_port = 8081
+            read_timeout = 2.5
+
+            [containerd]
+            data_dir = "/srv/faasdrs"
+
+            [containerd.cni]
+            subnet = "10.70.0.0/16"
+
+            [containerd.scale_to_zero]
+            idle_timeout = 600
+            cold_start_timeout = 10
+            mode = "pause"
+
+            [containerd.gc]
+            interval = 3600
+
+            [containerd.logs]
+            max_size = 1048576
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.gateway.tcp_port, Some(8081));
+        assert_eq!(config.gateway.read_timeout, Duration::from_millis(2500));
+        assert_eq!(config.containerd.snapshotter, consts::DEFAULT_SNAPSHOTTER);
+        assert_eq!(config.containerd.cni.tool, consts::DEFAULT_CNI_TOOL);
+        assert_eq!(
+            config.containerd.scale_to_zero.idle_timeout,
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            config.containerd.scale_to_zero.interval,
+            consts::DEFAULT_IDLE_CHECK_INTERVAL
+        );
+        assert_eq!(
+            config.containerd.scale_to_zero.cold_start_timeout,
+            Duration::from_secs(10)
+        );
+        assert_eq!(config.containerd.scale_to_zero.mode, IdleMode::Pause);
+        assert_eq!(config.containerd.gc.interval, Duration::from_secs(3600));
+        assert_eq!(
+            config.containerd.gc.grace_period,
+            consts::DEFAULT_GC_GRACE_PERIOD
+        );
+        assert_eq!(config.containerd.logs.max_size, 1 << 20);
+        assert_eq!(
+            config.containerd.logs.max_files,
+            consts::DEFAULT_LOG_MAX_FILES
+        );
+
+        assert!(!config.gateway.enable_health);
+
+        let env = HashMap::from([
+            ("FAASRS_PORT", "9090"),
+            ("CNI_TOOL", "/usr/bin/cni-tool"),
+            ("FAASRS_HEALTH", "true"),
+            ("FAASRS_WRITE_TIMEOUT", "30"),
+            ("FAASRS_READ_TIMEOUT", "1.5"),
+        ]);
+        config
+            .apply_env(|name| env.get(name).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.gateway.tcp_port, Some(9090));
+        assert!(config.gateway.enable_health);
+        assert_eq!(config.gateway.write_timeout, Duration::from_secs(30));
+        assert_eq!(config.gateway.read_timeout, Duration::from_millis(1500));
+        assert_eq!(config.containerd.cni.tool, "/usr/bin/cni-tool");
+        assert_eq!(config.containerd.data_dir, PathBuf::from("/srv/faasdrs"));
+        config.validate().unwrap();
+
+        let env = HashMap::from([("FAASRS_PORT", "http")]);
+        assert!(matches!(
+            config.apply_env(|name| env.get(name).map(|v| v.to_string())),
+            Err(ConfigError::Env("FAASRS_PORT", _, _))
+        ));
+        let env = HashMap::from([("FAASRS_SCALE_ZERO_IDLE", "-1")]);
+        assert!(matches!(
+            config.apply_env(|name| env.get(name).map(|v| v.to_string())),
+            Err(ConfigError::Env("FAASRS_SCALE_ZERO_IDLE", _, _))
+        ));
+        let env = HashMap::from([("FAASRS_HEALTH", "yes")]);
+        assert!(matches!(
+            config.apply_env(|name| env.get(name).map(|v| v.to_string())),
+            Err(ConfigError::Env("FAASRS_HEALTH", _, _))
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        Config::default().validate().unwrap();
+        assert!(Config::default().gateway.ignored_settings().is_empty());
+        let mut config = Config::default();
+        config.gateway.max_idle_conns_per_host = 100;
+        assert_eq!(config.gateway.ignored_settings().len(), 1);
+
+        let mut config = Config::default();
+        config.containerd.cni.subnet = "10.66.0.0/30".to_string();
+        assert!(config.validate().is_err());
+        config.containerd.cni.subnet = "not a subnet".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.containerd.data_dir = PathBuf::from("relative");
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.containerd.scale_to_zero.interval = Duration::ZERO;
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.containerd.scale_to_zero.cold_start_timeout = Duration::ZERO;
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.containerd.gc.interval = Duration::ZERO;
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        config.containerd.logs.max_size = 0;
+        assert!(config.validate().is_err());
+
+        assert!(toml::from_str::<Config>("[gateway]\nunknown = 1").is_err());
+    }
+}
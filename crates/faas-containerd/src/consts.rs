@@ -1,15 +1,89 @@
-#[allow(unused)]
-pub const DEFAULT_FUNCTION_NAMESPACE: &str = "faasrs-default";
+use std::time::Duration;
+
+pub use gateway::types::function::DEFAULT_FUNCTION_NAMESPACE;
 
-#[allow(unused)]
 pub const DEFAULT_SNAPSHOTTER: &str = "overlayfs";
 
 pub const DEFAULT_CTRD_SOCK: &str = "/run/containerd/containerd.sock";
 
 pub const DEFAULT_FAASDRS_DATA_DIR: &str = "/var/lib/faasdrs";
 
+pub const DEFAULT_CNI_CONF_DIR: &str = "/etc/cni/net.d";
+pub const DEFAULT_CNI_BIN_DIR: &str = "/opt/cni/bin";
+pub const DEFAULT_CNI_TOOL: &str = "cni-tool";
+pub const DEFAULT_SUBNET: &str = "10.66.0.0/16";
+
+/// Where secrets are mounted inside function containers
+pub const SECRET_MOUNT_DIR: &str = "/var/openfaas/secrets";
+
+/// Container label holding the name of the function a container belongs to
+pub const FUNCTION_LABEL: &str = "faasrs.function";
+/// Container label holding the replica index of the container inside its function
+pub const REPLICA_LABEL: &str = "faasrs.replica";
+/// Container label holding the comma separated secrets mounted into the container
+pub const SECRETS_LABEL: &str = "faasrs.secrets";
+/// Label marking the containerd namespaces managed through `/system/namespace`
+pub const NAMESPACE_LABEL: &str = "openfaas";
+pub const NAMESPACE_LABEL_VALUE: &str = "1";
+/// Prefix of the namespace labels holding the annotations of a namespace
+pub const NAMESPACE_ANNOTATION_PREFIX: &str = "openfaas.annotation/";
+/// Deployment label for the number of replicas created on deploy
+pub const MIN_REPLICAS_LABEL: &str = "com.openfaas.scale.min";
+pub const DEFAULT_REPLICAS: u32 = 1;
+/// Deployment label selecting how invocations are spread over the replicas
+pub const LOAD_BALANCER_LABEL: &str = "com.faasrs.loadbalancer";
+/// Deployment annotation with the port the function listens on, wins over the image
+pub const PORT_ANNOTATION: &str = "com.faasrs.port";
+/// Container label holding the port the container serves on
+pub const PORT_LABEL: &str = "faasrs.port";
+/// Port of the functions setting none and exposing none in their image
+pub const DEFAULT_FUNCTION_PORT: u16 = 8080;
+/// Deployment label opting the function into scale to zero, `true` or `false`
+pub const SCALE_ZERO_LABEL: &str = "com.openfaas.scale.zero";
+/// Deployment label overriding how long the function may stay idle, e.g. `15m`
+pub const SCALE_ZERO_DURATION_LABEL: &str = "com.openfaas.scale.zero-duration";
+/// Deployment label choosing what idling does to the tasks, `stop` or `pause`
+pub const SCALE_ZERO_MODE_LABEL: &str = "com.faasrs.scale.zero-mode";
+/// Deployment label keeping that many prepared replicas beside the serving ones
+pub const MIN_WARM_LABEL: &str = "com.faasrs.scale.min-warm";
+pub const WARM_POOL_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+pub const DEFAULT_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_COLD_START_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+pub const DEFAULT_GC_GRACE_PERIOD: Duration = Duration::from_secs(5 * 60);
+/// How often the output of the instances is stamped and moved to the log files
+pub const LOG_COLLECT_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_LOG_MAX_SIZE: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_LOG_MAX_FILES: u32 = 3;
+
 // 定义版本的常量
 pub const VERSION_MAJOR: u32 = 1;
 pub const VERSION_MINOR: u32 = 1;
 pub const VERSION_PATCH: u32 = 0;
 pub const VERSION_DEV: &str = ""; // 对应开发分支
+
+/// Release of the provider itself, the OCI runtime spec version is [`version`]
+pub const PROVIDER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the binary was built from, set through `GIT_SHA` or read from git by the build script
+pub const GIT_SHA: &str = match option_env!("GIT_SHA") {
+    Some(sha) => sha,
+    None => "",
+};
+
+/// Subject of that commit, set through `GIT_COMMIT_MESSAGE` or read from git by the build script
+pub const GIT_COMMIT_MESSAGE: &str = match option_env!("GIT_COMMIT_MESSAGE") {
+    Some(message) => message,
+    None => "",
+};
+
+pub const PROVIDER_NAME: &str = "faas-containerd";
+pub const ORCHESTRATION: &str = "containerd";
+
+pub fn version() -> String {
+    format!(
+        "{}.{}.{}{}",
+        VERSION_MAJOR, VERSION_MINOR, VERSION_PATCH, VERSION_DEV
+    )
+}
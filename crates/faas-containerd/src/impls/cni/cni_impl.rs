@@ -4,27 +4,24 @@ use derive_more::{Display, Error};
 use netns_rs::NetNs;
 use scopeguard::{ScopeGuard, guard};
 use serde_json::Value;
-use std::{fmt::Error, net::IpAddr, path::Path, sync::LazyLock};
+use std::{net::IpAddr, path::Path, time::SystemTime};
 
 use super::{Endpoint, command as cmd, util};
-
-static CNI_CONF_DIR: LazyLock<String> = LazyLock::new(|| {
-    std::env::var("CNI_CONF_DIR").unwrap_or_else(|_| "/etc/cni/net.d".to_string())
-});
+use crate::config::CniConfig;
 
 const CNI_DATA_DIR: &str = "/var/run/cni";
 const DEFAULT_CNI_CONF_FILENAME: &str = "10-faasrs.conflist";
-const DEFAULT_NETWORK_NAME: &str = "faasrs-cni-bridge";
+pub const DEFAULT_NETWORK_NAME: &str = "faasrs-cni-bridge";
 const DEFAULT_BRIDGE_NAME: &str = "faasrs0";
-const DEFAULT_SUBNET: &str = "10.66.0.0/16";
+/// Where netns-rs and `ip netns` keep the named network namespaces
+const NETNS_DIR: &str = "/var/run/netns";
 
-pub fn init_cni_network() -> Result<(), Err> {
+pub fn init_cni_network(config: &CniConfig) -> Result<(), Err> {
     util::init_net_fs(
-        Path::new(CNI_CONF_DIR.as_str()),
+        config,
         DEFAULT_CNI_CONF_FILENAME,
         DEFAULT_NETWORK_NAME,
         DEFAULT_BRIDGE_NAME,
-        DEFAULT_SUBNET,
         CNI_DATA_DIR,
     )
 }
@@ -104,7 +101,12 @@ pub fn create_cni_network(endpoint: &Endpoint) -> Result<(cidr::IpInet, NetNs),
 }
 
 pub fn delete_cni_network(endpoint: Endpoint) -> Result<(), NetworkError> {
-    match NetNs::get(endpoint.to_string()) {
+    delete_netns(&endpoint.to_string())
+}
+
+/// Release the address of the network namespace `name` and remove it
+pub fn delete_netns(name: &str) -> Result<(), NetworkError> {
+    match NetNs::get(name) {
         Ok(ns) => {
             let e1 = cmd::cni_del_bridge(ns.path(), DEFAULT_NETWORK_NAME);
             let e2 = ns.remove();
@@ -119,13 +121,18 @@ pub fn delete_cni_network(endpoint: Endpoint) -> Result<(), NetworkError> {
             Ok(())
         }
         Err(e) => {
-            let msg = format!("Failed to get netns {}: {}", endpoint, e);
+            let msg = format!("Failed to get netns {}: {}", name, e);
             log::warn!("{}", msg);
             Err(NetworkError { msg })
         }
     }
 }
 
+/// Whether the network namespace of the instance is there
+pub fn network_exists(endpoint: &Endpoint) -> bool {
+    NetNs::get(endpoint.to_string()).is_ok()
+}
+
 #[inline]
 pub fn check_network_exists(addr: IpAddr) -> bool {
     util::CNI_CONFIG_FILE
@@ -136,13 +143,64 @@ pub fn check_network_exists(addr: IpAddr) -> bool {
         .exists()
 }
 
-#[allow(unused)]
-fn cni_gateway() -> Result<String, Err> {
-    let ip: IpAddr = DEFAULT_SUBNET.parse().unwrap();
-    if let IpAddr::V4(ip) = ip {
-        let octets = &mut ip.octets();
-        octets[3] = 1;
-        return Ok(ip.to_string());
+/// Named network namespaces on the host, with when they were last modified
+pub fn list_netns() -> std::io::Result<Vec<(String, SystemTime)>> {
+    list_modified(Path::new(NETNS_DIR))
+}
+
+/// Addresses leased by the IPAM plugin, one file named after each of them
+pub fn ipam_leases() -> std::io::Result<Vec<(IpAddr, SystemTime)>> {
+    let dir = &util::CNI_CONFIG_FILE.get().unwrap().data_dir;
+    Ok(list_modified(dir)?
+        .into_iter()
+        // `lock` and `last_reserved_ip.N` are the bookkeeping of the plugin
+        .filter_map(|(name, modified)| Some((name.parse().ok()?, modified)))
+        .collect())
+}
+
+/// Drop the lease of `addr`, the plugin hands the address out again
+pub fn release_ipam_lease(addr: IpAddr) -> std::io::Result<()> {
+    let path = util::CNI_CONFIG_FILE
+        .get()
+        .unwrap()
+        .data_dir
+        .join(addr.to_string());
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn list_modified(dir: &Path) -> std::io::Result<Vec<(String, SystemTime)>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            // unknown counts as just created, nothing gets collected on a guess
+            .unwrap_or_else(|_| SystemTime::now());
+        files.push((entry.file_name().to_string_lossy().into_owned(), modified));
+    }
+    Ok(files)
+}
+
+/// Check that the CNI config file was written by `init_cni_network` and is still there
+pub fn check_cni_config() -> Result<(), NetworkError> {
+    let conf = util::CNI_CONFIG_FILE.get().ok_or(NetworkError {
+        msg: "CNI config is not initialised".to_string(),
+    })?;
+    let path = conf.conf_dir.join(&conf.conf_filename);
+    if path.exists() {
+        Ok(())
+    } else {
+        Err(NetworkError {
+            msg: format!("CNI config file {:?} is missing", path),
+        })
     }
-    Err(Box::new(Error))
 }
@@ -1,24 +1,27 @@
 use std::{
-    io::Error,
+    io::{Error, ErrorKind},
     path::Path,
     process::{Command, Output},
-    sync::LazyLock,
 };
 
-static CNI_BIN_DIR: LazyLock<String> =
-    LazyLock::new(|| std::env::var("CNI_BIN_DIR").unwrap_or_else(|_| "/opt/cni/bin".to_string()));
-static CNI_TOOL: LazyLock<String> =
-    LazyLock::new(|| std::env::var("CNI_TOOL").unwrap_or_else(|_| "cni-tool".to_string()));
+use super::util::{CNI_CONFIG_FILE, CniConfFile};
+
+fn cni_tool() -> Result<&'static CniConfFile, Error> {
+    CNI_CONFIG_FILE
+        .get()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "CNI config is not initialised"))
+}
 
 pub(super) fn cni_add_bridge(
     netns_path: &Path,
     bridge_network_name: &str,
 ) -> Result<Output, Error> {
-    Command::new(CNI_TOOL.as_str())
+    let conf = cni_tool()?;
+    Command::new(&conf.tool)
         .arg("add")
         .arg(bridge_network_name)
         .arg(netns_path)
-        .env("CNI_PATH", CNI_BIN_DIR.as_str())
+        .env("CNI_PATH", &conf.bin_dir)
         .output()
 }
 
@@ -26,11 +29,12 @@ pub(super) fn cni_del_bridge(
     netns_path: &Path,
     bridge_network_name: &str,
 ) -> Result<Output, Error> {
-    Command::new(CNI_TOOL.as_str())
+    let conf = cni_tool()?;
+    Command::new(&conf.tool)
         .arg("del")
         .arg(bridge_network_name)
         .arg(netns_path)
-        .env("CNI_PATH", CNI_BIN_DIR.as_str())
+        .env("CNI_PATH", &conf.bin_dir)
         .output()
 }
 
@@ -20,6 +20,14 @@ impl Endpoint {
             namespace: namespace.to_string(),
         }
     }
+
+    /// The container of the `replica`-th instance of this function, `<service>-<replica>`
+    pub fn instance(&self, replica: u32) -> Self {
+        Self {
+            service: format!("{}-{}", self.service, replica),
+            namespace: self.namespace.clone(),
+        }
+    }
 }
 
 /// format `<namespace>-<service>` as netns name, also the identifier of each function
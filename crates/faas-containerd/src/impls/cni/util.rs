@@ -3,6 +3,8 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
+use crate::config::CniConfig;
+
 pub static CNI_CONFIG_FILE: OnceLock<CniConfFile> = OnceLock::new();
 
 // /// Generate "cns-cid"
@@ -12,14 +14,22 @@ pub static CNI_CONFIG_FILE: OnceLock<CniConfFile> = OnceLock::new();
 // }
 
 pub fn init_net_fs(
-    conf_dir: &Path,
+    config: &CniConfig,
     conf_filename: &str,
     net_name: &str,
     bridge: &str,
-    subnet: &str,
     data_dir: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let conf_file = CniConfFile::new(conf_dir, conf_filename, net_name, bridge, subnet, data_dir)?;
+    let mut conf_file = CniConfFile::new(
+        &config.conf_dir,
+        conf_filename,
+        net_name,
+        bridge,
+        &config.subnet,
+        data_dir,
+    )?;
+    conf_file.bin_dir = config.bin_dir.clone();
+    conf_file.tool = config.tool.clone();
     CNI_CONFIG_FILE
         .set(conf_file)
         .map_err(|_| "Failed to set CNI_CONFIG_FILE")?;
@@ -61,6 +71,9 @@ pub(super) struct CniConfFile {
     pub conf_dir: PathBuf,
     pub conf_filename: String,
     pub data_dir: PathBuf,
+    /// `CNI_PATH` of the plugins run by `tool`
+    pub bin_dir: PathBuf,
+    pub tool: String,
 }
 
 impl CniConfFile {
@@ -87,6 +100,8 @@ impl CniConfFile {
             conf_dir: conf_dir.to_path_buf(),
             conf_filename: conf_filename.to_string(),
             data_dir: data_dir.join(net_name),
+            bin_dir: PathBuf::from(crate::consts::DEFAULT_CNI_BIN_DIR),
+            tool: crate::consts::DEFAULT_CNI_TOOL.to_string(),
         })
     }
 }
@@ -23,9 +23,28 @@ impl ContainerdService {
         &self,
         metadata: &ContainerStaticMetadata,
     ) -> Result<Container, ContainerError> {
+        let mut labels = vec![
+            (
+                crate::consts::FUNCTION_LABEL.to_string(),
+                metadata.function.service.clone(),
+            ),
+            (
+                crate::consts::REPLICA_LABEL.to_string(),
+                metadata.replica.to_string(),
+            ),
+            (
+                crate::consts::PORT_LABEL.to_string(),
+                metadata.port.to_string(),
+            ),
+        ];
+        if !metadata.secrets.is_empty() {
+            let names: Vec<&str> = metadata.secrets.keys().map(String::as_str).collect();
+            labels.push((crate::consts::SECRETS_LABEL.to_string(), names.join(",")));
+        }
         let container = Container {
             id: metadata.endpoint.service.clone(),
             image: metadata.image.clone(),
+            labels: labels.into_iter().collect(),
             runtime: Some(Runtime {
                 name: "io.containerd.runc.v2".to_string(),
                 options: None,
@@ -34,7 +53,7 @@ impl ContainerdService {
                 log::error!("Failed to get spec");
                 ContainerError::Internal
             })?),
-            snapshotter: crate::consts::DEFAULT_SNAPSHOTTER.to_string(),
+            snapshotter: self.snapshotter.clone(),
             snapshot_key: metadata.endpoint.service.clone(),
             ..Default::default()
         };
@@ -112,6 +131,32 @@ impl ContainerdService {
         Ok(resp.into_inner().containers)
     }
 
+    /// 获取属于某个函数的全部容器（每个副本一个）
+    pub async fn list_function_containers(
+        &self,
+        function: &Endpoint,
+    ) -> Result<Vec<Container>, ContainerError> {
+        let mut cc = self.client.containers();
+
+        let request = ListContainersRequest {
+            filters: vec![format!(
+                "labels.\"{}\"=={}",
+                crate::consts::FUNCTION_LABEL,
+                function.service
+            )],
+        };
+
+        let resp = cc
+            .list(with_namespace!(request, function.namespace))
+            .await
+            .map_err(|e| {
+                log::error!("Failed to list containers: {}", e);
+                ContainerError::Internal
+            })?;
+
+        Ok(resp.into_inner().containers)
+    }
+
     /// 不儿，这也要单独一个函数？
     #[deprecated]
     pub async fn list_container_into_string(
@@ -123,3 +168,84 @@ impl ContainerdService {
             .map(|ctrs| ctrs.into_iter().map(|ctr| ctr.id).collect())
     }
 }
+
+/// Names of the secrets mounted into the container
+pub fn container_secrets(container: &Container) -> Vec<String> {
+    container
+        .labels
+        .get(crate::consts::SECRETS_LABEL)
+        .map(|names| {
+            names
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Port recorded in the container labels, containers created before it was
+/// recorded served on the default port
+pub fn container_port(container: &Container) -> u16 {
+    container
+        .labels
+        .get(crate::consts::PORT_LABEL)
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(crate::consts::DEFAULT_FUNCTION_PORT)
+}
+
+/// The function and replica index recorded in the container labels,
+/// `None` for containers not created by faasd-rs
+pub fn function_replica(container: &Container) -> Option<(String, u32)> {
+    let function = container.labels.get(crate::consts::FUNCTION_LABEL)?;
+    let replica = container
+        .labels
+        .get(crate::consts::REPLICA_LABEL)?
+        .parse()
+        .ok()?;
+    Some((function.clone(), replica))
+}
+
+/// Whether the container was deployed before replicas existed: one container
+/// per function named after it, on its own snapshot and without labels
+pub fn is_legacy_function(container: &Container) -> bool {
+    !container.labels.contains_key(crate::consts::FUNCTION_LABEL)
+        && !container.labels.contains_key(crate::consts::REPLICA_LABEL)
+        && container.snapshot_key == container.id
+}
+
+#[cfg(test)]
+mod tests {
+    use containerd_client::services::v1::Container;
+
+    use crate::consts;
+
+    #[test]
+    fn test_legacy_function() {
+        // as the releases before replicas created them
+        let legacy = Container {
+            id: "echo".to_string(),
+            snapshot_key: "echo".to_string(),
+            image: "docker.io/library/echo:latest".to_string(),
+            ..Default::default()
+        };
+        assert!(super::is_legacy_function(&legacy));
+        assert_eq!(super::function_replica(&legacy), None);
+
+        let replica = Container {
+            id: "echo-0".to_string(),
+            snapshot_key: "echo-0".to_string(),
+            labels: [
+                (consts::FUNCTION_LABEL.to_string(), "echo".to_string()),
+                (consts::REPLICA_LABEL.to_string(), "0".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        assert!(!super::is_legacy_function(&replica));
+        assert_eq!(
+            super::function_replica(&replica),
+            Some(("echo".to_string(), 0))
+        );
+    }
+}
@@ -12,6 +12,8 @@ pub enum ContainerdError {
     WaitTaskError(String),
     CreateTaskError(String),
     StartTaskError(String),
+    GetVersionError(String),
+    ListSnapshotsError(String),
     #[allow(dead_code)]
     OtherError,
 }
@@ -1,37 +1,43 @@
-use gateway::types::function;
-
-use crate::consts;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use super::cni::Endpoint;
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct ContainerStaticMetadata {
     pub image: String,
+    /// The container of this replica, see [`Endpoint::instance`]
     pub endpoint: Endpoint,
+    /// The function this container is an instance of
+    pub function: Endpoint,
+    pub replica: u32,
+    /// Host files of the secrets mounted into the container, keyed by secret name
+    pub secrets: BTreeMap<String, PathBuf>,
+    /// Port the function listens on inside the container
+    pub port: u16,
 }
 
-impl From<function::Deployment> for ContainerStaticMetadata {
-    fn from(info: function::Deployment) -> Self {
+impl ContainerStaticMetadata {
+    pub fn new(image: &str, function: &Endpoint, replica: u32) -> Self {
         ContainerStaticMetadata {
-            image: info.image,
-            endpoint: Endpoint::new(
-                &info.service,
-                &info
-                    .namespace
-                    .unwrap_or(consts::DEFAULT_FUNCTION_NAMESPACE.to_string()),
-            ),
+            image: image.to_string(),
+            endpoint: function.instance(replica),
+            function: function.clone(),
+            replica,
+            secrets: BTreeMap::new(),
+            port: crate::consts::DEFAULT_FUNCTION_PORT,
         }
     }
-}
 
-// impl From<ContainerStaticMetadata> for function::Query {
-//     fn from(metadata: ContainerStaticMetadata) -> Self {
-//         function::Query {
-//             service: metadata.container_id,
-//             namespace: Some(metadata.namespace),
-//         }
-//     }
-// }
+    pub fn with_secrets(mut self, secrets: BTreeMap<String, PathBuf>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+}
 
 // /// A function is a container instance with correct cni connected
 // #[derive(Debug)]
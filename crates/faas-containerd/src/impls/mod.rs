@@ -2,13 +2,17 @@ pub mod cni;
 pub mod container;
 pub mod error;
 pub mod function;
+pub mod namespace;
 pub mod oci_image;
 pub mod snapshot;
 pub mod spec;
 pub mod task;
+pub mod version;
 
 use std::sync::OnceLock;
 
+use crate::config::Config;
+
 pub static __BACKEND: OnceLock<ContainerdService> = OnceLock::new();
 
 pub(crate) fn backend() -> &'static ContainerdService {
@@ -16,15 +20,23 @@ pub(crate) fn backend() -> &'static ContainerdService {
 }
 
 /// TODO: Panic on failure, should be handled in a better way
-pub async fn init_backend() {
-    let socket =
-        std::env::var("SOCKET_PATH").unwrap_or(crate::consts::DEFAULT_CTRD_SOCK.to_string());
-    let client = containerd_client::Client::from_path(socket).await.unwrap();
+pub async fn init_backend(config: &Config) {
+    let config = &config.containerd;
+    let client = containerd_client::Client::from_path(&config.socket_path)
+        .await
+        .unwrap();
 
-    __BACKEND.set(ContainerdService { client }).ok().unwrap();
-    cni::init_cni_network().unwrap();
+    __BACKEND
+        .set(ContainerdService {
+            client,
+            snapshotter: config.snapshotter.clone(),
+        })
+        .ok()
+        .unwrap();
+    cni::init_cni_network(&config.cni).unwrap();
 }
 
 pub struct ContainerdService {
     pub client: containerd_client::Client,
+    pub snapshotter: String,
 }
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use containerd_client::{
+    services::v1::{
+        CreateNamespaceRequest, DeleteImageRequest, DeleteNamespaceRequest, GetNamespaceRequest,
+        ListImagesRequest, ListNamespacesRequest, Namespace, UpdateNamespaceRequest,
+    },
+    with_namespace,
+};
+use derive_more::Display;
+use tonic::Request;
+
+use super::ContainerdService;
+
+#[derive(Debug, Display)]
+pub enum NamespaceError {
+    NotFound,
+    AlreadyExists,
+    InvalidArgument(String),
+    /// containerd refuses to delete namespaces which still own resources
+    FailedPrecondition(String),
+    Internal(String),
+}
+
+impl From<tonic::Status> for NamespaceError {
+    fn from(status: tonic::Status) -> Self {
+        use tonic::Code::*;
+        match status.code() {
+            NotFound => NamespaceError::NotFound,
+            AlreadyExists => NamespaceError::AlreadyExists,
+            InvalidArgument => NamespaceError::InvalidArgument(status.message().to_string()),
+            FailedPrecondition => NamespaceError::FailedPrecondition(status.message().to_string()),
+            _ => NamespaceError::Internal(status.message().to_string()),
+        }
+    }
+}
+
+impl ContainerdService {
+    pub async fn list_namespaces(&self) -> Result<Vec<Namespace>, NamespaceError> {
+        let mut c = self.client.namespaces();
+        let resp = c.list(ListNamespacesRequest::default()).await?;
+        Ok(resp.into_inner().namespaces)
+    }
+
+    /// `None` if containerd does not know the namespace
+    pub async fn get_namespace(&self, name: &str) -> Result<Option<Namespace>, NamespaceError> {
+        let mut c = self.client.namespaces();
+        let req = GetNamespaceRequest {
+            name: name.to_string(),
+        };
+        match c.get(req).await {
+            Ok(resp) => Ok(resp.into_inner().namespace),
+            Err(status) => match NamespaceError::from(status) {
+                NamespaceError::NotFound => Ok(None),
+                e => Err(e),
+            },
+        }
+    }
+
+    pub async fn create_namespace(
+        &self,
+        name: &str,
+        labels: HashMap<String, String>,
+    ) -> Result<(), NamespaceError> {
+        let mut c = self.client.namespaces();
+        let req = CreateNamespaceRequest {
+            namespace: Some(Namespace {
+                name: name.to_string(),
+                labels,
+            }),
+        };
+        c.create(req).await?;
+        Ok(())
+    }
+
+    /// 替换全部标签
+    pub async fn update_namespace(
+        &self,
+        name: &str,
+        labels: HashMap<String, String>,
+    ) -> Result<(), NamespaceError> {
+        let mut c = self.client.namespaces();
+        let req = UpdateNamespaceRequest {
+            namespace: Some(Namespace {
+                name: name.to_string(),
+                labels,
+            }),
+            update_mask: None,
+        };
+        c.update(req).await?;
+        Ok(())
+    }
+
+    /// 命名空间中的镜像、容器、快照都删除后才能成功
+    pub async fn delete_namespace(&self, name: &str) -> Result<(), NamespaceError> {
+        let mut c = self.client.namespaces();
+        let req = DeleteNamespaceRequest {
+            name: name.to_string(),
+        };
+        c.delete(req).await?;
+        Ok(())
+    }
+
+    /// 删除命名空间中的全部镜像，并等待 containerd 回收内容和快照
+    pub async fn remove_images(&self, ns: &str) -> Result<(), NamespaceError> {
+        let mut c = self.client.images();
+        let images = c
+            .list(with_namespace!(ListImagesRequest::default(), ns))
+            .await?
+            .into_inner()
+            .images;
+        for image in images {
+            let req = DeleteImageRequest {
+                name: image.name,
+                sync: true,
+                target: None,
+            };
+            c.delete(with_namespace!(req, ns)).await?;
+        }
+        Ok(())
+    }
+}
@@ -104,6 +104,25 @@ impl ContainerdService {
         }
     }
 
+    /// Digest of the manifest the image name points to, e.g. `sha256:...`
+    pub async fn image_digest(&self, img_name: &str, ns: &str) -> Result<String, ImageError> {
+        let mut img_cli = self.client.images();
+        let req = GetImageRequest {
+            name: img_name.to_string(),
+        };
+        let resp = img_cli
+            .get(with_namespace!(req, ns))
+            .await
+            .map_err(|e| {
+                ImageError::ImageNotFound(format!("Failed to get image {}: {}", img_name, e))
+            })?
+            .into_inner();
+        resp.image
+            .and_then(|image| image.target)
+            .map(|target| target.digest)
+            .ok_or_else(|| ImageError::ImageNotFound(format!("Image {} has no target", img_name)))
+    }
+
     pub async fn image_config(
         &self,
         img_name: &str,
@@ -1,5 +1,7 @@
 use containerd_client::{
-    services::v1::snapshots::{MountsRequest, PrepareSnapshotRequest, RemoveSnapshotRequest},
+    services::v1::snapshots::{
+        Info, ListSnapshotsRequest, MountsRequest, PrepareSnapshotRequest, RemoveSnapshotRequest,
+    },
     types::Mount,
     with_namespace,
 };
@@ -10,15 +12,10 @@ use crate::impls::error::ContainerdError;
 use super::{ContainerdService, cni::Endpoint, function::ContainerStaticMetadata};
 
 impl ContainerdService {
-    #[allow(unused)]
-    pub(super) async fn get_mounts(
-        &self,
-        cid: &str,
-        ns: &str,
-    ) -> Result<Vec<Mount>, ContainerdError> {
+    pub async fn get_mounts(&self, cid: &str, ns: &str) -> Result<Vec<Mount>, ContainerdError> {
         let mut sc = self.client.snapshots();
         let req = MountsRequest {
-            snapshotter: crate::consts::DEFAULT_SNAPSHOTTER.to_string(),
+            snapshotter: self.snapshotter.clone(),
             key: cid.to_string(),
         };
         let mounts = sc
@@ -56,7 +53,7 @@ impl ContainerdService {
         parent_snapshot: String,
     ) -> Result<Vec<Mount>, ContainerdError> {
         let req = PrepareSnapshotRequest {
-            snapshotter: crate::consts::DEFAULT_SNAPSHOTTER.to_string(),
+            snapshotter: self.snapshotter.clone(),
             key: cid.to_string(),
             parent: parent_snapshot,
             ..Default::default()
@@ -114,18 +111,48 @@ impl ContainerdService {
     }
 
     pub async fn remove_snapshot(&self, endpoint: &Endpoint) -> Result<(), ContainerdError> {
+        self.remove_snapshot_key(&endpoint.service, &endpoint.namespace)
+            .await
+    }
+
+    /// 删除任意 key 的快照，容器的快照 key 即容器 id
+    pub async fn remove_snapshot_key(&self, key: &str, ns: &str) -> Result<(), ContainerdError> {
         let mut sc = self.client.snapshots();
         let req = RemoveSnapshotRequest {
-            snapshotter: crate::consts::DEFAULT_SNAPSHOTTER.to_string(),
-            key: endpoint.service.clone(),
+            snapshotter: self.snapshotter.clone(),
+            key: key.to_string(),
         };
-        sc.remove(with_namespace!(req, endpoint.namespace))
-            .await
-            .map_err(|e| {
-                log::error!("Failed to delete snapshot: {}", e);
-                ContainerdError::DeleteContainerError(e.to_string())
-            })?;
+        sc.remove(with_namespace!(req, ns)).await.map_err(|e| {
+            log::error!("Failed to delete snapshot: {}", e);
+            ContainerdError::DeleteContainerError(e.to_string())
+        })?;
 
         Ok(())
     }
+
+    /// 列出命名空间下快照器中的全部快照，镜像层与容器的可写层都在内
+    pub async fn list_snapshots(&self, ns: &str) -> Result<Vec<Info>, ContainerdError> {
+        let mut sc = self.client.snapshots();
+        let req = ListSnapshotsRequest {
+            snapshotter: self.snapshotter.clone(),
+            ..Default::default()
+        };
+        let mut stream = sc
+            .list(with_namespace!(req, ns))
+            .await
+            .map_err(|e| {
+                log::error!("Failed to list snapshots: {}", e);
+                ContainerdError::ListSnapshotsError(e.to_string())
+            })?
+            .into_inner();
+        let mut snapshots = Vec::new();
+        while let Some(resp) = stream
+            .message()
+            .await
+            .map_err(|e| ContainerdError::ListSnapshotsError(e.to_string()))?
+        {
+            snapshots.extend(resp.info);
+        }
+        Ok(snapshots)
+    }
 }
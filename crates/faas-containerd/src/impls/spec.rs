@@ -1,7 +1,6 @@
 use super::{
     ContainerdService, cni::Endpoint, error::ContainerdError, function::ContainerStaticMetadata,
 };
-use crate::consts::{VERSION_DEV, VERSION_MAJOR, VERSION_MINOR, VERSION_PATCH};
 use oci_spec::{
     image::ImageConfiguration,
     runtime::{
@@ -11,19 +10,16 @@ use oci_spec::{
         UserBuilder,
     },
 };
-use std::path::Path;
-
-fn oci_version() -> String {
-    format!(
-        "{}.{}.{}{}",
-        VERSION_MAJOR, VERSION_MINOR, VERSION_PATCH, VERSION_DEV
-    )
-}
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 pub(super) fn generate_default_unix_spec(
     ns: &str,
     cid: &str,
     runtime_config: &RuntimeConfig,
+    secrets: &BTreeMap<String, PathBuf>,
 ) -> Result<oci_spec::runtime::Spec, ContainerdError> {
     let caps = [
         Capability::Chown,
@@ -41,8 +37,23 @@ pub(super) fn generate_default_unix_spec(
         Capability::Kill,
         Capability::AuditWrite,
     ];
+    let secret_mounts = secrets
+        .iter()
+        .map(|(name, source)| {
+            MountBuilder::default()
+                .destination(Path::new(crate::consts::SECRET_MOUNT_DIR).join(name))
+                .typ("bind")
+                .source(source)
+                .options(["rbind".into(), "ro".into()])
+                .build()
+                .map_err(|e| {
+                    log::error!("Failed to build OCI (secret {}) Mount: {}", name, e);
+                    ContainerdError::GenerateSpecError(e.to_string())
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
     let spec = SpecBuilder::default()
-        .version(oci_version())
+        .version(crate::consts::version())
         .root(
             RootBuilder::default()
                 .path("rootfs")
@@ -133,85 +144,90 @@ pub(super) fn generate_default_unix_spec(
                 .build()
                 .unwrap(),
         )
-        .mounts([
-            MountBuilder::default()
-                .destination("/proc")
-                .typ("proc")
-                .source("proc")
-                .options(["nosuid".into(), "noexec".into(), "nodev".into()])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/dev")
-                .typ("tmpfs")
-                .source("tmpfs")
-                .options([
-                    "nosuid".into(),
-                    "strictatime".into(),
-                    "mode=755".into(),
-                    "size=65536k".into(),
-                ])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/dev/pts")
-                .typ("devpts")
-                .source("devpts")
-                .options([
-                    "nosuid".into(),
-                    "noexec".into(),
-                    "newinstance".into(),
-                    "ptmxmode=0666".into(),
-                    "mode=0620".into(),
-                    "gid=5".into(),
-                ])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/dev/shm")
-                .typ("tmpfs")
-                .source("shm")
-                .options([
-                    "nosuid".into(),
-                    "noexec".into(),
-                    "nodev".into(),
-                    "mode=1777".into(),
-                    "size=65536k".into(),
-                ])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/dev/mqueue")
-                .typ("mqueue")
-                .source("mqueue")
-                .options(["nosuid".into(), "noexec".into(), "nodev".into()])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/sys")
-                .typ("sysfs")
-                .source("sysfs")
-                .options([
-                    "nosuid".into(),
-                    "noexec".into(),
-                    "nodev".into(),
-                    "ro".into(),
-                ])
-                .build()
-                .unwrap(),
-            MountBuilder::default()
-                .destination("/run")
-                .typ("tmpfs")
-                .source("tmpfs")
-                .options([
-                    "nosuid".into(),
-                    "strictatime".into(),
-                    "mode=755".into(),
-                    "size=65536k".into(),
-                ])
-                .build()
-                .unwrap(),
-        ])
+        .mounts(
+            [
+                MountBuilder::default()
+                    .destination("/proc")
+                    .typ("proc")
+                    .source("proc")
+                    .options(["nosuid".into(), "noexec".into(), "nodev".into()])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/dev")
+                    .typ("tmpfs")
+                    .source("tmpfs")
+                    .options([
+                        "nosuid".into(),
+                        "strictatime".into(),
+                        "mode=755".into(),
+                        "size=65536k".into(),
+                    ])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/dev/pts")
+                    .typ("devpts")
+                    .source("devpts")
+                    .options([
+                        "nosuid".into(),
+                        "noexec".into(),
+                        "newinstance".into(),
+                        "ptmxmode=0666".into(),
+                        "mode=0620".into(),
+                        "gid=5".into(),
+                    ])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/dev/shm")
+                    .typ("tmpfs")
+                    .source("shm")
+                    .options([
+                        "nosuid".into(),
+                        "noexec".into(),
+                        "nodev".into(),
+                        "mode=1777".into(),
+                        "size=65536k".into(),
+                    ])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/dev/mqueue")
+                    .typ("mqueue")
+                    .source("mqueue")
+                    .options(["nosuid".into(), "noexec".into(), "nodev".into()])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/sys")
+                    .typ("sysfs")
+                    .source("sysfs")
+                    .options([
+                        "nosuid".into(),
+                        "noexec".into(),
+                        "nodev".into(),
+                        "ro".into(),
+                    ])
+                    .build()
+                    .unwrap(),
+                MountBuilder::default()
+                    .destination("/run")
+                    .typ("tmpfs")
+                    .source("tmpfs")
+                    .options([
+                        "nosuid".into(),
+                        "strictatime".into(),
+                        "mode=755".into(),
+                        "size=65536k".into(),
+                    ])
+                    .build()
+                    .unwrap(),
+            ]
+            .into_iter()
+            .chain(secret_mounts)
+            .collect::<Vec<_>>(),
+        )
         .build()
         .map_err(|e| {
             log::error!("Failed to generate spec: {}", e);
@@ -263,6 +279,20 @@ pub struct RuntimeConfig {
     pub cwd: String,
 }
 
+impl RuntimeConfig {
+    /// First TCP port exposed by the image, `80/tcp` or `80` alike
+    pub fn serving_port(&self) -> Option<u16> {
+        self.ports.iter().find_map(|port| {
+            let (port, protocol) = port.split_once('/').unwrap_or((port, "tcp"));
+            if protocol.eq_ignore_ascii_case("tcp") {
+                port.parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
 impl TryFrom<ImageConfiguration> for RuntimeConfig {
     type Error = ContainerdError;
 
@@ -278,10 +308,7 @@ impl TryFrom<ImageConfiguration> for RuntimeConfig {
         let args = config.cmd().clone().ok_or_else(|| {
             ContainerdError::GenerateSpecError("Command arguments not found".to_string())
         })?;
-        let ports = config.exposed_ports().clone().unwrap_or_else(|| {
-            log::warn!("Exposed ports not found, using default port 8080/tcp");
-            vec!["8080/tcp".to_string()]
-        });
+        let ports = config.exposed_ports().clone().unwrap_or_default();
         let cwd = config.working_dir().clone().unwrap_or_else(|| {
             log::warn!("Working directory not found, using default /");
             "/".to_string()
@@ -296,6 +323,19 @@ impl TryFrom<ImageConfiguration> for RuntimeConfig {
 }
 
 impl ContainerdService {
+    /// Port the image of the function declares it listens on, if any
+    pub async fn exposed_port(
+        &self,
+        image: &str,
+        ns: &str,
+    ) -> Result<Option<u16>, ContainerdError> {
+        let image_conf = self.image_config(image, ns).await.map_err(|e| {
+            log::error!("Failed to get image config: {}", e);
+            ContainerdError::GenerateSpecError(e.to_string())
+        })?;
+        Ok(RuntimeConfig::try_from(image_conf)?.serving_port())
+    }
+
     pub async fn get_spec(
         &self,
         metadata: &ContainerStaticMetadata,
@@ -314,6 +354,7 @@ impl ContainerdService {
             &metadata.endpoint.namespace,
             &metadata.endpoint.service,
             &rt_conf,
+            &metadata.secrets,
         )?;
         let spec_json = serde_json::to_string(&spec).map_err(|e| {
             log::error!("Failed to serialize spec to JSON: {}", e);
@@ -327,3 +368,32 @@ impl ContainerdService {
         Ok(any_spec)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeConfig;
+
+    fn runtime_config(ports: &[&str]) -> RuntimeConfig {
+        RuntimeConfig {
+            env: Vec::new(),
+            args: Vec::new(),
+            ports: ports.iter().map(|port| port.to_string()).collect(),
+            cwd: "/".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_serving_port() {
+        assert_eq!(
+            runtime_config(&["80/tcp", "443/tcp"]).serving_port(),
+            Some(80)
+        );
+        assert_eq!(
+            runtime_config(&["53/udp", "3000/tcp"]).serving_port(),
+            Some(3000)
+        );
+        assert_eq!(runtime_config(&["5000"]).serving_port(), Some(5000));
+        assert_eq!(runtime_config(&["53/udp"]).serving_port(), None);
+        assert_eq!(runtime_config(&[]).serving_port(), None);
+    }
+}
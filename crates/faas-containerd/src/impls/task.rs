@@ -1,11 +1,15 @@
-use std::time::Duration;
+use std::{path::Path, time::Duration};
 
 use containerd_client::{
     services::v1::{
         CreateTaskRequest, DeleteTaskRequest, GetRequest, KillRequest, ListTasksRequest,
-        ListTasksResponse, StartRequest, WaitRequest, WaitResponse,
+        ListTasksResponse, PauseTaskRequest, ResumeTaskRequest, StartRequest, WaitRequest,
+        WaitResponse,
+    },
+    types::{
+        Mount,
+        v1::{Process, Status},
     },
-    types::{Mount, v1::Process},
     with_namespace,
 };
 use derive_more::Display;
@@ -36,6 +40,15 @@ impl From<tonic::Status> for TaskError {
     }
 }
 
+/// Whether the task is serving, only running tasks count as available replicas
+pub fn is_running(task: &Process) -> bool {
+    task.status == Status::Running as i32
+}
+
+pub fn is_paused(task: &Process) -> bool {
+    task.status == Status::Paused as i32 || task.status == Status::Pausing as i32
+}
+
 impl From<TaskError> for DeployError {
     fn from(e: TaskError) -> DeployError {
         match e {
@@ -57,18 +70,39 @@ impl From<TaskError> for DeleteError {
 }
 
 impl ContainerdService {
-    /// 创建并启动任务
-    pub async fn new_task(&self, mounts: Vec<Mount>, endpoint: &Endpoint) -> Result<(), TaskError> {
+    /// 创建并启动任务，stdout 和 stderr 追加写入 `log_file`
+    pub async fn new_task(
+        &self,
+        mounts: Vec<Mount>,
+        endpoint: &Endpoint,
+        log_file: Option<&Path>,
+    ) -> Result<(), TaskError> {
         let Endpoint {
             service: cid,
             namespace: ns,
         } = endpoint;
         // let mounts = self.get_mounts(cid, ns).await?;
-        self.do_create_task(cid, ns, mounts).await?;
+        self.do_create_task(cid, ns, mounts, log_file).await?;
         self.do_start_task(cid, ns).await?;
         Ok(())
     }
 
+    /// 只创建任务不启动，之后由 [`Self::start_task`] 启动
+    pub async fn create_task(
+        &self,
+        mounts: Vec<Mount>,
+        endpoint: &Endpoint,
+        log_file: Option<&Path>,
+    ) -> Result<(), TaskError> {
+        self.do_create_task(&endpoint.service, &endpoint.namespace, mounts, log_file)
+            .await
+    }
+
+    pub async fn start_task(&self, endpoint: &Endpoint) -> Result<(), TaskError> {
+        self.do_start_task(&endpoint.service, &endpoint.namespace)
+            .await
+    }
+
     async fn do_start_task(&self, cid: &str, ns: &str) -> Result<(), TaskError> {
         let mut c: containerd_client::services::v1::tasks_client::TasksClient<
             tonic::transport::Channel,
@@ -89,11 +123,18 @@ impl ContainerdService {
         cid: &str,
         ns: &str,
         rootfs: Vec<Mount>,
+        log_file: Option<&Path>,
     ) -> Result<(), TaskError> {
         let mut tc = self.client.tasks();
+        // the shim opens `file://` stdio in append mode, no copier needed on our side
+        let stdio = log_file
+            .map(|path| format!("file://{}", path.display()))
+            .unwrap_or_default();
         let create_request = CreateTaskRequest {
             container_id: cid.to_string(),
             rootfs,
+            stdout: stdio.clone(),
+            stderr: stdio,
             ..Default::default()
         };
         let _resp = tc.create(with_namespace!(create_request, ns)).await?;
@@ -176,6 +217,34 @@ impl ContainerdService {
         Ok(resp)
     }
 
+    /// 冻结任务的所有进程，内存和网络保持不变
+    pub async fn pause_task(&self, endpoint: &Endpoint) -> Result<(), TaskError> {
+        let mut c = self.client.tasks();
+        let request = PauseTaskRequest {
+            container_id: endpoint.service.clone(),
+        };
+        c.pause(with_namespace!(request, &endpoint.namespace))
+            .await?;
+        Ok(())
+    }
+
+    /// 恢复被冻结的任务
+    pub async fn resume_task(&self, endpoint: &Endpoint) -> Result<(), TaskError> {
+        let mut c = self.client.tasks();
+        let request = ResumeTaskRequest {
+            container_id: endpoint.service.clone(),
+        };
+        c.resume(with_namespace!(request, &endpoint.namespace))
+            .await?;
+        Ok(())
+    }
+
+    /// 删除已经退出的任务
+    pub async fn delete_task(&self, endpoint: &Endpoint) -> Result<(), TaskError> {
+        self.do_delete_task(&endpoint.service, &endpoint.namespace)
+            .await
+    }
+
     /// 杀死并删除任务
     pub async fn kill_task_with_timeout(&self, endpoint: &Endpoint) -> Result<(), TaskError> {
         let Endpoint {
@@ -183,6 +252,12 @@ impl ContainerdService {
             namespace: ns,
         } = endpoint;
         let kill_timeout = Duration::from_secs(5);
+        // 暂停的任务收不到信号，先恢复
+        if let Ok(task) = self.get_task(endpoint).await
+            && is_paused(&task)
+        {
+            self.resume_task(endpoint).await?;
+        }
         let wait_future = self.do_wait_task(cid, ns);
         self.do_kill_task(cid, ns).await?;
         match tokio::time::timeout(kill_timeout, wait_future).await {
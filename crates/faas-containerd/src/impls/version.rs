@@ -0,0 +1,15 @@
+use containerd_client::services::v1::VersionResponse;
+
+use super::{ContainerdService, error::ContainerdError};
+
+impl ContainerdService {
+    /// 获取 containerd 服务端版本，同时可用于检查 gRPC 连接是否正常
+    pub async fn version(&self) -> Result<VersionResponse, ContainerdError> {
+        let mut vc = self.client.version();
+        let resp = vc.version(()).await.map_err(|e| {
+            log::error!("Failed to get containerd version: {}", e);
+            ContainerdError::GetVersionError(e.to_string())
+        })?;
+        Ok(resp.into_inner())
+    }
+}
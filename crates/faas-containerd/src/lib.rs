@@ -1,5 +1,5 @@
 #![feature(ip_from)]
-#![feature(slice_as_array)]
+pub mod config;
 pub mod consts;
 pub mod impls;
 pub mod provider;
@@ -1,12 +1,34 @@
-use faas_containerd::consts::DEFAULT_FAASDRS_DATA_DIR;
+use faas_containerd::config::Config;
 use tokio::signal::unix::{SignalKind, signal};
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    faas_containerd::init_backend().await;
-    let provider = faas_containerd::provider::ContainerdProvider::new(DEFAULT_FAASDRS_DATA_DIR);
+    let config = Config::load().unwrap_or_else(|e| {
+        log::error!("Failed to load configuration: {}", e);
+        std::process::exit(1);
+    });
+    log::debug!("Configuration: {:?}", config);
+    faas_containerd::init_backend(&config).await;
+    let provider = faas_containerd::provider::ContainerdProvider::with_config(
+        &config.containerd.data_dir,
+        config.containerd.scale_to_zero.clone(),
+        config.containerd.gc.clone(),
+        config.containerd.logs.clone(),
+    )
+    .unwrap_or_else(|e| {
+        log::error!("Failed to open database: {}", e);
+        std::process::exit(1);
+    });
+    // functions deployed before replicas existed become replica 0 of themselves
+    provider.adopt_legacy_functions().await;
+    // bring the functions back before serving them, e.g. after a reboot
+    provider.reconcile().await;
+    provider.spawn_idle_controller();
+    provider.spawn_warm_pool_controller();
+    provider.spawn_gc_controller();
+    provider.spawn_log_collector();
 
     // leave for shutdown containers (stop tasks)
     let _handle = provider.clone();
@@ -27,7 +49,7 @@ async fn main() -> std::io::Result<()> {
         log::info!("Successfully shutdown all containers");
     });
 
-    gateway::bootstrap::serve(provider)
+    gateway::bootstrap::serve(provider, config.gateway)
         .unwrap_or_else(|e| {
             log::error!("Failed to start server: {}", e);
             std::process::exit(1);
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use derive_more::Display;
+
+use crate::{impls::cni::Endpoint, provider::ContainerdProvider};
+
+pub(crate) const BALANCER_TREE: &str = "balancers";
+
+/// How `resolve` picks one of the healthy instances of a function,
+/// set on deploy through the `com.faasrs.loadbalancer` label
+#[derive(Debug, Display, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    #[default]
+    #[display("round-robin")]
+    RoundRobin,
+    #[display("least-outstanding")]
+    LeastOutstanding,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Strategy::RoundRobin),
+            "least-outstanding" => Ok(Strategy::LeastOutstanding),
+            _ => Err(format!(
+                "unknown load balancing strategy {}, expected round-robin or least-outstanding",
+                s
+            )),
+        }
+    }
+}
+
+/// Selection state of one function
+#[derive(Debug, Default)]
+pub struct Balancer {
+    next: AtomicUsize,
+    outstanding: Mutex<HashMap<SocketAddr, Arc<AtomicUsize>>>,
+}
+
+impl Balancer {
+    /// Pick one of `candidates`, returning it with its outstanding request counter.
+    /// Ties between least loaded instances are broken in round-robin order.
+    pub fn pick(
+        &self,
+        strategy: Strategy,
+        candidates: &[SocketAddr],
+    ) -> Option<(SocketAddr, Arc<AtomicUsize>)> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        let mut outstanding = self.outstanding.lock().unwrap();
+        // forget the instances which are gone
+        outstanding.retain(|addr, _| candidates.contains(addr));
+
+        let order = (0..candidates.len()).map(|i| candidates[(start + i) % candidates.len()]);
+        let addr = match strategy {
+            Strategy::RoundRobin => candidates[start],
+            Strategy::LeastOutstanding => order
+                .min_by_key(|addr| {
+                    outstanding
+                        .get(addr)
+                        .map(|c| c.load(Ordering::Acquire))
+                        .unwrap_or(0)
+                })
+                .unwrap(),
+        };
+        let counter = outstanding.entry(addr).or_default().clone();
+        Some((addr, counter))
+    }
+
+    /// Invocations in flight over all the instances
+    pub fn outstanding(&self) -> usize {
+        self.outstanding
+            .lock()
+            .unwrap()
+            .values()
+            .map(|counter| counter.load(Ordering::Acquire))
+            .sum()
+    }
+}
+
+impl ContainerdProvider {
+    /// Strategy recorded for the function on deploy, round-robin if none
+    pub(crate) fn strategy(&self, function: &Endpoint) -> Strategy {
+        let stored = self
+            .database
+            .open_tree(BALANCER_TREE)
+            .and_then(|tree| tree.get(function.to_string()));
+        match stored {
+            Ok(Some(value)) => String::from_utf8_lossy(&value).parse().unwrap_or_else(|e| {
+                log::warn!("Invalid strategy recorded for {}: {}", function, e);
+                Strategy::default()
+            }),
+            Ok(None) => Strategy::default(),
+            Err(e) => {
+                log::error!("Failed to load strategy of {}: {:?}", function, e);
+                Strategy::default()
+            }
+        }
+    }
+
+    pub(crate) fn save_strategy(
+        &self,
+        function: &Endpoint,
+        strategy: Strategy,
+    ) -> Result<(), sled::Error> {
+        self.database
+            .open_tree(BALANCER_TREE)?
+            .insert(function.to_string(), strategy.to_string().as_bytes())?;
+        // restart the selection state, the strategy may have changed
+        self.balancers.lock().unwrap().remove(&function.to_string());
+        Ok(())
+    }
+
+    pub(crate) fn forget_strategy(&self, function: &Endpoint) {
+        if let Err(e) = self
+            .database
+            .open_tree(BALANCER_TREE)
+            .and_then(|tree| tree.remove(function.to_string()))
+        {
+            log::error!("Failed to remove strategy of {}: {:?}", function, e);
+        }
+        self.balancers.lock().unwrap().remove(&function.to_string());
+    }
+
+    pub(crate) fn balancer(&self, function: &Endpoint) -> Arc<Balancer> {
+        self.balancers
+            .lock()
+            .unwrap()
+            .entry(function.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn addrs(n: u8) -> Vec<SocketAddr> {
+        (1..=n)
+            .map(|i| SocketAddr::from((Ipv4Addr::new(10, 62, 0, i), 8080)))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let balancer = Balancer::default();
+        let candidates = addrs(3);
+        let picked: Vec<_> = (0..6)
+            .map(|_| balancer.pick(Strategy::RoundRobin, &candidates).unwrap().0)
+            .collect();
+        assert_eq!(picked[..3], candidates[..]);
+        assert_eq!(picked[3..], candidates[..]);
+        assert!(balancer.pick(Strategy::RoundRobin, &[]).is_none());
+    }
+
+    #[test]
+    fn test_least_outstanding() {
+        let balancer = Balancer::default();
+        let candidates = addrs(2);
+        let (first, busy) = balancer
+            .pick(Strategy::LeastOutstanding, &candidates)
+            .unwrap();
+        busy.fetch_add(5, Ordering::AcqRel);
+        for _ in 0..4 {
+            let (addr, _) = balancer
+                .pick(Strategy::LeastOutstanding, &candidates)
+                .unwrap();
+            assert_ne!(addr, first);
+        }
+        busy.fetch_sub(5, Ordering::AcqRel);
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(
+            "least-outstanding".parse::<Strategy>(),
+            Ok(Strategy::LeastOutstanding)
+        );
+        assert_eq!(Strategy::default().to_string(), "round-robin");
+        assert!("random".parse::<Strategy>().is_err());
+    }
+}
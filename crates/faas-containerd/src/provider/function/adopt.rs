@@ -0,0 +1,100 @@
+//! Functions deployed before replicas existed run in one container named after
+//! the function, without labels, on the `<namespace>-<service>` network. They
+//! are redeployed once as replica 0 from the same image, then the old
+//! container, snapshot and network are removed.
+
+use containerd_client::services::v1::Container;
+
+use crate::{
+    impls::{
+        backend,
+        cni::{self, Endpoint},
+        container::is_legacy_function,
+        function::ContainerStaticMetadata,
+        task::TaskError,
+    },
+    provider::ContainerdProvider,
+};
+
+impl ContainerdProvider {
+    /// Turn every legacy function into replica 0 of itself, returns how many were
+    pub async fn adopt_legacy_functions(&self) -> usize {
+        let namespaces = match self._list_namespaces().await {
+            Ok(namespaces) => namespaces,
+            Err(e) => {
+                log::error!("Adopt: failed to list namespaces: {}", e);
+                return 0;
+            }
+        };
+        let mut adopted = 0;
+        for namespace in namespaces {
+            let containers = match backend().list_container(&namespace).await {
+                Ok(containers) => containers,
+                Err(e) => {
+                    log::error!("Adopt: failed to list containers of {}: {:?}", namespace, e);
+                    continue;
+                }
+            };
+            for container in containers.iter().filter(|c| is_legacy_function(c)) {
+                let function = Endpoint::new(&container.id, &namespace);
+                match self.adopt_legacy_function(&function, container).await {
+                    Ok(()) => adopted += 1,
+                    Err(e) => log::error!("Adopt: failed to adopt {}: {}", function, e),
+                }
+            }
+        }
+        if adopted > 0 {
+            log::info!("Adopted {} functions deployed before replicas", adopted);
+        }
+        adopted
+    }
+
+    async fn adopt_legacy_function(
+        &self,
+        function: &Endpoint,
+        legacy: &Container,
+    ) -> Result<(), String> {
+        let lock = self.wake_lock(function);
+        let _adopting = lock.lock().await;
+        let replicas = backend()
+            .list_function_containers(function)
+            .await
+            .map_err(|e| e.to_string())?;
+        if replicas.is_empty() {
+            log::info!("Adopt: redeploying {} as replica 0", function);
+            let metadata = ContainerStaticMetadata::new(&legacy.image, function, 0);
+            self.create_instance(&metadata)
+                .await
+                .map_err(|e| e.to_string())?;
+        } else {
+            // a previous run got as far as the replica
+            log::info!("Adopt: {} already has replicas", function);
+        }
+
+        // the function itself is the endpoint of the legacy container
+        let mut errors = Vec::new();
+        match backend().kill_task_with_timeout(function).await {
+            Ok(()) | Err(TaskError::NotFound) => {}
+            Err(e) => errors.push(format!("task: {:?}", e)),
+        }
+        if let Err(e) = backend().delete_container(function).await {
+            errors.push(format!("container: {:?}", e));
+        }
+        if let Err(e) = backend().remove_snapshot(function).await {
+            errors.push(format!("snapshot: {:?}", e));
+        }
+        if cni::cni_impl::network_exists(function)
+            && let Err(e) = cni::cni_impl::delete_cni_network(function.clone())
+        {
+            errors.push(format!("network: {}", e));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "replica 0 is up, cleanup left {}",
+                errors.join(", ")
+            ))
+        }
+    }
+}
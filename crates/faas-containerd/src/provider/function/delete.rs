@@ -1,35 +1,46 @@
 use crate::impls::cni::Endpoint;
-use crate::impls::{backend, cni};
 use crate::provider::ContainerdProvider;
 use gateway::handlers::function::DeleteError;
 use gateway::types::function::Query;
 
 impl ContainerdProvider {
     pub(crate) async fn _delete(&self, function: Query) -> Result<(), DeleteError> {
-        let endpoint: Endpoint = function.into();
-        log::trace!("Deleting function: {:?}", endpoint);
+        let function: Endpoint = function.into();
+        self.remove_function(&function).await?;
+        self.forget_logs(&function).await;
+        Ok(())
+    }
 
-        backend().kill_task_with_timeout(&endpoint).await?;
+    /// Tear the function down, its logs are kept for the redeployment of an update
+    pub(crate) async fn remove_function(&self, function: &Endpoint) -> Result<(), DeleteError> {
+        log::trace!("Deleting function: {:?}", function);
 
-        let del_ctr_err = backend().delete_container(&endpoint).await.map_err(|e| {
-            log::error!("Failed to delete container: {:?}", e);
-            e
-        });
+        let replicas = self
+            .instance_replicas(function)
+            .await
+            .map_err(DeleteError::Internal)?;
+        if replicas.is_empty() {
+            return Err(DeleteError::NotFound("container not found".to_string()));
+        }
 
-        let rm_snap_err = backend().remove_snapshot(&endpoint).await.map_err(|e| {
-            log::error!("Failed to remove snapshot: {:?}", e);
-            e
-        });
+        let mut errors = Vec::new();
+        for replica in replicas {
+            if let Err(e) = self.remove_instance(function, replica).await {
+                errors.push(e);
+            }
+        }
 
-        let del_net_err = cni::cni_impl::delete_cni_network(endpoint);
+        self.forget_strategy(function);
+        self.forget_timeouts(function);
+        self.forget_protocol(function);
+        self.forget_idle(function);
+        self.forget_warm_pool(function);
+        self.forget_function_record(function);
 
-        if del_ctr_err.is_ok() && rm_snap_err.is_ok() && del_net_err.is_ok() {
+        if errors.is_empty() {
             Ok(())
         } else {
-            Err(DeleteError::Internal(format!(
-                "{:?}, {:?}, {:?}",
-                del_ctr_err, rm_snap_err, del_net_err
-            )))
+            Err(DeleteError::Internal(format!("{:?}", errors)))
         }
     }
 }
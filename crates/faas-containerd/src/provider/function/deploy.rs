@@ -1,96 +1,193 @@
-use crate::impls::cni;
+use crate::consts;
+use crate::impls::cni::Endpoint;
 use crate::impls::{self, backend, function::ContainerStaticMetadata};
-use crate::provider::ContainerdProvider;
-use gateway::handlers::function::DeployError;
-use gateway::types::function::Deployment;
-use scopeguard::{ScopeGuard, guard};
+use crate::provider::{
+    ContainerdProvider, balancer::Strategy, function::record::FunctionRecord, idle::idle_policy,
+    warm::min_warm,
+};
+use chrono::{DateTime, Utc};
+use gateway::handlers::{function::DeployError, namespace::NamespaceError};
+use gateway::types::function::{Deployment, FunctionTimeouts, Protocol, Query};
+
+/// Number of replicas to create on deploy, from the `com.openfaas.scale.min` label
+pub(crate) fn min_replicas(config: &Deployment) -> Result<u32, DeployError> {
+    let Some(value) = config
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(consts::MIN_REPLICAS_LABEL))
+    else {
+        return Ok(consts::DEFAULT_REPLICAS);
+    };
+    match value.parse::<u32>() {
+        Ok(replicas) if replicas > 0 => Ok(replicas),
+        _ => Err(DeployError::Invalid(format!(
+            "label {} should be a positive integer, got {}",
+            consts::MIN_REPLICAS_LABEL,
+            value
+        ))),
+    }
+}
+
+/// Load balancing strategy from the `com.faasrs.loadbalancer` label
+pub(crate) fn load_balancer(config: &Deployment) -> Result<Strategy, DeployError> {
+    config
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(consts::LOAD_BALANCER_LABEL))
+        .map_or(Ok(Strategy::default()), |value| {
+            value.parse().map_err(DeployError::Invalid)
+        })
+}
+
+/// Port from the `com.faasrs.port` annotation, `None` leaves it to the image
+pub(crate) fn port_annotation(config: &Deployment) -> Result<Option<u16>, DeployError> {
+    let Some(value) = config
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(consts::PORT_ANNOTATION))
+    else {
+        return Ok(None);
+    };
+    match value.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(Some(port)),
+        _ => Err(DeployError::Invalid(format!(
+            "annotation {} should be a port number, got {}",
+            consts::PORT_ANNOTATION,
+            value
+        ))),
+    }
+}
 
 impl ContainerdProvider {
     pub(crate) async fn _deploy(&self, config: Deployment) -> Result<(), DeployError> {
-        let metadata = ContainerStaticMetadata::from(config);
-        log::trace!("Deploying function: {:?}", metadata);
+        let replicas = min_replicas(&config)?;
+        self.deploy_replicas(config, replicas, None).await
+    }
+
+    /// `created_at` is kept by the updates, a new function is created now
+    pub(crate) async fn deploy_replicas(
+        &self,
+        config: Deployment,
+        replicas: u32,
+        created_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DeployError> {
+        let function = Endpoint::from(Query {
+            service: config.service.clone(),
+            namespace: config.namespace.clone(),
+        });
+        self.check_namespace(&function.namespace)
+            .await
+            .map_err(|e| match e {
+                NamespaceError::NotFound(e) => DeployError::Invalid(e),
+                e => DeployError::InternalError(e.to_string()),
+            })?;
+        let strategy = load_balancer(&config)?;
+        let timeouts = FunctionTimeouts::from_annotations(config.annotations.as_ref())
+            .map_err(DeployError::Invalid)?;
+        let protocol = Protocol::from_annotations(config.annotations.as_ref())
+            .map_err(DeployError::Invalid)?;
+        let annotated_port = port_annotation(&config)?;
+        let idle = idle_policy(&config)?;
+        let warm = min_warm(&config)?;
+        let secrets = self
+            .secret_mounts(
+                &function.namespace,
+                config.secrets.as_deref().unwrap_or_default(),
+            )
+            .map_err(|e| DeployError::Invalid(e.to_string()))?;
+        log::trace!(
+            "Deploying function: {:?} with {} replicas",
+            function,
+            replicas
+        );
 
         // not going to check the conflict of namespace, should be handled by containerd backend
         backend()
-            .prepare_image(&metadata.image, &metadata.endpoint.namespace, true)
+            .prepare_image(&config.image, &function.namespace, true)
             .await
             .map_err(|img_err| {
                 use impls::oci_image::ImageError;
-                log::error!("Image '{}' fetch failed: {}", &metadata.image, img_err);
+                log::error!("Image '{}' fetch failed: {}", config.image, img_err);
                 match img_err {
                     ImageError::ImageNotFound(e) => DeployError::Invalid(e.to_string()),
                     _ => DeployError::InternalError(img_err.to_string()),
                 }
             })?;
-        log::trace!("Image '{}' fetch ok", &metadata.image);
-
-        let mounts = backend().prepare_snapshot(&metadata).await.map_err(|e| {
-            log::error!("Failed to prepare snapshot: {:?}", e);
-            DeployError::InternalError(e.to_string())
-        })?;
-
-        let snapshot_defer = scopeguard::guard((), |()| {
-            log::trace!("Cleaning up snapshot");
-            let endpoint = metadata.endpoint.clone();
-            tokio::spawn(async move { backend().remove_snapshot(&endpoint).await });
-        });
-
-        // let network = CNIEndpoint::new(&metadata.container_id, &metadata.namespace)?;
-        let (ip, netns) = cni::cni_impl::create_cni_network(&metadata.endpoint).map_err(|e| {
-            log::error!("Failed to create CNI network: {}", e);
-            DeployError::InternalError(e.to_string())
-        })?;
-
-        let netns_defer = guard(netns, |ns| ns.remove().unwrap());
-
-        let _ = backend().create_container(&metadata).await.map_err(|e| {
-            log::error!("Failed to create container: {:?}", e);
-            DeployError::InternalError(e.to_string())
-        })?;
-
-        let container_defer = scopeguard::guard((), |()| {
-            let endpoint = metadata.endpoint.clone();
-            tokio::spawn(async move { backend().delete_container(&endpoint).await });
-        });
-
-        // TODO: Use ostree-ext
-        // let img_conf = BACKEND.get().unwrap().get_runtime_config(&metadata.image).unwrap();
-
-        backend().new_task(mounts, &metadata.endpoint).await?;
-
-        let task_defer = scopeguard::guard((), |()| {
-            let endpoint = metadata.endpoint.clone();
-            tokio::spawn(async move { backend().kill_task_with_timeout(&endpoint).await });
-        });
+        log::trace!("Image '{}' fetch ok", config.image);
+        let image_digest = backend()
+            .image_digest(&config.image, &function.namespace)
+            .await
+            .map_err(|e| log::warn!("Failed to resolve digest of '{}': {}", config.image, e))
+            .ok();
 
-        use std::net::IpAddr::*;
+        let port = match annotated_port {
+            Some(port) => port,
+            None => backend()
+                .exposed_port(&config.image, &function.namespace)
+                .await
+                .map_err(|e| DeployError::InternalError(e.to_string()))?
+                .unwrap_or(consts::DEFAULT_FUNCTION_PORT),
+        };
+        log::trace!("Function {} serves on port {}", function, port);
 
-        match ip.address() {
-            V4(addr) => {
-                if let Err(err) = self
-                    .database
-                    .insert(metadata.endpoint.to_string(), &addr.octets())
-                {
-                    log::error!("Failed to insert into database: {:?}", err);
-                    return Err(DeployError::InternalError(err.to_string()));
-                }
-            }
-            V6(addr) => {
-                if let Err(err) = self
-                    .database
-                    .insert(metadata.endpoint.to_string(), &addr.octets())
-                {
-                    log::error!("Failed to insert into database: {:?}", err);
-                    return Err(DeployError::InternalError(err.to_string()));
+        for replica in 0..replicas {
+            let metadata = ContainerStaticMetadata::new(&config.image, &function, replica)
+                .with_secrets(secrets.clone())
+                .with_port(port);
+            if let Err(e) = self.create_instance(&metadata).await {
+                // roll back the replicas already running
+                for created in 0..replica {
+                    if let Err(e) = self.remove_instance(&function, created).await {
+                        log::error!("Failed to roll back replica {}: {:?}", created, e);
+                    }
                 }
+                return Err(e);
             }
         }
 
-        log::info!("container was created successfully: {}", metadata.endpoint);
-        ScopeGuard::into_inner(snapshot_defer);
-        ScopeGuard::into_inner(netns_defer);
-        ScopeGuard::into_inner(container_defer);
-        ScopeGuard::into_inner(task_defer);
+        if let Err(e) = self.save_strategy(&function, strategy) {
+            log::error!("Failed to save strategy of {}: {:?}", function, e);
+        }
+        if let Err(e) = self.save_timeouts(&function, &timeouts) {
+            log::error!("Failed to save timeouts of {}: {:?}", function, e);
+        }
+        if let Err(e) = self.save_protocol(&function, protocol) {
+            log::error!("Failed to save protocol of {}: {:?}", function, e);
+        }
+        if let Err(e) = self.save_idle_policy(idle.as_ref()) {
+            log::error!("Failed to save idle policy of {}: {:?}", function, e);
+        }
+        if let Err(e) = self.save_min_warm(&function, warm) {
+            log::error!("Failed to save warm pool of {}: {:?}", function, e);
+        }
+        let revision = self.next_revision(&function).unwrap_or_else(|e| {
+            log::error!("Failed to count revision of {}: {:?}", function, e);
+            0
+        });
+        let record = FunctionRecord {
+            deployment: config,
+            image_digest,
+            created_at: created_at.unwrap_or_else(Utc::now),
+            revision,
+            addresses: self
+                .instance_addresses(&function)
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+        };
+        if let Err(e) = self.save_function_record(&function, &record) {
+            log::error!("Failed to save record of {}: {:?}", function, e);
+        }
+        // the function serves already, a pool left short is topped up later
+        if let Err(e) = self.fill_warm_pool(&function).await {
+            log::error!("Failed to fill warm pool of {}: {}", function, e);
+        }
+
+        log::info!(
+            "function was deployed successfully: {} ({} replicas)",
+            function,
+            replicas
+        );
         Ok(())
     }
 }
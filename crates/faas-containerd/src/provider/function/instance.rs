@@ -0,0 +1,417 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use containerd_client::services::v1::Container;
+use gateway::handlers::function::{DeleteError, DeployError};
+use scopeguard::{ScopeGuard, guard};
+
+use crate::consts;
+use crate::impls::cni::{self, Endpoint};
+use crate::impls::{
+    backend,
+    container::{container_port, container_secrets},
+    function::ContainerStaticMetadata,
+    task::{TaskError, is_running},
+};
+use crate::provider::{ContainerdProvider, schema::ADDRESSES_TREE};
+
+/// Database key of the address of one replica, `<namespace>-<service>/<replica>`
+fn address_key(function: &Endpoint, replica: u32) -> String {
+    format!("{}/{}", function, replica)
+}
+
+/// The octets of the address followed by the port in network byte order
+pub(crate) fn encode_address(addr: SocketAddr) -> Vec<u8> {
+    let mut value = match addr.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    value.extend_from_slice(&addr.port().to_be_bytes());
+    value
+}
+
+/// Records written before the port was stored only hold the address,
+/// those replicas serve on the default port
+pub(crate) fn decode_address(value: &[u8]) -> Option<SocketAddr> {
+    let (ip, port) = match value.len() {
+        4 | 16 => (value, consts::DEFAULT_FUNCTION_PORT),
+        6 | 18 => {
+            let (ip, port) = value.split_at(value.len() - 2);
+            (ip, u16::from_be_bytes([port[0], port[1]]))
+        }
+        _ => return None,
+    };
+    let ip = if let Ok(octets) = <[u8; 4]>::try_from(ip) {
+        IpAddr::V4(Ipv4Addr::from(octets))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(ip).ok()?))
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Replica addresses of the function recorded in `tree`, keyed by [`address_key`]
+pub(crate) fn scan_addresses(
+    tree: &sled::Tree,
+    function: &Endpoint,
+) -> Result<Vec<(u32, SocketAddr)>, sled::Error> {
+    let prefix = format!("{}/", function);
+    let mut addresses = Vec::new();
+    for item in tree.scan_prefix(&prefix) {
+        let (key, value) = item?;
+        let replica = String::from_utf8_lossy(&key[prefix.len()..]).parse::<u32>();
+        match (replica, decode_address(&value)) {
+            (Ok(replica), Some(addr)) => addresses.push((replica, addr)),
+            _ => log::warn!(
+                "Ignoring malformed address record {:?}",
+                String::from_utf8_lossy(&key)
+            ),
+        }
+    }
+    Ok(addresses)
+}
+
+/// Remove whatever task and network a replica left behind, an exited task
+/// still holds the container id
+async fn clear_stale_task(endpoint: &Endpoint) -> Result<(), TaskError> {
+    let stale = match backend().get_task(endpoint).await {
+        Ok(task) if is_running(&task) => backend().kill_task_with_timeout(endpoint).await,
+        Ok(_) => backend().delete_task(endpoint).await,
+        Err(e) => Err(e),
+    };
+    match stale {
+        Ok(()) | Err(TaskError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+    if cni::cni_impl::network_exists(endpoint)
+        && let Err(e) = cni::cni_impl::delete_cni_network(endpoint.clone())
+    {
+        log::warn!("Failed to delete stale network of {}: {}", endpoint, e);
+    }
+    Ok(())
+}
+
+impl ContainerdProvider {
+    /// Addresses of every replica of the function recorded in the database
+    pub(crate) fn instance_addresses(
+        &self,
+        function: &Endpoint,
+    ) -> Result<Vec<(u32, SocketAddr)>, sled::Error> {
+        scan_addresses(&self.database.open_tree(ADDRESSES_TREE)?, function)
+    }
+
+    pub(crate) fn forget_instance_address(&self, function: &Endpoint, replica: u32) {
+        self.update_record_address(function, replica, None);
+        if let Err(e) = self
+            .database
+            .open_tree(ADDRESSES_TREE)
+            .and_then(|tree| tree.remove(address_key(function, replica)))
+        {
+            log::error!(
+                "Failed to remove address of {}/{}: {:?}",
+                function,
+                replica,
+                e
+            );
+        }
+    }
+
+    pub(crate) fn record_address(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: SocketAddr,
+    ) -> Result<(), DeployError> {
+        let key = address_key(function, replica);
+        self.update_record_address(function, replica, Some(addr));
+        self.database
+            .open_tree(ADDRESSES_TREE)
+            .and_then(|tree| tree.insert(key, encode_address(addr)))
+            .map(|_| ())
+            .map_err(|err| {
+                log::error!("Failed to insert into database: {:?}", err);
+                DeployError::InternalError(err.to_string())
+            })
+    }
+
+    /// Give a replica whose task is gone a new network and task, on top of
+    /// the container and snapshot it kept
+    pub(crate) async fn restart_instance(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        port: u16,
+    ) -> Result<SocketAddr, DeployError> {
+        let endpoint = function.instance(replica);
+        log::trace!("Restarting instance: {:?}", endpoint);
+
+        clear_stale_task(&endpoint).await.map_err(|e| {
+            log::error!("Failed to clean up task of {}: {:?}", endpoint, e);
+            DeployError::InternalError(e.to_string())
+        })?;
+
+        let mounts = backend()
+            .get_mounts(&endpoint.service, &endpoint.namespace)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to get mounts of {}: {:?}", endpoint, e);
+                DeployError::InternalError(e.to_string())
+            })?;
+
+        let (ip, netns) = cni::cni_impl::create_cni_network(&endpoint).map_err(|e| {
+            log::error!("Failed to create CNI network: {}", e);
+            DeployError::InternalError(e.to_string())
+        })?;
+        let netns_defer = guard(netns, |ns| ns.remove().unwrap());
+
+        let log_file = self
+            .instance_log_file(function, &endpoint)
+            .map_err(|e| DeployError::Invalid(e.to_string()))?;
+        backend()
+            .new_task(mounts, &endpoint, Some(&log_file))
+            .await?;
+        let task_defer = scopeguard::guard((), |()| {
+            let endpoint = endpoint.clone();
+            tokio::spawn(async move { backend().kill_task_with_timeout(&endpoint).await });
+        });
+
+        let addr = SocketAddr::new(ip.address(), port);
+        self.record_address(function, replica, addr)?;
+
+        log::info!("task was restarted successfully: {}", endpoint);
+        ScopeGuard::into_inner(netns_defer);
+        ScopeGuard::into_inner(task_defer);
+        Ok(addr)
+    }
+
+    /// Metadata of a new replica made like `template`, an existing replica of the
+    /// function: same image, port and secrets
+    pub(crate) fn replica_like(
+        &self,
+        function: &Endpoint,
+        template: &Container,
+        replica: u32,
+    ) -> Result<ContainerStaticMetadata, String> {
+        let secrets = self
+            .secret_mounts(&function.namespace, &container_secrets(template))
+            .map_err(|e| e.to_string())?;
+        Ok(
+            ContainerStaticMetadata::new(&template.image, function, replica)
+                .with_secrets(secrets)
+                .with_port(container_port(template)),
+        )
+    }
+
+    /// Prepare the snapshot, network, container and task of one replica,
+    /// then record its address. Everything created is rolled back on failure.
+    pub(crate) async fn create_instance(
+        &self,
+        metadata: &ContainerStaticMetadata,
+    ) -> Result<SocketAddr, DeployError> {
+        self.build_instance(metadata, true).await
+    }
+
+    /// Same as [`Self::create_instance`] but the task is only created, the
+    /// caller publishes the replica in the warm pool of the function
+    pub(crate) async fn create_warm_instance(
+        &self,
+        metadata: &ContainerStaticMetadata,
+    ) -> Result<SocketAddr, DeployError> {
+        self.build_instance(metadata, false).await
+    }
+
+    async fn build_instance(
+        &self,
+        metadata: &ContainerStaticMetadata,
+        start: bool,
+    ) -> Result<SocketAddr, DeployError> {
+        let mounts = backend().prepare_snapshot(metadata).await.map_err(|e| {
+            log::error!("Failed to prepare snapshot: {:?}", e);
+            DeployError::InternalError(e.to_string())
+        })?;
+
+        let snapshot_defer = scopeguard::guard((), |()| {
+            log::trace!("Cleaning up snapshot");
+            let endpoint = metadata.endpoint.clone();
+            tokio::spawn(async move { backend().remove_snapshot(&endpoint).await });
+        });
+
+        let (ip, netns) = cni::cni_impl::create_cni_network(&metadata.endpoint).map_err(|e| {
+            log::error!("Failed to create CNI network: {}", e);
+            DeployError::InternalError(e.to_string())
+        })?;
+
+        let netns_defer = guard(netns, |ns| ns.remove().unwrap());
+
+        let _ = backend().create_container(metadata).await.map_err(|e| {
+            log::error!("Failed to create container: {:?}", e);
+            DeployError::InternalError(e.to_string())
+        })?;
+
+        let container_defer = scopeguard::guard((), |()| {
+            let endpoint = metadata.endpoint.clone();
+            tokio::spawn(async move { backend().delete_container(&endpoint).await });
+        });
+
+        // TODO: Use ostree-ext
+        // let img_conf = BACKEND.get().unwrap().get_runtime_config(&metadata.image).unwrap();
+
+        let log_file = self
+            .instance_log_file(&metadata.function, &metadata.endpoint)
+            .map_err(|e| DeployError::Invalid(e.to_string()))?;
+        if let Some(dir) = log_file.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                log::error!("Failed to create log directory {:?}: {}", dir, e);
+                DeployError::InternalError(e.to_string())
+            })?;
+        }
+        backend()
+            .create_task(mounts, &metadata.endpoint, Some(&log_file))
+            .await?;
+
+        let task_defer = scopeguard::guard((), |()| {
+            let endpoint = metadata.endpoint.clone();
+            tokio::spawn(async move { backend().kill_task_with_timeout(&endpoint).await });
+        });
+
+        let addr = SocketAddr::new(ip.address(), metadata.port);
+        if start {
+            backend().start_task(&metadata.endpoint).await?;
+            self.record_address(&metadata.function, metadata.replica, addr)?;
+            log::info!("container was created successfully: {}", metadata.endpoint);
+        } else {
+            log::info!("warm container was prepared: {}", metadata.endpoint);
+        }
+
+        ScopeGuard::into_inner(snapshot_defer);
+        ScopeGuard::into_inner(netns_defer);
+        ScopeGuard::into_inner(container_defer);
+        ScopeGuard::into_inner(task_defer);
+        Ok(addr)
+    }
+
+    /// Tear down one replica, keeps going after partial failures and reports all of them
+    pub(crate) async fn remove_instance(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+    ) -> Result<(), DeleteError> {
+        let endpoint = function.instance(replica);
+        log::trace!("Removing instance: {:?}", endpoint);
+
+        let kill_err = match backend().kill_task_with_timeout(&endpoint).await {
+            Err(TaskError::NotFound) => Ok(()),
+            result => result.map_err(|e| {
+                log::error!("Failed to kill task: {:?}", e);
+                e
+            }),
+        };
+
+        let del_ctr_err = backend().delete_container(&endpoint).await.map_err(|e| {
+            log::error!("Failed to delete container: {:?}", e);
+            e
+        });
+
+        let rm_snap_err = backend().remove_snapshot(&endpoint).await.map_err(|e| {
+            log::error!("Failed to remove snapshot: {:?}", e);
+            e
+        });
+
+        // an instance scaled to zero has no network anymore
+        let del_net_err = if cni::cni_impl::network_exists(&endpoint) {
+            cni::cni_impl::delete_cni_network(endpoint)
+        } else {
+            Ok(())
+        };
+
+        self.forget_instance_address(function, replica);
+        self.forget_warm(function, replica);
+
+        if kill_err.is_ok() && del_ctr_err.is_ok() && rm_snap_err.is_ok() && del_net_err.is_ok() {
+            Ok(())
+        } else {
+            Err(DeleteError::Internal(format!(
+                "{:?}, {:?}, {:?}, {:?}",
+                kill_err, del_ctr_err, rm_snap_err, del_net_err
+            )))
+        }
+    }
+
+    /// Tear down a replica nobody routes to anymore without waiting for it
+    pub(crate) fn discard_instance(&self, function: &Endpoint, replica: u32) {
+        self.forget_instance_address(function, replica);
+        self.forget_warm(function, replica);
+        let endpoint = function.instance(replica);
+        tokio::spawn(async move {
+            if let Err(e) = clear_stale_task(&endpoint).await {
+                log::error!("Failed to clean up task of {}: {:?}", endpoint, e);
+                return;
+            }
+            if let Err(e) = backend().delete_container(&endpoint).await {
+                log::error!("Failed to delete container {}: {:?}", endpoint, e);
+                return;
+            }
+            if let Err(e) = backend().remove_snapshot(&endpoint).await {
+                log::error!("Failed to remove snapshot {}: {:?}", endpoint, e);
+            }
+        });
+    }
+
+    /// Replica indexes of the function, from both containerd and the database
+    pub(crate) async fn instance_replicas(&self, function: &Endpoint) -> Result<Vec<u32>, String> {
+        let containers = backend()
+            .list_function_containers(function)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut replicas: Vec<u32> = containers
+            .iter()
+            .filter_map(crate::impls::container::function_replica)
+            .map(|(_, replica)| replica)
+            .collect();
+        let addresses = self
+            .instance_addresses(function)
+            .map_err(|e| e.to_string())?;
+        replicas.extend(addresses.into_iter().map(|(replica, _)| replica));
+        replicas.sort_unstable();
+        replicas.dedup();
+        Ok(replicas)
+    }
+
+    /// Same as [`Self::instance_replicas`] without the warm pool
+    pub(crate) async fn serving_replicas(&self, function: &Endpoint) -> Result<Vec<u32>, String> {
+        let warm = self.warm_replicas(function).map_err(|e| e.to_string())?;
+        let mut replicas = self.instance_replicas(function).await?;
+        replicas.retain(|replica| !warm.iter().any(|(warm, _)| warm == replica));
+        Ok(replicas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use crate::impls::cni::Endpoint;
+
+    #[test]
+    fn test_address_key() {
+        let function = Endpoint::new("echo", "faasrs-default");
+        assert_eq!(super::address_key(&function, 2), "faasrs-default-echo/2");
+        assert_eq!(function.instance(2).service, "echo-2");
+        assert_eq!(
+            super::decode_address(&[10, 66, 0, 5]),
+            Some(SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 8080)))
+        );
+        assert_eq!(super::decode_address(&[10, 66, 0]), None);
+    }
+
+    #[test]
+    fn test_address_with_port() {
+        for addr in [
+            SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 3000)),
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 80)),
+        ] {
+            assert_eq!(
+                super::decode_address(&super::encode_address(addr)),
+                Some(addr)
+            );
+        }
+    }
+}
@@ -1,6 +1,10 @@
+pub mod adopt;
 pub mod delete;
 pub mod deploy;
+pub mod instance;
 pub mod list;
+pub mod record;
 pub mod resolve;
+pub mod scale;
 pub mod status;
 pub mod update;
@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use chrono::{DateTime, Utc};
+use gateway::types::function::{Deployment, Status};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    impls::cni::Endpoint,
+    provider::{
+        ContainerdProvider,
+        schema::{FUNCTIONS_TREE, REVISIONS_TREE},
+    },
+};
+
+/// What the function was deployed with, kept for `status` and `list`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FunctionRecord {
+    /// The request as it was received
+    pub deployment: Deployment,
+    /// Manifest the image name resolved to at deploy time
+    pub image_digest: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Deployments of the function so far, updates included
+    #[serde(default)]
+    pub revision: u64,
+    /// Address of every serving replica
+    #[serde(default)]
+    pub addresses: BTreeMap<u32, SocketAddr>,
+}
+
+impl FunctionRecord {
+    /// Fill in the fields the containers cannot tell
+    pub fn apply(self, status: &mut Status) {
+        let deployment = self.deployment;
+        status.image = deployment.image;
+        status.env_process = deployment.env_process;
+        status.env_vars = deployment.env_vars;
+        status.constraints = deployment.constraints;
+        status.secrets = deployment.secrets;
+        status.labels = deployment.labels;
+        status.annotations = deployment.annotations;
+        status.limits = deployment.limits;
+        status.requests = deployment.requests;
+        status.read_only_root_filesystem = deployment.read_only_root_filesystem;
+        status.created_at = Some(self.created_at.to_rfc3339());
+        status.image_digest = self.image_digest;
+        status.addresses = Some(
+            self.addresses
+                .into_iter()
+                .map(|(replica, addr)| (replica, addr.to_string()))
+                .collect(),
+        );
+    }
+}
+
+impl ContainerdProvider {
+    pub(crate) fn save_function_record(
+        &self,
+        function: &Endpoint,
+        record: &FunctionRecord,
+    ) -> Result<(), sled::Error> {
+        let value = serde_json::to_vec(record).expect("function record is serializable");
+        self.database
+            .open_tree(FUNCTIONS_TREE)?
+            .insert(function.to_string(), value)?;
+        Ok(())
+    }
+
+    /// `None` for functions deployed before the records were kept
+    pub(crate) fn function_record(&self, function: &Endpoint) -> Option<FunctionRecord> {
+        let value = self
+            .database
+            .open_tree(FUNCTIONS_TREE)
+            .and_then(|tree| tree.get(function.to_string()))
+            .unwrap_or_else(|e| {
+                log::error!("Failed to load record of {}: {:?}", function, e);
+                None
+            })?;
+        serde_json::from_slice(&value)
+            .map_err(|e| log::warn!("Ignoring invalid record of {}: {}", function, e))
+            .ok()
+    }
+
+    pub(crate) fn forget_function_record(&self, function: &Endpoint) {
+        for name in [FUNCTIONS_TREE, REVISIONS_TREE] {
+            if let Err(e) = self
+                .database
+                .open_tree(name)
+                .and_then(|tree| tree.remove(function.to_string()))
+            {
+                log::error!("Failed to remove {} record of {}: {:?}", name, function, e);
+            }
+        }
+    }
+
+    /// Current revision of the function, 0 before its first deployment
+    pub(crate) fn revision(&self, function: &Endpoint) -> Result<u64, sled::Error> {
+        let value = self
+            .database
+            .open_tree(REVISIONS_TREE)?
+            .get(function.to_string())?;
+        Ok(value
+            .and_then(|value| <[u8; 8]>::try_from(value.as_ref()).ok())
+            .map_or(0, u64::from_be_bytes))
+    }
+
+    pub(crate) fn set_revision(
+        &self,
+        function: &Endpoint,
+        revision: u64,
+    ) -> Result<(), sled::Error> {
+        self.database
+            .open_tree(REVISIONS_TREE)?
+            .insert(function.to_string(), &revision.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Count one more deployment of the function
+    pub(crate) fn next_revision(&self, function: &Endpoint) -> Result<u64, sled::Error> {
+        let revision = self.revision(function)? + 1;
+        self.set_revision(function, revision)?;
+        Ok(revision)
+    }
+
+    /// Keep the addresses in the record of the function in step with the
+    /// replicas, `None` forgets the replica
+    pub(crate) fn update_record_address(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: Option<SocketAddr>,
+    ) {
+        self.update_record_address_by_key(&function.to_string(), replica, addr);
+    }
+
+    /// Same as [`Self::update_record_address`], for a function only known by
+    /// its `<namespace>-<service>` key
+    pub(crate) fn update_record_address_by_key(
+        &self,
+        function: &str,
+        replica: u32,
+        addr: Option<SocketAddr>,
+    ) {
+        let updated = self.database.open_tree(FUNCTIONS_TREE).and_then(|tree| {
+            tree.fetch_and_update(function, |value| {
+                let value = value?;
+                let Ok(mut record) = serde_json::from_slice::<FunctionRecord>(value) else {
+                    return Some(value.to_vec());
+                };
+                match addr {
+                    Some(addr) => record.addresses.insert(replica, addr),
+                    None => record.addresses.remove(&replica),
+                };
+                Some(serde_json::to_vec(&record).expect("function record is serializable"))
+            })
+        });
+        if let Err(e) = updated {
+            log::error!("Failed to update record of {}: {:?}", function, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, SocketAddr},
+    };
+
+    use chrono::Utc;
+    use gateway::types::function::{Deployment, Status};
+
+    use super::FunctionRecord;
+    use crate::provider::test::deployment;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let record = FunctionRecord {
+            deployment: Deployment {
+                namespace: Some("faasrs-default".to_string()),
+                env_process: Some("cat".to_string()),
+                env_vars: Some(HashMap::from([("MODE".to_string(), "fast".to_string())])),
+                secrets: Some(vec!["token".to_string()]),
+                read_only_root_filesystem: true,
+                ..deployment(&[])
+            },
+            image_digest: Some("sha256:0123".to_string()),
+            created_at: Utc::now(),
+            revision: 3,
+            addresses: [(0, SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 8080)))].into(),
+        };
+        let value = serde_json::to_vec(&record).unwrap();
+        let decoded: FunctionRecord = serde_json::from_slice(&value).unwrap();
+        assert_eq!(decoded.deployment.env_process.as_deref(), Some("cat"));
+        assert_eq!(decoded.deployment.secrets, record.deployment.secrets);
+        assert!(decoded.deployment.read_only_root_filesystem);
+        assert_eq!(decoded.image_digest, record.image_digest);
+        assert_eq!(decoded.created_at, record.created_at);
+        assert_eq!(decoded.revision, 3);
+        assert_eq!(decoded.addresses, record.addresses);
+
+        let mut status: Status = serde_json::from_str(r#"{"name":"echo","image":""}"#).unwrap();
+        decoded.apply(&mut status);
+        assert_eq!(status.image, "docker.io/library/echo:latest");
+        assert_eq!(status.image_digest.as_deref(), Some("sha256:0123"));
+        assert_eq!(
+            status.addresses,
+            Some([(0, "10.66.0.5:8080".to_string())].into())
+        );
+        assert_eq!(status.created_at, Some(record.created_at.to_rfc3339()));
+    }
+}
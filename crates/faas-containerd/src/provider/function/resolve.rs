@@ -1,65 +1,144 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::SocketAddr;
 
 use actix_http::uri::Builder;
 use gateway::handlers::function::ResolveError;
-use gateway::types::function::Query;
+use gateway::types::{function::Query, upstream::Upstream};
 
 use crate::impls::cni::{self, Endpoint};
-use crate::provider::ContainerdProvider;
+use crate::provider::{ContainerdProvider, schema::FUNCTIONS_TREE};
 
-fn upstream(addr: IpAddr) -> Builder {
+fn upstream(addr: SocketAddr) -> Builder {
     actix_http::Uri::builder()
         .scheme("http")
-        .authority(format!("{}:{}", addr, 8080))
+        .authority(addr.to_string())
 }
 
 impl ContainerdProvider {
-    pub(crate) async fn _resolve(
+    /// Recorded addresses whose network is still there, the others are forgotten
+    pub(crate) fn healthy_addresses(
         &self,
-        query: Query,
-    ) -> Result<actix_http::uri::Builder, ResolveError> {
-        let endpoint = Endpoint::from(query);
-        log::trace!("Resolving function: {:?}", endpoint);
-        let addr_oct = self
-            .database
-            .get(endpoint.to_string())
-            .map_err(|e| {
-                log::error!("Failed to get container address: {:?}", e);
-                ResolveError::Internal(e.to_string())
-            })?
-            .ok_or(ResolveError::NotFound("container not found".to_string()))?;
-
-        log::trace!("Container address: {:?}", addr_oct.as_array::<4>());
-
-        // We force the address to be IPv4 here
-        let addr = IpAddr::V4(Ipv4Addr::from_octets(*addr_oct.as_array::<4>().unwrap()));
+        function: &Endpoint,
+    ) -> Result<Vec<SocketAddr>, ResolveError> {
+        let addresses = self.instance_addresses(function).map_err(|e| {
+            log::error!("Failed to get container address: {:?}", e);
+            ResolveError::Internal(e.to_string())
+        })?;
 
         // Check if the coresponding netns is still alive
         // We can achieve this by checking the /run/cni/faasrs-cni-bridge,
         // if the ip filename is still there
+        let mut healthy = Vec::with_capacity(addresses.len());
+        for (replica, addr) in addresses {
+            if cni::cni_impl::check_network_exists(addr.ip()) {
+                healthy.push(addr);
+            } else {
+                log::error!("CNI network not exists for {}", addr);
+                self.forget_instance_address(function, replica);
+            }
+        }
+        Ok(healthy)
+    }
+
+    /// Whether the function was deployed, functions deployed before the records
+    /// were kept only have their addresses
+    fn is_deployed(&self, function: &Endpoint) -> Result<bool, ResolveError> {
+        let recorded = self
+            .database
+            .open_tree(FUNCTIONS_TREE)
+            .and_then(|tree| tree.contains_key(function.to_string()))
+            .and_then(|recorded| Ok(recorded || !self.instance_addresses(function)?.is_empty()));
+        recorded.map_err(|e| {
+            log::error!("Failed to look up function {}: {:?}", function, e);
+            ResolveError::Internal(e.to_string())
+        })
+    }
+
+    pub(crate) async fn _resolve(&self, query: Query) -> Result<Upstream, ResolveError> {
+        let endpoint = Endpoint::from(query);
+        log::trace!("Resolving function: {:?}", endpoint);
+        // the per function state is kept in memory, none for names nothing deployed
+        if !self.is_deployed(&endpoint)? {
+            return Err(ResolveError::NotFound(format!(
+                "function {} not found",
+                endpoint
+            )));
+        }
+        self.touch(&endpoint);
+        let strategy = self.strategy(&endpoint);
+        let balancer = self.balancer(&endpoint);
+        let pick = |healthy: &[SocketAddr]| {
+            let (addr, inflight) = balancer.pick(strategy, healthy)?;
+            log::trace!("Picked {} for {} ({})", addr, endpoint, strategy);
+            Some(
+                Upstream::new(upstream(addr))
+                    .with_inflight(inflight)
+                    .with_timeouts(self.timeouts(&endpoint))
+                    .with_protocol(self.protocol(&endpoint)),
+            )
+        };
+
+        // the idle controller stops and pauses under the wake lock, so the markers are
+        // checked under it and the invocation counts as outstanding before it is released
+        {
+            let lock = self.wake_lock(&endpoint);
+            let _checking = lock.lock().await;
+            if !self.paused(&endpoint) && !self.scaled_to_zero(&endpoint) {
+                let healthy = self.healthy_addresses(&endpoint)?;
+                if let Some(upstream) = pick(&healthy) {
+                    return Ok(upstream);
+                }
+            }
+        }
 
-        if cni::cni_impl::check_network_exists(addr) {
-            log::trace!("CNI network exists for {}", addr);
-            Ok(upstream(addr))
-        } else {
-            log::error!("CNI network not exists for {}", addr);
-            let _ = self.database.remove(endpoint.to_string());
-            Err(ResolveError::Internal("CNI network not exists".to_string()))
+        // brought back under the wake lock, the invocation was touched above so
+        // the controller does not take it away before it is picked
+        if self.paused(&endpoint) {
+            self.resume_instances(&endpoint).await?;
+        }
+        let mut healthy = self.healthy_addresses(&endpoint)?;
+        if healthy.is_empty() || self.scaled_to_zero(&endpoint) {
+            healthy = self.cold_start(&endpoint).await?;
         }
+        pick(&healthy).ok_or(ResolveError::Internal("CNI network not exists".to_string()))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use gateway::{handlers::function::ResolveError, types::function::Query};
+
+    use crate::provider::test::provider;
+
+    #[tokio::test]
+    async fn test_resolve_unknown() {
+        let provider = provider();
+        for service in ["nope", "nope-1", "nope-2"] {
+            let query = Query {
+                service: service.to_string(),
+                namespace: None,
+            };
+            let result = provider._resolve(query).await;
+            assert!(matches!(result, Err(ResolveError::NotFound(_))));
+        }
+        // nothing is kept for names that were never deployed
+        assert!(provider.last_invocations.lock().unwrap().is_empty());
+        assert!(provider.wake_locks.lock().unwrap().is_empty());
+        assert!(provider.balancers.lock().unwrap().is_empty());
+    }
 
     #[test]
     fn test_uri() {
-        let addr = IpAddr::V4(Ipv4Addr::new(10, 42, 2, 48));
+        let addr = SocketAddr::from((Ipv4Addr::new(10, 42, 2, 48), 3000));
         let uri = super::upstream(addr).path_and_query("").build().unwrap();
         assert_eq!(uri.scheme_str(), Some("http"));
-        assert_eq!(uri.authority().unwrap().host(), addr.to_string());
-        assert_eq!(uri.authority().unwrap().port_u16(), Some(8080));
-        assert_eq!(uri.to_string(), format!("http://{}:8080/", addr));
+        assert_eq!(uri.authority().unwrap().host(), addr.ip().to_string());
+        assert_eq!(uri.authority().unwrap().port_u16(), Some(3000));
+        assert_eq!(uri.to_string(), "http://10.42.2.48:3000/");
+
+        let addr = SocketAddr::from((Ipv6Addr::LOCALHOST, 80));
+        let uri = super::upstream(addr).path_and_query("").build().unwrap();
+        assert_eq!(uri.to_string(), "http://[::1]:80/");
     }
 }
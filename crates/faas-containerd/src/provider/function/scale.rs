@@ -0,0 +1,164 @@
+use std::net::SocketAddr;
+
+use containerd_client::services::v1::Container;
+use gateway::{handlers::function::ScaleError, types::function::Query};
+
+use crate::{
+    impls::{backend, cni::Endpoint, container::function_replica},
+    provider::ContainerdProvider,
+};
+
+impl ContainerdProvider {
+    /// Scaling to 0 keeps the lowest replica with its task stopped, the next
+    /// invocation cold starts it like a function scaled to zero for being idle
+    pub(crate) async fn _scale(&self, function: Query, replicas: u32) -> Result<(), ScaleError> {
+        let function: Endpoint = function.into();
+        log::trace!("Scaling function {:?} to {} replicas", function, replicas);
+
+        // the stopped or paused replicas come back before more are added
+        if replicas > 0 {
+            if self.scaled_to_zero(&function) {
+                self.cold_start(&function)
+                    .await
+                    .map_err(|e| ScaleError::Internal(e.to_string()))?;
+            } else if self.paused(&function) {
+                self.resume_instances(&function)
+                    .await
+                    .map_err(|e| ScaleError::Internal(e.to_string()))?;
+            }
+        }
+
+        let lock = self.wake_lock(&function);
+        let scaling = lock.lock().await;
+        let containers = backend()
+            .list_function_containers(&function)
+            .await
+            .map_err(|e| {
+                log::error!("failed to list containers of {:?}: {:?}", function, e);
+                ScaleError::Internal(e.to_string())
+            })?;
+        // new replicas are made like the existing ones, same image, port and secrets
+        let first = containers
+            .first()
+            .ok_or(ScaleError::NotFound("container not found".to_string()))?;
+        let warm = self
+            .warm_replicas(&function)
+            .map_err(|e| ScaleError::Internal(e.to_string()))?;
+        let all: Vec<u32> = containers
+            .iter()
+            .filter_map(function_replica)
+            .map(|(_, replica)| replica)
+            .collect();
+        let mut current: Vec<u32> = all
+            .iter()
+            .copied()
+            .filter(|replica| !warm.iter().any(|(warm, _)| warm == replica))
+            .collect();
+        current.sort_unstable();
+
+        let target = (replicas as usize).max(1);
+        if current.len() < target {
+            let mut claimed = Vec::new();
+            let mut created = Vec::new();
+            let missing = target - current.len();
+            if let Err(e) = self
+                .scale_up(&function, first, &all, missing, &mut claimed, &mut created)
+                .await
+            {
+                // leave the function as it was, the prepared replicas go back to the pool
+                log::error!("failed to scale up {:?}: {}", function, e);
+                for replica in created {
+                    if let Err(e) = self.remove_instance(&function, replica).await {
+                        log::error!("failed to roll back {}/{}: {:?}", function, replica, e);
+                    }
+                }
+                self.return_warm(&function, &claimed);
+                return Err(e);
+            }
+        } else {
+            // remove the highest replica indexes first
+            for replica in current.iter().rev().take(current.len() - target) {
+                self.remove_instance(&function, *replica)
+                    .await
+                    .map_err(|e| {
+                        log::error!("failed to scale down {:?}: {:?}", function, e);
+                        ScaleError::Internal(e.to_string())
+                    })?;
+            }
+        }
+        if replicas == 0 {
+            self.stop_replicas(&function)
+                .await
+                .map_err(ScaleError::Internal)?;
+        }
+
+        drop(scaling);
+
+        log::info!("function {} scaled to {} replicas", function, replicas);
+        if let Err(e) = self.fill_warm_pool(&function).await {
+            log::error!("Failed to fill warm pool of {}: {}", function, e);
+        }
+        Ok(())
+    }
+
+    /// Add `missing` replicas, prepared ones first. The prepared replicas are
+    /// claimed into `claimed` and only started once the others were created,
+    /// until then the caller can hand them back on failure. The replicas made
+    /// from scratch are recorded in `created` for the caller to remove.
+    async fn scale_up(
+        &self,
+        function: &Endpoint,
+        template: &Container,
+        all: &[u32],
+        missing: usize,
+        claimed: &mut Vec<(u32, SocketAddr)>,
+        created: &mut Vec<u32>,
+    ) -> Result<(), ScaleError> {
+        claimed.extend(self.claim_warm(function, missing));
+        let pending = self.pending_warm(function);
+        let count = missing - claimed.len();
+        self.create_replicas(function, template, all, &pending, count, created)
+            .await?;
+        // prepared replicas only need their task started
+        while let Some((replica, addr)) = claimed.pop() {
+            if let Err(e) = self.start_warm(function, replica, addr).await {
+                log::warn!("Discarding warm replica {}/{}: {}", function, replica, e);
+                if let Err(e) = self.remove_instance(function, replica).await {
+                    log::error!("Failed to remove {}/{}: {:?}", function, replica, e);
+                }
+                self.create_replicas(function, template, all, &pending, 1, created)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create `count` replicas at the lowest free indexes, the ones being
+    /// prepared for the pool are taken
+    async fn create_replicas(
+        &self,
+        function: &Endpoint,
+        template: &Container,
+        all: &[u32],
+        pending: &[u32],
+        mut count: usize,
+        created: &mut Vec<u32>,
+    ) -> Result<(), ScaleError> {
+        let mut replica = 0;
+        while count > 0 {
+            if !all.contains(&replica) && !created.contains(&replica) && !pending.contains(&replica)
+            {
+                let metadata = self
+                    .replica_like(function, template, replica)
+                    .map_err(ScaleError::Invalid)?;
+                self.create_instance(&metadata)
+                    .await
+                    .map_err(|e| ScaleError::Internal(e.to_string()))?;
+                created.push(replica);
+                count -= 1;
+            }
+            replica += 1;
+        }
+        Ok(())
+    }
+}
@@ -1,39 +1,29 @@
+use containerd_client::services::v1::Container;
 use gateway::{
     handlers::function::ResolveError,
     types::function::{Query, Status},
 };
 
 use crate::{
-    impls::{backend, cni::Endpoint, container::ContainerError},
+    impls::{
+        backend,
+        cni::Endpoint,
+        container::{container_secrets, function_replica},
+        task,
+    },
     provider::ContainerdProvider,
 };
 
-impl ContainerdProvider {
-    pub(crate) async fn _status(&self, function: Query) -> Result<Status, ResolveError> {
-        let endpoint: Endpoint = function.into();
-        let container = backend().load_container(&endpoint).await.map_err(|e| {
-            log::error!(
-                "failed to load container for function {:?} because {:?}",
-                endpoint,
-                e
-            );
-            match e {
-                ContainerError::NotFound => ResolveError::NotFound(e.to_string()),
-                ContainerError::Internal => ResolveError::Internal(e.to_string()),
-                _ => ResolveError::Invalid(e.to_string()),
-            }
-        })?;
-
-        let created_at = container.created_at.unwrap().to_string();
-        let mut replicas = 0;
-
+/// Build the status of a function from the containers of its serving replicas
+async fn function_status(function: &Endpoint, containers: &[&Container]) -> Status {
+    let mut available = 0;
+    let mut paused = 0;
+    for container in containers {
+        let endpoint = Endpoint::new(&container.id, &function.namespace);
         match backend().get_task(&endpoint).await {
-            Ok(task) => {
-                let status = task.status;
-                if status == 2 || status == 3 {
-                    replicas = 1;
-                }
-            }
+            Ok(task) if task::is_running(&task) => available += 1,
+            Ok(task) if task::is_paused(&task) => paused += 1,
+            Ok(_) => {}
             Err(e) => {
                 log::warn!(
                     "failed to get task for function {:?} because {:?}",
@@ -42,28 +32,90 @@ impl ContainerdProvider {
                 );
             }
         }
+    }
+
+    let created_at = containers
+        .iter()
+        .filter_map(|c| c.created_at.as_ref())
+        .min_by_key(|t| (t.seconds, t.nanos))
+        .map(|t| t.to_string());
+    let image = containers
+        .first()
+        .map(|c| c.image.clone())
+        .unwrap_or_default();
 
-        // 大部分字段并未实现，使用None填充
-        let status = Status {
-            name: container.id,
-            namespace: Some(endpoint.namespace),
-            image: container.image,
-            env_process: None,
-            env_vars: None,
-            constraints: None,
-            secrets: None,
-            labels: None,
-            annotations: None,
-            limits: None,
-            requests: None,
-            read_only_root_filesystem: false,
-            invocation_count: None,
-            replicas: Some(replicas),
-            available_replicas: Some(replicas),
-            created_at: Some(created_at),
-            usage: None,
-        };
+    // 大部分字段并未实现，使用None填充
+    Status {
+        name: function.service.clone(),
+        namespace: Some(function.namespace.clone()),
+        image,
+        env_process: None,
+        env_vars: None,
+        constraints: None,
+        secrets: containers
+            .first()
+            .map(|c| container_secrets(c))
+            .filter(|secrets| !secrets.is_empty()),
+        labels: None,
+        annotations: None,
+        limits: None,
+        requests: None,
+        read_only_root_filesystem: false,
+        invocation_count: None,
+        replicas: Some(containers.len() as i32),
+        available_replicas: Some(available),
+        paused_replicas: (paused > 0).then_some(paused),
+        warm_pool: None,
+        created_at,
+        image_digest: None,
+        addresses: None,
+        usage: None,
+    }
+}
+
+impl ContainerdProvider {
+    /// Status of the function, the warm pool reported apart from the replicas
+    pub(crate) async fn function_status(
+        &self,
+        function: &Endpoint,
+        containers: &[Container],
+    ) -> Status {
+        let warm = self.warm_replicas(function).unwrap_or_else(|e| {
+            log::error!("Failed to load warm replicas of {}: {:?}", function, e);
+            Vec::new()
+        });
+        let serving: Vec<&Container> = containers
+            .iter()
+            .filter(|container| {
+                function_replica(container)
+                    .is_none_or(|(_, replica)| !warm.iter().any(|(warm, _)| *warm == replica))
+            })
+            .collect();
+        let mut status = function_status(function, &serving).await;
+        if let Some(record) = self.function_record(function) {
+            record.apply(&mut status);
+        }
+        status.warm_pool = self.warm_pool(function);
+        status
+    }
+
+    pub(crate) async fn _status(&self, function: Query) -> Result<Status, ResolveError> {
+        let function: Endpoint = function.into();
+        let containers = backend()
+            .list_function_containers(&function)
+            .await
+            .map_err(|e| {
+                log::error!(
+                    "failed to load containers for function {:?} because {:?}",
+                    function,
+                    e
+                );
+                ResolveError::Internal(e.to_string())
+            })?;
+        if containers.is_empty() {
+            return Err(ResolveError::NotFound("container not found".to_string()));
+        }
 
-        Ok(status)
+        Ok(self.function_status(&function, &containers).await)
     }
 }
@@ -1,9 +1,20 @@
 use gateway::{
-    handlers::function::{DeleteError, DeployError, UpdateError},
-    types::function::{Deployment, Query},
+    handlers::{
+        function::{DeleteError, DeployError, UpdateError},
+        namespace::NamespaceError,
+    },
+    types::function::{Deployment, FunctionTimeouts, Protocol, Query},
 };
 
-use crate::provider::ContainerdProvider;
+use crate::{
+    impls::cni::Endpoint,
+    provider::{
+        ContainerdProvider,
+        function::deploy::{load_balancer, min_replicas, port_annotation},
+        idle::idle_policy,
+        warm::min_warm,
+    },
+};
 
 impl ContainerdProvider {
     pub(crate) async fn _update(&self, param: Deployment) -> Result<(), UpdateError> {
@@ -11,7 +22,42 @@ impl ContainerdProvider {
             service: param.service.clone(),
             namespace: param.namespace.clone(),
         };
-        self._delete(function).await.map_err(|e| {
+        let min = min_replicas(&param).map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        // reject bad annotations before the running function is torn down
+        FunctionTimeouts::from_annotations(param.annotations.as_ref())
+            .map_err(UpdateError::Invalid)?;
+        Protocol::from_annotations(param.annotations.as_ref()).map_err(UpdateError::Invalid)?;
+        port_annotation(&param).map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        load_balancer(&param).map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        idle_policy(&param).map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        min_warm(&param).map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        let endpoint = Endpoint::from(function.clone());
+        self.check_namespace(&endpoint.namespace)
+            .await
+            .map_err(|e| match e {
+                NamespaceError::NotFound(e) => UpdateError::Invalid(e),
+                e => UpdateError::Internal(e.to_string()),
+            })?;
+        self.secret_mounts(
+            &endpoint.namespace,
+            param.secrets.as_deref().unwrap_or_default(),
+        )
+        .map_err(|e| UpdateError::Invalid(e.to_string()))?;
+        // keep the function running at its current scale
+        let current = self
+            .serving_replicas(&endpoint)
+            .await
+            .map_err(UpdateError::Internal)?
+            .len() as u32;
+        let revision = self
+            .revision(&endpoint)
+            .map_err(|e| UpdateError::Internal(e.to_string()))?;
+        // the function stays the one created first, the record goes with the delete
+        let created_at = self
+            .function_record(&endpoint)
+            .map(|record| record.created_at);
+
+        self.remove_function(&endpoint).await.map_err(|e| {
             log::error!("failed to delete function when update because {:?}", e);
             match e {
                 DeleteError::NotFound(e) => UpdateError::NotFound(e.to_string()),
@@ -19,13 +65,19 @@ impl ContainerdProvider {
                 _ => UpdateError::Internal(e.to_string()),
             }
         })?;
-        self._deploy(param).await.map_err(|e| {
-            log::error!("failed to deploy function when update because {:?}", e);
-            match e {
-                DeployError::Invalid(e) => UpdateError::Invalid(e.to_string()),
-                DeployError::InternalError(e) => UpdateError::Internal(e.to_string()),
-            }
-        })?;
+        // the revisions carry on across the redeployment
+        if let Err(e) = self.set_revision(&endpoint, revision) {
+            log::error!("Failed to keep revision of {}: {:?}", endpoint, e);
+        }
+        self.deploy_replicas(param, current.max(min), created_at)
+            .await
+            .map_err(|e| {
+                log::error!("failed to deploy function when update because {:?}", e);
+                match e {
+                    DeployError::Invalid(e) => UpdateError::Invalid(e.to_string()),
+                    DeployError::InternalError(e) => UpdateError::Internal(e.to_string()),
+                }
+            })?;
 
         Ok(())
     }
@@ -0,0 +1,712 @@
+//! Garbage collection. Crashes and failed deletes leave behind containers,
+//! snapshots, network namespaces, address leases and records of functions or
+//! replicas that are gone. The database is read before containerd is listed:
+//! a record is only written once what it describes exists, so one without its
+//! container was left behind. Whatever has no record may still be in the making
+//! and is only collected past the grace period.
+//!
+//! What exists is listed first into [`Observed`], [`plan`] decides on it
+//! without touching anything and only then the garbage is removed.
+
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use containerd_client::services::v1::{Container, snapshots::Kind};
+use gateway::{handlers::gc::GcError, types::gc::GcReport};
+
+use crate::{
+    impls::{
+        backend,
+        cni::{Endpoint, cni_impl},
+        container::{function_replica, is_legacy_function},
+    },
+    provider::{
+        ContainerdProvider,
+        balancer::BALANCER_TREE,
+        function::instance::decode_address,
+        idle::{IDLE_TREE, PAUSED_TREE, SCALE_ZERO_TREE},
+        protocol::PROTOCOL_TREE,
+        schema::{ADDRESSES_TREE, FUNCTIONS_TREE, REVISIONS_TREE},
+        timeout::TIMEOUT_TREE,
+        warm::{WARM_POOL_TREE, WARM_TREE},
+    },
+};
+
+/// Trees keyed `<namespace>-<service>`, one record per function
+const FUNCTION_TREES: [&str; 9] = [
+    FUNCTIONS_TREE,
+    REVISIONS_TREE,
+    BALANCER_TREE,
+    TIMEOUT_TREE,
+    PROTOCOL_TREE,
+    SCALE_ZERO_TREE,
+    IDLE_TREE,
+    PAUSED_TREE,
+    WARM_POOL_TREE,
+];
+/// Trees keyed `<namespace>-<service>/<replica>`, one record per replica. The
+/// pool is read first, a replica taken from it moves on to the addresses.
+const REPLICA_TREES: [&str; 2] = [WARM_TREE, ADDRESSES_TREE];
+
+/// A record as it was read, removed only if still the same
+#[derive(Debug, Clone)]
+struct Record {
+    tree: &'static str,
+    key: String,
+    value: sled::IVec,
+}
+
+impl Record {
+    fn is_replica(&self) -> bool {
+        REPLICA_TREES.contains(&self.tree)
+    }
+
+    /// `<namespace>-<service>` of the function the record belongs to
+    fn function(&self) -> &str {
+        match self.key.rsplit_once('/') {
+            Some((function, _)) if self.is_replica() => function,
+            _ => &self.key,
+        }
+    }
+}
+
+/// The containers of the managed namespaces
+#[derive(Default)]
+struct Inventory {
+    /// Function, replica and creation time of every function container
+    replicas: Vec<(Endpoint, u32, SystemTime)>,
+    /// `<namespace>-<service>` of the functions having containers
+    functions: HashSet<String>,
+    /// `<namespace>-<service>/<replica>` of the replicas having a container
+    replica_keys: HashSet<String>,
+    /// Network namespace names of the replicas having a container
+    netns: HashSet<String>,
+    /// `(namespace, id)` of every container, its snapshot shares the id
+    containers: HashSet<(String, String)>,
+}
+
+impl Inventory {
+    async fn list(namespaces: &[String]) -> Result<Self, String> {
+        let mut inventory = Inventory::default();
+        for namespace in namespaces {
+            let containers = backend()
+                .list_container(namespace)
+                .await
+                .map_err(|e| format!("failed to list containers of {}: {:?}", namespace, e))?;
+            for container in containers {
+                inventory.add(namespace, container);
+            }
+        }
+        Ok(inventory)
+    }
+
+    fn add(&mut self, namespace: &str, container: Container) {
+        self.containers
+            .insert((namespace.to_string(), container.id.clone()));
+        if is_legacy_function(&container) {
+            // the function itself until it is adopted, replica 0 as the migration
+            // recorded it, on the network named after the function
+            let function = Endpoint::new(&container.id, namespace);
+            self.functions.insert(function.to_string());
+            self.replica_keys.insert(format!("{}/0", function));
+            self.netns.insert(function.to_string());
+            return;
+        }
+        let Some((service, replica)) = function_replica(&container) else {
+            return;
+        };
+        let function = Endpoint::new(&service, namespace);
+        self.functions.insert(function.to_string());
+        self.replica_keys
+            .insert(format!("{}/{}", function, replica));
+        self.netns.insert(function.instance(replica).to_string());
+        let created = created_at(container.created_at);
+        self.replicas.push((function, replica, created));
+    }
+}
+
+/// Everything the collection decides on, listed before anything is removed
+#[derive(Default)]
+struct Observed {
+    namespaces: Vec<String>,
+    /// Read before the containers were listed
+    records: Vec<Record>,
+    inventory: Inventory,
+    /// `(namespace, key, created)` of the writable snapshots
+    snapshots: Vec<(String, String, SystemTime)>,
+    /// Network namespaces with their modification time, `None` if they could not be listed
+    netns: Option<Vec<(String, SystemTime)>>,
+    /// Address leases with their modification time, `None` if they could not be listed
+    leases: Option<Vec<(IpAddr, SystemTime)>>,
+}
+
+/// What [`plan`] found to collect
+#[derive(Debug, Default)]
+struct Garbage {
+    /// Replicas of functions the database knows nothing of
+    containers: Vec<(Endpoint, u32)>,
+    /// `(namespace, key)` of writable snapshots without their container
+    snapshots: Vec<(String, String)>,
+    /// Network namespaces of replicas without a container
+    netns: Vec<String>,
+    /// Leases of addresses no replica is recorded with
+    leases: Vec<IpAddr>,
+    /// Records of functions and replicas without a container
+    records: Vec<Record>,
+}
+
+/// Time reported by containerd, unknown counts as just now
+fn created_at(timestamp: Option<prost_types::Timestamp>) -> SystemTime {
+    timestamp
+        .and_then(|timestamp| SystemTime::try_from(timestamp).ok())
+        .unwrap_or_else(SystemTime::now)
+}
+
+/// Whether `name` has the shape of the network namespace of a replica,
+/// `<namespace>-<service>-<replica>`, in one of `namespaces`
+fn is_replica_netns(name: &str, namespaces: &[String]) -> bool {
+    namespaces.iter().any(|namespace| {
+        name.strip_prefix(namespace.as_str())
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.rsplit_once('-'))
+            .is_some_and(|(service, replica)| !service.is_empty() && replica.parse::<u32>().is_ok())
+    })
+}
+
+/// Decide what is garbage in `observed`, nothing younger than `grace_period` is
+fn plan(observed: &Observed, now: SystemTime, grace_period: Duration) -> Garbage {
+    let expired = |time: SystemTime| {
+        now.duration_since(time)
+            .is_ok_and(|age| age >= grace_period)
+    };
+    let Observed {
+        namespaces,
+        records,
+        inventory,
+        snapshots,
+        netns,
+        leases,
+    } = observed;
+    let mut garbage = Garbage::default();
+
+    let known: HashSet<&str> = records.iter().map(Record::function).collect();
+    for (function, replica, created) in &inventory.replicas {
+        if !known.contains(function.to_string().as_str()) && expired(*created) {
+            garbage.containers.push((function.clone(), *replica));
+        }
+    }
+
+    // image layers are committed snapshots, left to containerd
+    for (namespace, key, created) in snapshots {
+        if !inventory
+            .containers
+            .contains(&(namespace.clone(), key.clone()))
+            && expired(*created)
+        {
+            garbage.snapshots.push((namespace.clone(), key.clone()));
+        }
+    }
+
+    if let Some(netns) = netns {
+        // a legacy function named like a replica, e.g. `hello-2`, is on the
+        // network named after it, never taken for replica 2 of `hello`
+        let functions: HashSet<&str> = known
+            .iter()
+            .copied()
+            .chain(inventory.functions.iter().map(String::as_str))
+            .collect();
+        for (name, modified) in netns {
+            if is_replica_netns(name, namespaces)
+                && !inventory.netns.contains(name)
+                && !functions.contains(name.as_str())
+                && expired(*modified)
+            {
+                garbage.netns.push(name.clone());
+            }
+        }
+    }
+
+    if let (Some(netns), Some(leases)) = (netns, leases) {
+        garbage.leases = orphaned_leases(records, inventory, netns, leases, expired);
+    }
+
+    for record in records {
+        let live = if record.is_replica() {
+            inventory.replica_keys.contains(&record.key)
+        } else {
+            inventory.functions.contains(&record.key)
+        };
+        if !live {
+            garbage.records.push(record.clone());
+        }
+    }
+    garbage
+}
+
+fn orphaned_leases(
+    records: &[Record],
+    inventory: &Inventory,
+    netns: &[(String, SystemTime)],
+    leases: &[(IpAddr, SystemTime)],
+    expired: impl Fn(SystemTime) -> bool,
+) -> Vec<IpAddr> {
+    let recorded: HashSet<&str> = records
+        .iter()
+        .filter(|record| record.is_replica())
+        .map(|record| record.key.as_str())
+        .collect();
+    let networks: HashSet<&str> = netns.iter().map(|(name, _)| name.as_str()).collect();
+    // the lease of a network nothing records the address of cannot be told apart
+    if let Some((function, replica, _)) =
+        inventory.replicas.iter().find(|(function, replica, _)| {
+            networks.contains(function.instance(*replica).to_string().as_str())
+                && !recorded.contains(format!("{}/{}", function, replica).as_str())
+        })
+    {
+        log::debug!(
+            "Leaving the leases alone, {} has a network but no recorded address",
+            function.instance(*replica)
+        );
+        return Vec::new();
+    }
+    let live: HashSet<IpAddr> = records
+        .iter()
+        .filter(|record| record.is_replica() && inventory.replica_keys.contains(&record.key))
+        .filter_map(|record| decode_address(&record.value))
+        .map(|addr| addr.ip())
+        .collect();
+    leases
+        .iter()
+        .filter(|(addr, modified)| !live.contains(addr) && expired(*modified))
+        .map(|(addr, _)| *addr)
+        .collect()
+}
+
+impl ContainerdProvider {
+    pub(crate) async fn _gc(&self, dry_run: bool) -> Result<GcReport, GcError> {
+        let Ok(_collecting) = self.gc_lock.try_lock() else {
+            return Err(GcError::Conflict(
+                "garbage collection is already running".to_string(),
+            ));
+        };
+        let mut report = GcReport {
+            dry_run,
+            ..Default::default()
+        };
+        let observed = self.observe(&mut report.errors).await?;
+        let garbage = plan(&observed, SystemTime::now(), self.gc_config.grace_period);
+        self.collect(garbage, &mut report).await;
+
+        let collected = report.containers.len()
+            + report.snapshots.len()
+            + report.netns.len()
+            + report.ipam_leases.len()
+            + report.records.len();
+        if collected > 0 || !report.errors.is_empty() {
+            log::info!(
+                "Garbage {}: {} containers, {} snapshots, {} netns, {} leases, {} records, {} errors",
+                if dry_run { "found" } else { "collected" },
+                report.containers.len(),
+                report.snapshots.len(),
+                report.netns.len(),
+                report.ipam_leases.len(),
+                report.records.len(),
+                report.errors.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// Collect every `interval` of the configuration, for as long as the daemon runs
+    pub fn spawn_gc_controller(self: &Arc<Self>) {
+        let provider = self.clone();
+        let interval = self.gc_config.interval;
+        tokio::spawn(async move {
+            // the startup reconciliation just ran, leave it one interval
+            let start = tokio::time::Instant::now() + interval;
+            let mut ticker = tokio::time::interval_at(start, interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = provider._gc(false).await {
+                    log::warn!("Garbage collection skipped: {}", e);
+                }
+            }
+        });
+    }
+
+    /// List what exists, the parts that could not be listed are reported in
+    /// `errors` and left alone
+    async fn observe(&self, errors: &mut Vec<String>) -> Result<Observed, GcError> {
+        let records = self
+            .gc_records()
+            .map_err(|e| GcError::Internal(format!("failed to read records: {:?}", e)))?;
+        let namespaces = self
+            ._list_namespaces()
+            .await
+            .map_err(|e| GcError::Internal(e.to_string()))?;
+        // deciding on part of the containers could collect live records
+        let inventory = Inventory::list(&namespaces)
+            .await
+            .map_err(GcError::Internal)?;
+
+        let mut snapshots = Vec::new();
+        for namespace in &namespaces {
+            match backend().list_snapshots(namespace).await {
+                Ok(listed) => snapshots.extend(
+                    listed
+                        .into_iter()
+                        .filter(|snapshot| snapshot.kind == Kind::Active as i32)
+                        .map(|snapshot| {
+                            let created = created_at(snapshot.created_at);
+                            (namespace.clone(), snapshot.name, created)
+                        }),
+                ),
+                Err(e) => errors.push(format!("snapshots of {}: {}", namespace, e)),
+            }
+        }
+        let netns = cni_impl::list_netns()
+            .map_err(|e| errors.push(format!("network namespaces: {}", e)))
+            .ok();
+        let leases = cni_impl::ipam_leases()
+            .map_err(|e| errors.push(format!("address leases: {}", e)))
+            .ok();
+        Ok(Observed {
+            namespaces,
+            records,
+            inventory,
+            snapshots,
+            netns,
+            leases,
+        })
+    }
+
+    fn gc_records(&self) -> Result<Vec<Record>, sled::Error> {
+        let mut records = Vec::new();
+        for tree in FUNCTION_TREES.into_iter().chain(REPLICA_TREES) {
+            for item in self.database.open_tree(tree)?.iter() {
+                let (key, value) = item?;
+                let Ok(key) = String::from_utf8(key.to_vec()) else {
+                    continue;
+                };
+                records.push(Record { tree, key, value });
+            }
+        }
+        Ok(records)
+    }
+
+    /// Remove the garbage, or only report it on a dry run
+    async fn collect(&self, garbage: Garbage, report: &mut GcReport) {
+        let dry_run = report.dry_run;
+        for (function, replica) in garbage.containers {
+            let endpoint = function.instance(replica);
+            let name = format!("{}/{}", endpoint.namespace, endpoint.service);
+            if !dry_run {
+                let lock = self.wake_lock(&function);
+                let _collecting = lock.lock().await;
+                if let Err(e) = self.remove_instance(&function, replica).await {
+                    report.errors.push(format!("container {}: {}", name, e));
+                    continue;
+                }
+            }
+            log::debug!("Orphaned container {}", name);
+            report.containers.push(name);
+        }
+
+        for (namespace, key) in garbage.snapshots {
+            let name = format!("{}/{}", namespace, key);
+            if !dry_run && let Err(e) = backend().remove_snapshot_key(&key, &namespace).await {
+                report.errors.push(format!("snapshot {}: {}", name, e));
+                continue;
+            }
+            log::debug!("Orphaned snapshot {}", name);
+            report.snapshots.push(name);
+        }
+
+        for name in garbage.netns {
+            if !dry_run && let Err(e) = cni_impl::delete_netns(&name) {
+                report.errors.push(format!("netns {}: {}", name, e));
+                continue;
+            }
+            log::debug!("Orphaned network namespace {}", name);
+            report.netns.push(name);
+        }
+
+        for addr in garbage.leases {
+            if !dry_run && let Err(e) = cni_impl::release_ipam_lease(addr) {
+                report.errors.push(format!("lease {}: {}", addr, e));
+                continue;
+            }
+            log::debug!("Orphaned address lease {}", addr);
+            report.ipam_leases.push(addr.to_string());
+        }
+
+        self.collect_records(garbage.records, report);
+    }
+
+    fn collect_records(&self, records: Vec<Record>, report: &mut GcReport) {
+        for record in records {
+            let name = format!("{}/{}", record.tree, record.key);
+            if !report.dry_run {
+                // rewritten since it was read, it is in use again
+                let removed = self.database.open_tree(record.tree).and_then(|tree| {
+                    tree.compare_and_swap(&record.key, Some(&record.value), None::<&[u8]>)
+                });
+                match removed {
+                    Ok(Ok(())) => self.forget_collected(&record),
+                    Ok(Err(_)) => continue,
+                    Err(e) => {
+                        report.errors.push(format!("record {}: {:?}", name, e));
+                        continue;
+                    }
+                }
+            }
+            log::debug!("Orphaned record {}", name);
+            report.records.push(name);
+        }
+    }
+
+    /// Drop what the daemon keeps in memory beside the collected record
+    fn forget_collected(&self, record: &Record) {
+        let function = record.function();
+        if !record.is_replica() {
+            self.balancers.lock().unwrap().remove(function);
+            self.last_invocations.lock().unwrap().remove(function);
+            self.warm_stats.lock().unwrap().remove(function);
+        } else if record.tree == ADDRESSES_TREE
+            && let Some(replica) = record
+                .key
+                .rsplit_once('/')
+                .and_then(|(_, replica)| replica.parse().ok())
+        {
+            self.update_record_address_by_key(function, replica, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, SocketAddr},
+    };
+
+    use super::*;
+    use crate::{
+        consts,
+        provider::{function::instance::encode_address, test::provider},
+    };
+
+    const GRACE: Duration = Duration::from_secs(600);
+
+    fn namespaces() -> Vec<String> {
+        vec!["faasrs-default".to_string(), "team-a".to_string()]
+    }
+
+    fn record(tree: &'static str, key: &str, value: &[u8]) -> Record {
+        Record {
+            tree,
+            key: key.to_string(),
+            value: sled::IVec::from(value),
+        }
+    }
+
+    fn address(last: u8) -> Vec<u8> {
+        encode_address(SocketAddr::from((Ipv4Addr::new(10, 66, 0, last), 8080)))
+    }
+
+    fn replica(service: &str, replica: u32, created: SystemTime) -> Container {
+        Container {
+            id: format!("{}-{}", service, replica),
+            snapshot_key: format!("{}-{}", service, replica),
+            labels: HashMap::from([
+                (consts::FUNCTION_LABEL.to_string(), service.to_string()),
+                (consts::REPLICA_LABEL.to_string(), replica.to_string()),
+            ]),
+            created_at: Some(created.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replica_netns() {
+        let namespaces = namespaces();
+        assert!(is_replica_netns("faasrs-default-echo-0", &namespaces));
+        assert!(is_replica_netns("team-a-hello-world-12", &namespaces));
+        assert!(!is_replica_netns("faasrs-default-echo", &namespaces));
+        assert!(!is_replica_netns("faasrs-default--0", &namespaces));
+        assert!(!is_replica_netns("cni-1234-5678", &namespaces));
+        assert!(!is_replica_netns("team-b-echo-0", &namespaces));
+
+        assert_eq!(
+            record(ADDRESSES_TREE, "faasrs-default-echo/1", &[]).function(),
+            "faasrs-default-echo"
+        );
+        assert_eq!(
+            record(FUNCTIONS_TREE, "faasrs-default-echo", &[]).function(),
+            "faasrs-default-echo"
+        );
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = SystemTime::now();
+        let old = now - GRACE * 2;
+        let fresh = now - GRACE / 2;
+
+        let mut inventory = Inventory::default();
+        // echo is deployed, replica 1 of gone was left behind, new is being deployed
+        inventory.add("faasrs-default", replica("echo", 0, old));
+        inventory.add("faasrs-default", replica("gone", 1, old));
+        inventory.add("faasrs-default", replica("new", 0, fresh));
+        // deployed before replicas, named like replica 2 of hello
+        inventory.add(
+            "faasrs-default",
+            Container {
+                id: "hello-2".to_string(),
+                snapshot_key: "hello-2".to_string(),
+                created_at: Some(old.into()),
+                ..Default::default()
+            },
+        );
+
+        let observed = Observed {
+            namespaces: namespaces(),
+            records: vec![
+                record(FUNCTIONS_TREE, "faasrs-default-echo", b"{}"),
+                record(ADDRESSES_TREE, "faasrs-default-echo/0", &address(5)),
+                // deleted while the daemon was down
+                record(FUNCTIONS_TREE, "faasrs-default-deleted", b"{}"),
+                record(ADDRESSES_TREE, "faasrs-default-deleted/0", &address(6)),
+                // migrated from the baseline for the legacy function
+                record(ADDRESSES_TREE, "faasrs-default-hello-2/0", &address(7)),
+            ],
+            inventory,
+            snapshots: vec![
+                ("faasrs-default".to_string(), "echo-0".to_string(), old),
+                ("faasrs-default".to_string(), "hello-2".to_string(), old),
+                ("faasrs-default".to_string(), "failed-0".to_string(), old),
+                (
+                    "faasrs-default".to_string(),
+                    "creating-0".to_string(),
+                    fresh,
+                ),
+            ],
+            netns: Some(vec![
+                ("faasrs-default-echo-0".to_string(), old),
+                ("faasrs-default-hello-2".to_string(), old),
+                ("faasrs-default-failed-0".to_string(), old),
+                ("faasrs-default-creating-0".to_string(), fresh),
+                ("cni-1234".to_string(), old),
+            ]),
+            leases: Some(vec![
+                (Ipv4Addr::new(10, 66, 0, 5).into(), old),
+                (Ipv4Addr::new(10, 66, 0, 6).into(), old),
+                (Ipv4Addr::new(10, 66, 0, 7).into(), old),
+                (Ipv4Addr::new(10, 66, 0, 8).into(), fresh),
+            ]),
+        };
+        let garbage = plan(&observed, now, GRACE);
+
+        // new has no record yet but is within the grace period
+        assert_eq!(
+            garbage.containers,
+            vec![(Endpoint::new("gone", "faasrs-default"), 1)]
+        );
+        assert_eq!(
+            garbage.snapshots,
+            vec![("faasrs-default".to_string(), "failed-0".to_string())]
+        );
+        // hello-2 is the network of the legacy function, not of replica 2 of hello
+        assert_eq!(garbage.netns, vec!["faasrs-default-failed-0".to_string()]);
+        let lease: IpAddr = Ipv4Addr::new(10, 66, 0, 6).into();
+        assert_eq!(garbage.leases, vec![lease]);
+        let records: Vec<_> = garbage
+            .records
+            .iter()
+            .map(|record| format!("{}/{}", record.tree, record.key))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                "functions/faasrs-default-deleted",
+                "addresses/faasrs-default-deleted/0"
+            ]
+        );
+
+        // past the grace period the function still without a record goes too
+        let garbage = plan(&observed, now + GRACE, GRACE);
+        assert_eq!(garbage.containers.len(), 2);
+        assert_eq!(garbage.snapshots.len(), 2);
+        assert_eq!(garbage.netns.len(), 2);
+        assert_eq!(garbage.leases.len(), 2);
+
+        // a replica on its network without a recorded address holds a lease
+        // nothing tells apart, the leases are left alone
+        let mut observed = observed;
+        observed
+            .netns
+            .as_mut()
+            .unwrap()
+            .push(("faasrs-default-new-0".to_string(), fresh));
+        assert!(plan(&observed, now + GRACE, GRACE).leases.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_collect_records() {
+        let provider = provider();
+        let tree = |name| provider.database.open_tree(name).unwrap();
+        tree(FUNCTIONS_TREE)
+            .insert("faasrs-default-deleted", b"{}".as_slice())
+            .unwrap();
+        tree(ADDRESSES_TREE)
+            .insert("faasrs-default-deleted/0", address(6))
+            .unwrap();
+        tree(FUNCTIONS_TREE)
+            .insert("faasrs-default-redeployed", b"{}".as_slice())
+            .unwrap();
+
+        let observed = Observed {
+            records: provider.gc_records().unwrap(),
+            ..Default::default()
+        };
+        let garbage = plan(&observed, SystemTime::now(), GRACE);
+        assert_eq!(garbage.records.len(), 3);
+
+        // a dry run only reports
+        let mut report = GcReport {
+            dry_run: true,
+            ..Default::default()
+        };
+        provider.collect_records(garbage.records.clone(), &mut report);
+        assert_eq!(report.records.len(), 3);
+        assert_eq!(tree(FUNCTIONS_TREE).len(), 2);
+        assert_eq!(tree(ADDRESSES_TREE).len(), 1);
+
+        // written again by a deploy since it was read, it stays
+        tree(FUNCTIONS_TREE)
+            .insert("faasrs-default-redeployed", b"{\"revision\":2}".as_slice())
+            .unwrap();
+        let mut report = GcReport::default();
+        provider.collect_records(garbage.records, &mut report);
+        assert_eq!(
+            report.records,
+            vec![
+                "functions/faasrs-default-deleted",
+                "addresses/faasrs-default-deleted/0"
+            ]
+        );
+        assert!(report.errors.is_empty());
+        assert_eq!(tree(FUNCTIONS_TREE).len(), 1);
+        assert!(
+            tree(FUNCTIONS_TREE)
+                .contains_key("faasrs-default-redeployed")
+                .unwrap()
+        );
+        assert!(tree(ADDRESSES_TREE).is_empty());
+    }
+}
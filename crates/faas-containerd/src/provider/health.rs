@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use gateway::types::health::HealthStatus;
+
+use crate::{
+    impls::{backend, cni},
+    provider::ContainerdProvider,
+};
+
+const CONTAINERD_HEALTH_TIMEOUT: Duration = Duration::from_secs(3);
+
+impl ContainerdProvider {
+    pub(crate) async fn _health(&self) -> HealthStatus {
+        let mut status = HealthStatus::new();
+
+        let containerd =
+            match tokio::time::timeout(CONTAINERD_HEALTH_TIMEOUT, backend().version()).await {
+                Ok(Ok(version)) => {
+                    log::trace!("containerd version: {:?}", version);
+                    Ok(())
+                }
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err("containerd did not answer the version call in time".to_string()),
+            };
+        status.add("containerd", containerd);
+        status.add("database", self.check_database().await);
+        status.add("cni", cni::cni_impl::check_cni_config());
+
+        status
+    }
+
+    /// Write a probe record into a dedicated tree and flush it to disk
+    async fn check_database(&self) -> Result<(), sled::Error> {
+        let tree = self.database.open_tree("healthz")?;
+        let now = chrono::Utc::now().timestamp().to_be_bytes();
+        tree.insert("probe", &now)?;
+        tree.flush_async().await?;
+        Ok(())
+    }
+}
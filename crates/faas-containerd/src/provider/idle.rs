@@ -0,0 +1,652 @@
+//! Scale to zero. The functions labelled `com.openfaas.scale.zero=true` have their
+//! tasks stopped once they went without invocations for long enough, their
+//! containers, snapshots and records are kept for them to come back.
+//! The first invocation afterwards starts them again and waits for them.
+//!
+//! In the `pause` mode the tasks are frozen instead, they keep their memory and
+//! network and the next invocation resumes them almost at once.
+
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use derive_more::Display;
+use gateway::{
+    handlers::function::{DeployError, ResolveError},
+    types::function::{Deployment, parse_duration},
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+use crate::{
+    consts,
+    impls::{
+        backend,
+        cni::{self, Endpoint},
+        container::{container_port, function_replica},
+        task::TaskError,
+    },
+    provider::ContainerdProvider,
+};
+
+/// Interval between connection attempts while a function starts
+const PORT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Whether one of the addresses accepts a connection before `timeout`
+async fn wait_for_ports(addresses: &[SocketAddr], timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let listening = |addr: SocketAddr| async move {
+        loop {
+            if TcpStream::connect(addr).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(PORT_POLL_INTERVAL).await;
+        }
+    };
+    let any = futures::future::select_all(addresses.iter().map(|addr| Box::pin(listening(*addr))));
+    tokio::time::timeout_at(deadline, any).await.is_ok()
+}
+
+/// Functions opting in, with their own idle timeout if they set one
+pub(crate) const SCALE_ZERO_TREE: &str = "scale_zero";
+/// Functions whose tasks were stopped, with the time they were
+pub(crate) const IDLE_TREE: &str = "idle";
+/// Functions whose tasks were paused, with the time they were
+pub(crate) const PAUSED_TREE: &str = "paused";
+
+/// What happens to the tasks of an idle function
+#[derive(Serialize, Deserialize, Debug, Display, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleMode {
+    /// Kill the tasks and release their networks, the next invocation cold starts them
+    #[default]
+    #[display("stop")]
+    Stop,
+    /// Freeze the tasks, the next invocation resumes them
+    #[display("pause")]
+    Pause,
+}
+
+impl FromStr for IdleMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(IdleMode::Stop),
+            "pause" => Ok(IdleMode::Pause),
+            _ => Err(format!("unknown idle mode {}, expected stop or pause", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdlePolicy {
+    pub service: String,
+    pub namespace: String,
+    /// `None` leaves it to the daemon configuration
+    pub idle_timeout: Option<Duration>,
+    /// `None` leaves it to the daemon configuration
+    #[serde(default)]
+    pub mode: Option<IdleMode>,
+}
+
+impl IdlePolicy {
+    fn function(&self) -> Endpoint {
+        Endpoint::new(&self.service, &self.namespace)
+    }
+}
+
+/// Read the `com.openfaas.scale.zero` labels, `None` if the function does not opt in
+pub(crate) fn idle_policy(config: &Deployment) -> Result<Option<IdlePolicy>, DeployError> {
+    let labels = config.labels.as_ref();
+    let label = |name: &str| labels.and_then(|labels| labels.get(name));
+    match label(consts::SCALE_ZERO_LABEL).map(String::as_str) {
+        None | Some("false") => return Ok(None),
+        Some("true") => {}
+        Some(value) => {
+            return Err(DeployError::Invalid(format!(
+                "label {} should be true or false, got {}",
+                consts::SCALE_ZERO_LABEL,
+                value
+            )));
+        }
+    }
+    let idle_timeout = label(consts::SCALE_ZERO_DURATION_LABEL)
+        .map(|value| {
+            parse_duration(value).map_err(|e| {
+                DeployError::Invalid(format!(
+                    "label {} = {:?}: {}",
+                    consts::SCALE_ZERO_DURATION_LABEL,
+                    value,
+                    e
+                ))
+            })
+        })
+        .transpose()?;
+    let mode = label(consts::SCALE_ZERO_MODE_LABEL)
+        .map(|value| {
+            value.parse::<IdleMode>().map_err(|e| {
+                DeployError::Invalid(format!("label {}: {}", consts::SCALE_ZERO_MODE_LABEL, e))
+            })
+        })
+        .transpose()?;
+    let function = Endpoint::from(gateway::types::function::Query {
+        service: config.service.clone(),
+        namespace: config.namespace.clone(),
+    });
+    Ok(Some(IdlePolicy {
+        service: function.service,
+        namespace: function.namespace,
+        idle_timeout,
+        mode,
+    }))
+}
+
+impl ContainerdProvider {
+    /// Called for every invocation, the function is not idle anymore
+    pub(crate) fn touch(&self, function: &Endpoint) {
+        self.last_invocations
+            .lock()
+            .unwrap()
+            .insert(function.to_string(), Instant::now());
+    }
+
+    /// Time of the last invocation, a function never invoked since the daemon
+    /// started counts from the first time it is asked
+    fn last_invocation(&self, function: &Endpoint) -> Instant {
+        *self
+            .last_invocations
+            .lock()
+            .unwrap()
+            .entry(function.to_string())
+            .or_insert_with(Instant::now)
+    }
+
+    pub(crate) fn save_idle_policy(&self, policy: Option<&IdlePolicy>) -> Result<(), sled::Error> {
+        let Some(policy) = policy else {
+            return Ok(());
+        };
+        let value = serde_json::to_vec(policy).expect("idle policy is serializable");
+        self.database
+            .open_tree(SCALE_ZERO_TREE)?
+            .insert(policy.function().to_string(), value)?;
+        Ok(())
+    }
+
+    fn idle_policies(&self) -> Result<Vec<IdlePolicy>, sled::Error> {
+        let mut policies = Vec::new();
+        for item in self.database.open_tree(SCALE_ZERO_TREE)?.iter() {
+            let (key, value) = item?;
+            match serde_json::from_slice(&value) {
+                Ok(policy) => policies.push(policy),
+                Err(e) => log::warn!(
+                    "Ignoring invalid idle policy of {}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ),
+            }
+        }
+        Ok(policies)
+    }
+
+    fn has_marker(&self, tree: &str, function: &Endpoint) -> bool {
+        self.database
+            .open_tree(tree)
+            .and_then(|tree| tree.contains_key(function.to_string()))
+            .unwrap_or_else(|e| {
+                log::error!("Failed to load {} state of {}: {:?}", tree, function, e);
+                false
+            })
+    }
+
+    fn set_marker(&self, tree: &str, function: &Endpoint) -> Result<(), String> {
+        self.database
+            .open_tree(tree)
+            .and_then(|tree| tree.insert(function.to_string(), Utc::now().to_rfc3339().as_bytes()))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn clear_marker(&self, tree: &str, function: &Endpoint) {
+        if let Err(e) = self
+            .database
+            .open_tree(tree)
+            .and_then(|tree| tree.remove(function.to_string()))
+        {
+            log::error!("Failed to remove {} record of {}: {:?}", tree, function, e);
+        }
+    }
+
+    /// Whether the tasks of the function were stopped for being idle
+    pub(crate) fn scaled_to_zero(&self, function: &Endpoint) -> bool {
+        self.has_marker(IDLE_TREE, function)
+    }
+
+    /// Whether the tasks of the function were paused for being idle
+    pub(crate) fn paused(&self, function: &Endpoint) -> bool {
+        self.has_marker(PAUSED_TREE, function)
+    }
+
+    /// The tasks of the function were thawed by someone else than [`Self::resume_instances`]
+    pub(crate) fn clear_paused(&self, function: &Endpoint) {
+        self.clear_marker(PAUSED_TREE, function);
+    }
+
+    /// Serializes pausing and waking up the function
+    pub(crate) fn wake_lock(&self, function: &Endpoint) -> Arc<tokio::sync::Mutex<()>> {
+        self.wake_locks
+            .lock()
+            .unwrap()
+            .entry(function.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Drop everything scale to zero knows about the function
+    pub(crate) fn forget_idle(&self, function: &Endpoint) {
+        for name in [SCALE_ZERO_TREE, IDLE_TREE, PAUSED_TREE] {
+            if let Err(e) = self
+                .database
+                .open_tree(name)
+                .and_then(|tree| tree.remove(function.to_string()))
+            {
+                log::error!("Failed to remove {} record of {}: {:?}", name, function, e);
+            }
+        }
+        self.last_invocations
+            .lock()
+            .unwrap()
+            .remove(&function.to_string());
+        self.wake_locks
+            .lock()
+            .unwrap()
+            .remove(&function.to_string());
+    }
+
+    /// Bring back the tasks of a function scaled to zero or whose tasks went
+    /// away, then hold the caller until one replica accepts connections.
+    /// Invocations arriving meanwhile wait for the same cold start.
+    pub(crate) async fn cold_start(
+        &self,
+        function: &Endpoint,
+    ) -> Result<Vec<SocketAddr>, ResolveError> {
+        self.wake_once(
+            function,
+            || self.healthy_addresses(function),
+            || self.restart_replicas(function),
+        )
+        .await
+    }
+
+    /// Run `start` under the wake lock, unless the function was brought back by
+    /// another caller while this one waited, `running` tells where it serves then
+    async fn wake_once<R, S, F>(
+        &self,
+        function: &Endpoint,
+        running: R,
+        start: S,
+    ) -> Result<Vec<SocketAddr>, ResolveError>
+    where
+        R: FnOnce() -> Result<Vec<SocketAddr>, ResolveError>,
+        S: FnOnce() -> F,
+        F: Future<Output = Result<Vec<SocketAddr>, ResolveError>>,
+    {
+        let lock = self.wake_lock(function);
+        let _started = lock.lock().await;
+
+        // another invocation may have done it while this one waited
+        if !self.scaled_to_zero(function) {
+            let running = running()?;
+            if !running.is_empty() {
+                return Ok(running);
+            }
+        }
+        start().await
+    }
+
+    /// Start the stopped replicas again, the wake lock is held by the caller
+    async fn restart_replicas(&self, function: &Endpoint) -> Result<Vec<SocketAddr>, ResolveError> {
+        let containers = backend()
+            .list_function_containers(function)
+            .await
+            .map_err(|e| ResolveError::Internal(e.to_string()))?;
+        if containers.is_empty() {
+            return Err(ResolveError::NotFound("container not found".to_string()));
+        }
+
+        let warm = self
+            .warm_replicas(function)
+            .map_err(|e| ResolveError::Internal(e.to_string()))?;
+
+        let begin = Instant::now();
+        log::info!("Cold starting {}", function);
+        let mut addresses = Vec::with_capacity(containers.len());
+        for container in &containers {
+            let Some((_, replica)) = function_replica(container) else {
+                continue;
+            };
+            if warm.iter().any(|(warm, _)| *warm == replica) {
+                continue;
+            }
+            // a prepared replica takes the place of the stopped one, only its task starts
+            if let Some((_, addr)) = self.take_warm(function).await {
+                self.discard_instance(function, replica);
+                addresses.push(addr);
+                continue;
+            }
+            match self
+                .restart_instance(function, replica, container_port(container))
+                .await
+            {
+                Ok(addr) => addresses.push(addr),
+                Err(e) => log::error!("Failed to restart {}/{}: {}", function, replica, e),
+            }
+        }
+        if addresses.is_empty() {
+            return Err(ResolveError::Internal(format!(
+                "failed to cold start {}",
+                function
+            )));
+        }
+        self.clear_marker(IDLE_TREE, function);
+
+        let timeout = self.scale_to_zero.cold_start_timeout;
+        if !wait_for_ports(&addresses, timeout).await {
+            log::error!("{} did not listen within {:?}", function, timeout);
+            return Err(ResolveError::Internal(format!(
+                "cold start of {} timed out",
+                function
+            )));
+        }
+        log::info!("{} cold started in {:?}", function, begin.elapsed());
+        Ok(addresses)
+    }
+
+    /// Look for idle functions every `interval`, for as long as the daemon runs
+    pub fn spawn_idle_controller(self: &Arc<Self>) {
+        let provider = self.clone();
+        tokio::spawn(async move {
+            let config = &provider.scale_to_zero;
+            log::info!(
+                "Scale to zero enabled, idle timeout {:?}, checked every {:?}, {} by default",
+                config.idle_timeout,
+                config.interval,
+                config.mode
+            );
+            let mut ticker = tokio::time::interval(config.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                provider.scale_idle_to_zero().await;
+            }
+        });
+    }
+
+    async fn scale_idle_to_zero(&self) {
+        let policies = match self.idle_policies() {
+            Ok(policies) => policies,
+            Err(e) => {
+                log::error!("Failed to load idle policies: {:?}", e);
+                return;
+            }
+        };
+        for policy in policies {
+            let function = policy.function();
+            let idle_timeout = policy
+                .idle_timeout
+                .unwrap_or(self.scale_to_zero.idle_timeout);
+            // checked again under the wake lock, this only skips the busy functions cheaply
+            if self.idle_for(&function, idle_timeout).is_none() {
+                continue;
+            }
+            let mode = policy.mode.unwrap_or(self.scale_to_zero.mode);
+            let result = match mode {
+                IdleMode::Stop => self.stop_instances(&function, idle_timeout).await,
+                IdleMode::Pause => self.pause_instances(&function, idle_timeout).await,
+            };
+            if let Err(e) = result {
+                log::error!("Failed to scale {} to zero: {}", function, e);
+            }
+        }
+    }
+
+    /// How long the function went without invocations, `None` if it is already
+    /// stopped or paused, in flight or invoked within `idle_timeout`
+    fn idle_for(&self, function: &Endpoint, idle_timeout: Duration) -> Option<Duration> {
+        if self.scaled_to_zero(function) || self.paused(function) {
+            return None;
+        }
+        let idle = self.last_invocation(function).elapsed();
+        if idle < idle_timeout {
+            return None;
+        }
+        if self.balancer(function).outstanding() > 0 {
+            log::debug!("{} has invocations in flight, not idle", function);
+            return None;
+        }
+        Some(idle)
+    }
+
+    /// Stop the tasks of an idle function, unless it was invoked since the
+    /// controller looked at it
+    pub(crate) async fn stop_instances(
+        &self,
+        function: &Endpoint,
+        idle_timeout: Duration,
+    ) -> Result<(), String> {
+        let lock = self.wake_lock(function);
+        let _stopping = lock.lock().await;
+        let Some(idle) = self.idle_for(function, idle_timeout) else {
+            return Ok(());
+        };
+        log::info!("Scaling {} to zero (stop), idle for {:?}", function, idle);
+        self.stop_replicas(function).await
+    }
+
+    /// Stop the tasks of every replica and release their networks, the
+    /// containers and snapshots stay. The wake lock is held by the caller.
+    pub(crate) async fn stop_replicas(&self, function: &Endpoint) -> Result<(), String> {
+        let replicas = self.serving_replicas(function).await?;
+        let mut errors = Vec::new();
+        for replica in replicas {
+            // stop routing to the replica before its task goes away
+            self.forget_instance_address(function, replica);
+            let endpoint = function.instance(replica);
+            match backend().kill_task_with_timeout(&endpoint).await {
+                Ok(()) | Err(TaskError::NotFound) => {}
+                Err(e) => errors.push(format!("{}: {:?}", endpoint, e)),
+            }
+            if cni::cni_impl::network_exists(&endpoint)
+                && let Err(e) = cni::cni_impl::delete_cni_network(endpoint.clone())
+            {
+                errors.push(format!("{}: {}", endpoint, e));
+            }
+        }
+        // a replica still running keeps serving, the next tick stops it again
+        if !errors.is_empty() {
+            return Err(errors.join(", "));
+        }
+        self.set_marker(IDLE_TREE, function)
+    }
+
+    /// Freeze the tasks of an idle function, their networks and addresses stay.
+    /// Nothing happens if it was invoked since the controller looked at it.
+    pub(crate) async fn pause_instances(
+        &self,
+        function: &Endpoint,
+        idle_timeout: Duration,
+    ) -> Result<(), String> {
+        let lock = self.wake_lock(function);
+        let _pausing = lock.lock().await;
+        let Some(idle) = self.idle_for(function, idle_timeout) else {
+            return Ok(());
+        };
+        log::info!("Scaling {} to zero (pause), idle for {:?}", function, idle);
+        // marked first, an invocation arriving meanwhile waits to resume it
+        self.set_marker(PAUSED_TREE, function)?;
+        let mut paused = 0;
+        let mut errors = Vec::new();
+        for replica in self.serving_replicas(function).await? {
+            let endpoint = function.instance(replica);
+            match backend().pause_task(&endpoint).await {
+                Ok(()) => paused += 1,
+                Err(e) => errors.push(format!("{}: {:?}", endpoint, e)),
+            }
+        }
+        if paused == 0 {
+            self.clear_marker(PAUSED_TREE, function);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    /// Thaw the tasks of a paused function before an invocation reaches it
+    pub(crate) async fn resume_instances(&self, function: &Endpoint) -> Result<(), ResolveError> {
+        let lock = self.wake_lock(function);
+        let _resuming = lock.lock().await;
+        if !self.paused(function) {
+            return Ok(());
+        }
+        let begin = Instant::now();
+        let replicas = self
+            .serving_replicas(function)
+            .await
+            .map_err(ResolveError::Internal)?;
+        for replica in replicas {
+            let endpoint = function.instance(replica);
+            match backend().resume_task(&endpoint).await {
+                Ok(()) => {}
+                // gone while paused, left to the cold start
+                Err(TaskError::NotFound) => self.forget_instance_address(function, replica),
+                Err(e) => log::warn!("Failed to resume {}: {:?}", endpoint, e),
+            }
+        }
+        self.clear_marker(PAUSED_TREE, function);
+        log::info!("{} resumed in {:?}", function, begin.elapsed());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use super::{IDLE_TREE, IdleMode, idle_policy, wait_for_ports};
+    use crate::{
+        consts,
+        impls::cni::Endpoint,
+        provider::test::{deployment, provider},
+    };
+
+    #[tokio::test]
+    async fn test_concurrent_invocations_share_cold_start() {
+        let provider = provider();
+        let function = Endpoint::new("echo", "faasrs-default");
+        provider.set_marker(IDLE_TREE, &function).unwrap();
+
+        let addr = SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 8080));
+        let serving = Mutex::new(Vec::new());
+        let starts = AtomicUsize::new(0);
+        let invoke = || async {
+            provider
+                .wake_once(
+                    &function,
+                    || Ok(serving.lock().unwrap().clone()),
+                    || async {
+                        starts.fetch_add(1, Ordering::SeqCst);
+                        // the others pile up on the wake lock meanwhile
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        serving.lock().unwrap().push(addr);
+                        provider.clear_marker(IDLE_TREE, &function);
+                        Ok(vec![addr])
+                    },
+                )
+                .await
+        };
+        let results = futures::future::join_all((0..8).map(|_| invoke())).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        for result in results {
+            assert_eq!(result.unwrap(), vec![addr]);
+        }
+        assert!(!provider.scaled_to_zero(&function));
+
+        // stopped again, the next invocation starts it again
+        provider.set_marker(IDLE_TREE, &function).unwrap();
+        assert_eq!(invoke().await.unwrap(), vec![addr]);
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        assert!(wait_for_ports(&[closed, open], Duration::from_secs(1)).await);
+        assert!(!wait_for_ports(&[closed], Duration::from_millis(200)).await);
+    }
+
+    #[test]
+    fn test_idle_policy() {
+        assert_eq!(idle_policy(&deployment(&[])).unwrap(), None);
+        assert_eq!(
+            idle_policy(&deployment(&[(consts::SCALE_ZERO_LABEL, "false")])).unwrap(),
+            None
+        );
+
+        let policy = idle_policy(&deployment(&[(consts::SCALE_ZERO_LABEL, "true")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy.service, "echo");
+        assert_eq!(policy.namespace, consts::DEFAULT_FUNCTION_NAMESPACE);
+        assert_eq!(policy.idle_timeout, None);
+
+        let policy = idle_policy(&deployment(&[
+            (consts::SCALE_ZERO_LABEL, "true"),
+            (consts::SCALE_ZERO_DURATION_LABEL, "5m"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(policy.idle_timeout, Some(Duration::from_secs(300)));
+        assert_eq!(policy.mode, None);
+
+        let policy = idle_policy(&deployment(&[
+            (consts::SCALE_ZERO_LABEL, "true"),
+            (consts::SCALE_ZERO_MODE_LABEL, "pause"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(policy.mode, Some(IdleMode::Pause));
+        assert!(
+            idle_policy(&deployment(&[
+                (consts::SCALE_ZERO_LABEL, "true"),
+                (consts::SCALE_ZERO_MODE_LABEL, "freeze"),
+            ]))
+            .is_err()
+        );
+
+        assert!(idle_policy(&deployment(&[(consts::SCALE_ZERO_LABEL, "yes")])).is_err());
+        assert!(
+            idle_policy(&deployment(&[
+                (consts::SCALE_ZERO_LABEL, "true"),
+                (consts::SCALE_ZERO_DURATION_LABEL, "soon"),
+            ]))
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use gateway::{
+    handlers::info::InfoError,
+    types::info::{ProviderInfo, VersionInfo},
+};
+
+use crate::{
+    consts,
+    impls::{backend, cni},
+    provider::ContainerdProvider,
+};
+
+impl ContainerdProvider {
+    pub(crate) async fn _info(&self) -> Result<ProviderInfo, InfoError> {
+        let containerd = backend().version().await.map_err(|e| {
+            log::error!("failed to get containerd version because {:?}", e);
+            InfoError::Internal(e.to_string())
+        })?;
+
+        let details = BTreeMap::from([
+            ("containerd_version".to_string(), containerd.version),
+            ("containerd_revision".to_string(), containerd.revision),
+            ("snapshotter".to_string(), backend().snapshotter.clone()),
+            (
+                "cni_network".to_string(),
+                cni::cni_impl::DEFAULT_NETWORK_NAME.to_string(),
+            ),
+        ]);
+
+        Ok(ProviderInfo {
+            name: consts::PROVIDER_NAME.to_string(),
+            version: VersionInfo {
+                commit_message: consts::GIT_COMMIT_MESSAGE.to_string(),
+                sha: consts::GIT_SHA.to_string(),
+                release: consts::PROVIDER_VERSION.to_string(),
+            },
+            orchestration: consts::ORCHESTRATION.to_string(),
+            details,
+        })
+    }
+}
@@ -0,0 +1,783 @@
+//! Function output. The shim appends the stdout and stderr of every instance to
+//! `<instance>.log` as they come, without timestamps. The collector moves the
+//! complete lines from there to `<instance>.stamped` every
+//! [`consts::LOG_COLLECT_INTERVAL`], each prefixed with the time it was collected,
+//! so `since` applies line by line. The stamped files are rotated to
+//! `<instance>.stamped.1` and so on at `max_size`, the raw ones are truncated
+//! once collected past it.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::OsString,
+    io::{BufRead, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{StreamExt, stream::BoxStream};
+use gateway::{
+    handlers::logs::LogError,
+    types::{function::Query, logs::LogMessage},
+};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt};
+
+use crate::{config::LogsConfig, consts, impls::cni::Endpoint, provider::ContainerdProvider};
+
+/// How far the collector got in every raw log file, keyed by its path
+const LOG_OFFSETS_TREE: &str = "log_offsets";
+const RAW_SUFFIX: &str = ".log";
+const STAMPED_SUFFIX: &str = ".stamped";
+/// Longer output without a line break is split into lines of this size
+const MAX_LINE: usize = 64 * 1024;
+
+fn message(
+    function: &Endpoint,
+    instance: &str,
+    timestamp: DateTime<Utc>,
+    text: &str,
+) -> LogMessage {
+    LogMessage {
+        name: function.service.clone(),
+        namespace: function.namespace.clone(),
+        instance: instance.to_string(),
+        timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        text: text.to_string(),
+    }
+}
+
+/// Split the complete lines out of `buf`, leaving the unterminated rest in it
+fn take_lines(buf: &mut Vec<u8>) -> Vec<String> {
+    let Some(end) = buf.iter().rposition(|b| *b == b'\n') else {
+        return Vec::new();
+    };
+    let rest = buf.split_off(end + 1);
+    let lines = String::from_utf8_lossy(&buf[..end])
+        .split('\n')
+        .map(str::to_string)
+        .collect();
+    *buf = rest;
+    lines
+}
+
+/// `<time> <text>` as written by the collector
+fn parse_stamped(line: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (timestamp, text) = line.split_once(' ')?;
+    let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some((timestamp.with_timezone(&Utc), text))
+}
+
+/// `<path>.<n>`, the `n`-th most recent rotation of the file
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Shift the rotations of the file by one, the oldest beyond `max_files` is dropped
+fn rotate(path: &Path, max_files: u32) -> std::io::Result<()> {
+    let result = if max_files == 0 {
+        std::fs::remove_file(path)
+    } else {
+        for n in (1..max_files).rev() {
+            match std::fs::rename(rotated(path, n), rotated(path, n + 1)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        std::fs::rename(path, rotated(path, 1))
+    };
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Stamp the complete lines of `raw` after `offset` with `now` and append them to
+/// the stamped file, returns the offset to start from next time
+fn collect_file(
+    raw: &Path,
+    stamped: &Path,
+    mut offset: u64,
+    now: DateTime<Utc>,
+    limits: &LogsConfig,
+) -> std::io::Result<u64> {
+    let mut file = std::fs::File::open(raw)?;
+    let len = file.metadata()?.len();
+    if len < offset {
+        // truncated behind our back
+        offset = 0;
+    }
+    if len == offset {
+        return Ok(offset);
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = std::io::BufReader::new(file.take(len - offset));
+
+    let stamp = now.to_rfc3339_opts(SecondsFormat::Nanos, true);
+    let open = || {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(stamped)
+    };
+    let mut out = open()?;
+    let mut size = out.metadata()?.len();
+    let mut line = Vec::with_capacity(stamp.len() + 1 + MAX_LINE);
+    loop {
+        line.clear();
+        line.extend_from_slice(stamp.as_bytes());
+        line.push(b' ');
+        let read = reader
+            .by_ref()
+            .take(MAX_LINE as u64)
+            .read_until(b'\n', &mut line)?;
+        if read == 0 || (line.last() != Some(&b'\n') && read < MAX_LINE) {
+            // the rest of the line is still to come
+            break;
+        }
+        offset += read as u64;
+        if line.last() != Some(&b'\n') {
+            line.push(b'\n');
+        }
+        if size > 0 && size + line.len() as u64 > limits.max_size {
+            drop(out);
+            rotate(stamped, limits.max_files)?;
+            out = open()?;
+            size = 0;
+        }
+        out.write_all(&line)?;
+        size += line.len() as u64;
+    }
+
+    // like logrotate's copytruncate, output written between the check and the
+    // truncation is lost
+    if offset >= limits.max_size && std::fs::metadata(raw)?.len() == offset {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(raw)?
+            .set_len(0)?;
+        offset = 0;
+    }
+    Ok(offset)
+}
+
+/// `<namespace>/<function>/<instance>.log` under the logs directory
+fn raw_log_files(logs_dir: &Path) -> Vec<PathBuf> {
+    let read_dir = |dir: &Path| {
+        std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>()
+    };
+    let mut files = Vec::new();
+    for namespace in read_dir(logs_dir).into_iter().filter(|p| p.is_dir()) {
+        for function in read_dir(&namespace).into_iter().filter(|p| p.is_dir()) {
+            files.extend(read_dir(&function).into_iter().filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.ends_with(RAW_SUFFIX))
+            }));
+        }
+    }
+    files
+}
+
+/// Stamped file of the instance the raw file `<instance>.log` belongs to
+fn stamped_file(raw: &Path) -> PathBuf {
+    let name = raw.file_name().unwrap_or_default().to_string_lossy();
+    let instance = name.strip_suffix(RAW_SUFFIX).unwrap_or(&name);
+    raw.with_file_name(format!("{}{}", instance, STAMPED_SUFFIX))
+}
+
+/// Stamped files of one instance
+struct LogSource {
+    instance: String,
+    /// Oldest first
+    rotated: Vec<PathBuf>,
+    current: Option<PathBuf>,
+    /// Length of the current file when the logs were asked for, what comes
+    /// after it is left to the follower
+    current_len: u64,
+}
+
+impl LogSource {
+    fn lines(&self) -> BoxStream<'static, (DateTime<Utc>, String)> {
+        let rotated = self.rotated.clone().into_iter().map(|path| (path, None));
+        let current = self
+            .current
+            .clone()
+            .map(|path| (path, Some(self.current_len)));
+        futures::stream::iter(rotated.chain(current))
+            .flat_map(|(path, limit)| read_stamped(path, limit))
+            .boxed()
+    }
+}
+
+/// Lines of a stamped file, up to `limit` bytes of it
+fn read_stamped(path: PathBuf, limit: Option<u64>) -> BoxStream<'static, (DateTime<Utc>, String)> {
+    futures::stream::once(async move {
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => Some(file.take(limit.unwrap_or(u64::MAX))),
+            Err(e) => {
+                // rotated meanwhile
+                log::debug!("Skipping {:?}: {}", path, e);
+                None
+            }
+        }
+    })
+    .filter_map(futures::future::ready)
+    .flat_map(|file| {
+        futures::stream::unfold(tokio::io::BufReader::new(file), |mut reader| async move {
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line).await {
+                    Ok(0) | Err(_) => return None,
+                    Ok(_) => {}
+                }
+                let line = String::from_utf8_lossy(&line);
+                if let Some((timestamp, text)) = parse_stamped(line.trim_end_matches('\n')) {
+                    return Some(((timestamp, text.to_string()), reader));
+                }
+            }
+        })
+    })
+    .boxed()
+}
+
+fn internal(e: std::io::Error) -> LogError {
+    log::error!("Failed to read function logs: {}", e);
+    LogError::Internal(e.to_string())
+}
+
+/// The names become directories, so they must not escape the logs directory
+fn check_name(kind: &str, name: &str) -> Result<(), LogError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(LogError::Invalid(format!(
+            "invalid {} name {:?}",
+            kind, name
+        )));
+    }
+    Ok(())
+}
+
+impl ContainerdProvider {
+    /// `<data dir>/logs/<namespace>/<function>`
+    fn function_log_dir(&self, function: &Endpoint) -> Result<PathBuf, LogError> {
+        check_name("namespace", &function.namespace)?;
+        check_name("function", &function.service)?;
+        Ok(self
+            .logs_dir
+            .join(&function.namespace)
+            .join(&function.service))
+    }
+
+    /// File the stdout and stderr of a container instance are appended to,
+    /// `<data dir>/logs/<namespace>/<function>/<instance>.log`
+    pub(crate) fn instance_log_file(
+        &self,
+        function: &Endpoint,
+        instance: &Endpoint,
+    ) -> Result<PathBuf, LogError> {
+        Ok(self
+            .function_log_dir(function)?
+            .join(format!("{}{}", instance.service, RAW_SUFFIX)))
+    }
+
+    /// Remove the output of a deleted function, its followers end with it
+    pub(crate) async fn forget_logs(&self, function: &Endpoint) {
+        let dir = match self.function_log_dir(function) {
+            Ok(dir) => dir,
+            Err(e) => return log::warn!("Not removing logs of {}: {}", function, e),
+        };
+        match tokio::fs::remove_dir_all(&dir).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => log::error!("Failed to remove {:?}: {}", dir, e),
+        }
+    }
+
+    /// Collect the output of every instance each [`consts::LOG_COLLECT_INTERVAL`],
+    /// for as long as the daemon runs
+    pub fn spawn_log_collector(self: &Arc<Self>) {
+        let provider = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(consts::LOG_COLLECT_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                let provider = provider.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || provider.collect_logs()).await {
+                    log::error!("Log collector failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Move what the instances wrote since the last time to their stamped files
+    pub(crate) fn collect_logs(&self) {
+        let offsets = match self.database.open_tree(LOG_OFFSETS_TREE) {
+            Ok(offsets) => offsets,
+            Err(e) => {
+                log::error!("Failed to load log offsets: {:?}", e);
+                return;
+            }
+        };
+        let now = Utc::now();
+        let mut seen = HashSet::new();
+        for raw in raw_log_files(&self.logs_dir) {
+            let key = raw.to_string_lossy().to_string();
+            let offset = offsets
+                .get(&key)
+                .ok()
+                .flatten()
+                .and_then(|value| <[u8; 8]>::try_from(value.as_ref()).ok())
+                .map_or(0, u64::from_be_bytes);
+            match collect_file(&raw, &stamped_file(&raw), offset, now, &self.logs_config) {
+                Ok(next) if next != offset => {
+                    if let Err(e) = offsets.insert(key.as_str(), &next.to_be_bytes()) {
+                        log::error!("Failed to save log offset of {:?}: {:?}", raw, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to collect logs of {:?}: {}", raw, e),
+            }
+            seen.insert(key);
+        }
+        // the files went with their function or namespace
+        for key in offsets.iter().keys().filter_map(|key| key.ok()) {
+            if !seen.contains(String::from_utf8_lossy(&key).as_ref())
+                && let Err(e) = offsets.remove(&key)
+            {
+                log::error!("Failed to remove log offset: {:?}", e);
+            }
+        }
+    }
+
+    async fn log_sources(&self, function: &Endpoint) -> Result<Vec<LogSource>, LogError> {
+        let dir = self.function_log_dir(function)?;
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(LogError::NotFound(format!(
+                    "no logs for function {}",
+                    function
+                )));
+            }
+            Err(e) => return Err(internal(e)),
+        };
+        // instance -> rotation number, 0 for the current file
+        let mut files = HashMap::<String, Vec<(u32, PathBuf)>>::new();
+        while let Some(entry) = entries.next_entry().await.map_err(internal)? {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let (instance, n) = match name.rsplit_once(STAMPED_SUFFIX) {
+                Some((instance, "")) => (instance, 0),
+                Some((instance, n)) => match n.strip_prefix('.').and_then(|n| n.parse().ok()) {
+                    Some(n) => (instance, n),
+                    None => continue,
+                },
+                None => continue,
+            };
+            files
+                .entry(instance.to_string())
+                .or_default()
+                .push((n, path.clone()));
+        }
+
+        let mut sources = Vec::new();
+        for (instance, mut files) in files {
+            files.sort_by_key(|(n, _)| std::cmp::Reverse(*n));
+            let current = match files.last() {
+                Some((0, _)) => files.pop().map(|(_, path)| path),
+                _ => None,
+            };
+            let current_len = match &current {
+                Some(path) => tokio::fs::metadata(path).await.map_or(0, |m| m.len()),
+                None => 0,
+            };
+            sources.push(LogSource {
+                instance,
+                rotated: files.into_iter().map(|(_, path)| path).collect(),
+                current,
+                current_len,
+            });
+        }
+        sources.sort_by(|a, b| a.instance.cmp(&b.instance));
+        Ok(sources)
+    }
+
+    /// Without `tail` the instances are streamed one after the other as their
+    /// files are read, with it the last lines of all of them are ordered by time
+    pub(crate) async fn _logs(
+        &self,
+        function: Query,
+        since: Option<DateTime<Utc>>,
+        tail: Option<usize>,
+        follow: bool,
+    ) -> Result<BoxStream<'static, LogMessage>, LogError> {
+        let function = Endpoint::from(function);
+        let sources = self.log_sources(&function).await?;
+        let recent = move |timestamp: &DateTime<Utc>| since.is_none_or(|since| *timestamp >= since);
+        let offsets: HashMap<String, u64> = sources
+            .iter()
+            .map(|source| (source.instance.clone(), source.current_len))
+            .collect();
+
+        let source_lines = |source: &LogSource| {
+            source
+                .lines()
+                .filter(move |(timestamp, _)| futures::future::ready(recent(timestamp)))
+        };
+
+        let history = match tail {
+            None => {
+                let lines: Vec<_> = sources
+                    .iter()
+                    .map(|source| {
+                        let function = function.clone();
+                        let instance = source.instance.clone();
+                        source_lines(source)
+                            .map(move |(timestamp, text)| {
+                                message(&function, &instance, timestamp, &text)
+                            })
+                            .boxed()
+                    })
+                    .collect();
+                futures::stream::iter(lines).flatten().boxed()
+            }
+            Some(tail) => {
+                let mut lines = Vec::new();
+                for source in &sources {
+                    let mut last = VecDeque::new();
+                    let mut stream = source_lines(source).boxed();
+                    while let Some(line) = stream.next().await {
+                        last.push_back(line);
+                        if last.len() > tail {
+                            last.pop_front();
+                        }
+                    }
+                    lines.extend(
+                        last.into_iter()
+                            .map(|(timestamp, text)| (timestamp, source.instance.clone(), text)),
+                    );
+                }
+                lines.sort_by_key(|(timestamp, _, _)| *timestamp);
+                let skip = lines.len().saturating_sub(tail);
+                let messages: Vec<_> = lines
+                    .into_iter()
+                    .skip(skip)
+                    .map(|(timestamp, instance, text)| {
+                        message(&function, &instance, timestamp, &text)
+                    })
+                    .collect();
+                futures::stream::iter(messages).boxed()
+            }
+        };
+
+        if follow {
+            let dir = self.function_log_dir(&function)?;
+            Ok(history.chain(follow_dir(function, dir, offsets)).boxed())
+        } else {
+            Ok(history)
+        }
+    }
+}
+
+/// Poll the stamped files of the function for lines appended after `offsets`,
+/// instances started meanwhile are followed from their first line. Ends when
+/// the directory is removed with the function or its namespace.
+fn follow_dir(
+    function: Endpoint,
+    dir: PathBuf,
+    offsets: HashMap<String, u64>,
+) -> BoxStream<'static, LogMessage> {
+    struct Followed {
+        offset: u64,
+        buf: Vec<u8>,
+    }
+
+    struct State {
+        function: Endpoint,
+        dir: PathBuf,
+        followed: HashMap<String, Followed>,
+        pending: VecDeque<LogMessage>,
+    }
+
+    async fn read_range(path: &Path, from: u64, buf: &mut Vec<u8>) -> std::io::Result<u64> {
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(SeekFrom::Start(from)).await?;
+        Ok(file.read_to_end(buf).await? as u64)
+    }
+
+    async fn read_new(path: &Path, followed: &mut Followed) -> std::io::Result<()> {
+        let len = tokio::fs::metadata(path).await?.len();
+        if len < followed.offset {
+            // rotated, finish the previous file first
+            let _ = read_range(&rotated(path, 1), followed.offset, &mut followed.buf).await;
+            followed.offset = 0;
+        }
+        followed.offset += read_range(path, followed.offset, &mut followed.buf).await?;
+        Ok(())
+    }
+
+    async fn poll(state: &mut State) -> std::io::Result<()> {
+        let mut entries = tokio::fs::read_dir(&state.dir).await?;
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if let Some(instance) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(STAMPED_SUFFIX))
+            {
+                files.push((instance.to_string(), path.clone()));
+            }
+        }
+        files.sort();
+        for (instance, path) in files {
+            let followed = state.followed.entry(instance.clone()).or_insert(Followed {
+                offset: 0,
+                buf: Vec::new(),
+            });
+            if let Err(e) = read_new(&path, followed).await {
+                log::debug!("Failed to follow {:?}: {}", path, e);
+                continue;
+            }
+            for line in take_lines(&mut followed.buf) {
+                if let Some((timestamp, text)) = parse_stamped(&line) {
+                    state
+                        .pending
+                        .push_back(message(&state.function, &instance, timestamp, text));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let state = State {
+        function,
+        dir,
+        followed: offsets
+            .into_iter()
+            .map(|(instance, offset)| {
+                (
+                    instance,
+                    Followed {
+                        offset,
+                        buf: Vec::new(),
+                    },
+                )
+            })
+            .collect(),
+        pending: VecDeque::new(),
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(message) = state.pending.pop_front() {
+                return Some((message, state));
+            }
+            tokio::time::sleep(consts::LOG_COLLECT_INTERVAL).await;
+            if let Err(e) = poll(&mut state).await {
+                log::debug!("Stop following {:?}: {}", state.dir, e);
+                return None;
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::test::provider;
+
+    fn limits(max_size: u64, max_files: u32) -> LogsConfig {
+        LogsConfig {
+            max_size,
+            max_files,
+        }
+    }
+
+    #[test]
+    fn test_take_lines() {
+        let mut buf = b"first\nsecond\nthi".to_vec();
+        assert_eq!(take_lines(&mut buf), vec!["first", "second"]);
+        assert_eq!(buf, b"thi");
+        buf.extend_from_slice(b"rd\n");
+        assert_eq!(take_lines(&mut buf), vec!["third"]);
+        assert!(buf.is_empty());
+        assert!(take_lines(&mut buf).is_empty());
+    }
+
+    #[test]
+    fn test_collect_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let raw = dir.join("echo-0.log");
+        let stamped = stamped_file(&raw);
+        assert_eq!(stamped, dir.join("echo-0.stamped"));
+
+        let first = Utc::now();
+        std::fs::write(&raw, "one\ntwo\nthr").unwrap();
+        let offset = collect_file(&raw, &stamped, 0, first, &limits(1024, 2)).unwrap();
+        assert_eq!(offset, 8);
+        let content = std::fs::read_to_string(&stamped).unwrap();
+        let lines: Vec<_> = content.lines().map(|l| parse_stamped(l).unwrap()).collect();
+        assert_eq!(lines, vec![(first, "one"), (first, "two")]);
+
+        // the rest of the line is stamped when it is complete
+        let second = first + chrono::Duration::seconds(1);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&raw)
+            .unwrap()
+            .write_all(b"ee\n")
+            .unwrap();
+        let offset = collect_file(&raw, &stamped, offset, second, &limits(1024, 2)).unwrap();
+        assert_eq!(offset, 14);
+        let content = std::fs::read_to_string(&stamped).unwrap();
+        assert_eq!(
+            parse_stamped(content.lines().last().unwrap()),
+            Some((second, "three"))
+        );
+
+        // past max_size the stamped file rotates and the raw one is truncated
+        let line = format!("{}\n", "x".repeat(40));
+        std::fs::write(&raw, line.repeat(4)).unwrap();
+        let offset = collect_file(&raw, &stamped, 0, second, &limits(100, 2)).unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!(std::fs::metadata(&raw).unwrap().len(), 0);
+        assert!(std::fs::metadata(&stamped).unwrap().len() <= 100);
+        assert!(rotated(&stamped, 1).exists());
+        assert!(rotated(&stamped, 2).exists());
+        assert!(!rotated(&stamped, 3).exists());
+    }
+
+    #[tokio::test]
+    async fn test_logs_since_and_tail() {
+        let provider = provider();
+        let function = Endpoint::new("echo", "faasrs-test");
+        let raw = provider
+            .instance_log_file(&function, &function.instance(0))
+            .unwrap();
+        std::fs::create_dir_all(raw.parent().unwrap()).unwrap();
+        let early = Utc::now() - chrono::Duration::minutes(10);
+        let late = Utc::now() - chrono::Duration::minutes(1);
+        std::fs::write(
+            stamped_file(&raw),
+            format!(
+                "{} one\n{} two\n{} three\n",
+                early.to_rfc3339(),
+                early.to_rfc3339(),
+                late.to_rfc3339()
+            ),
+        )
+        .unwrap();
+
+        let query = Query {
+            service: "echo".to_string(),
+            namespace: Some("faasrs-test".to_string()),
+        };
+        let texts = |messages: Vec<LogMessage>| -> Vec<String> {
+            messages.into_iter().map(|m| m.text).collect()
+        };
+        let messages: Vec<_> = provider
+            ._logs(query.clone(), None, Some(2), false)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(messages[0].instance, "echo-0");
+        assert_eq!(texts(messages), vec!["two", "three"]);
+
+        let since = late - chrono::Duration::seconds(1);
+        let messages: Vec<_> = provider
+            ._logs(query.clone(), Some(since), None, false)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(texts(messages), vec!["three"]);
+
+        // output not collected yet is not returned
+        std::fs::write(&raw, "four\n").unwrap();
+        let messages: Vec<_> = provider
+            ._logs(query.clone(), Some(Utc::now()), None, false)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(messages.is_empty());
+        provider.collect_logs();
+        let messages: Vec<_> = provider
+            ._logs(query.clone(), Some(since), None, false)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(texts(messages), vec!["three", "four"]);
+
+        let missing = Query {
+            service: "missing".to_string(),
+            namespace: Some("faasrs-test".to_string()),
+        };
+        assert!(matches!(
+            provider._logs(missing, None, None, false).await,
+            Err(LogError::NotFound(_))
+        ));
+        for (service, namespace) in [("..", "faasrs-test"), ("echo", "../secrets"), ("", "")] {
+            let escaping = Query {
+                service: service.to_string(),
+                namespace: Some(namespace.to_string()),
+            };
+            assert!(matches!(
+                provider._logs(escaping, None, None, false).await,
+                Err(LogError::Invalid(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follow_new_instance() {
+        let provider = provider();
+        let function = Endpoint::new("echo", "faasrs-test");
+        let first = provider
+            .instance_log_file(&function, &function.instance(0))
+            .unwrap();
+        std::fs::create_dir_all(first.parent().unwrap()).unwrap();
+        std::fs::write(&first, "old\n").unwrap();
+        provider.collect_logs();
+
+        let query = Query {
+            service: "echo".to_string(),
+            namespace: Some("faasrs-test".to_string()),
+        };
+        let mut stream = provider._logs(query, None, Some(0), true).await.unwrap();
+
+        // a replica started after the stream opened
+        let second = provider
+            .instance_log_file(&function, &function.instance(1))
+            .unwrap();
+        std::fs::write(&second, "hello\n").unwrap();
+        provider.collect_logs();
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.instance, "echo-1");
+        assert_eq!(message.text, "hello");
+
+        // deleting the function ends the stream
+        provider.forget_logs(&function).await;
+        let end = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next()).await;
+        assert!(end.unwrap().is_none());
+    }
+}
@@ -1,29 +1,120 @@
+pub mod balancer;
 pub mod function;
+pub mod gc;
+pub mod health;
+pub mod idle;
+pub mod info;
+pub mod logs;
+pub mod namespace;
+pub mod protocol;
+pub mod reconcile;
+pub mod schema;
+pub mod secret;
+#[cfg(test)]
+mod test;
+pub mod timeout;
+pub mod warm;
 
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
+use crate::config::{GcConfig, LogsConfig, ScaleToZeroConfig};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
 use gateway::{
-    handlers::function::{DeleteError, DeployError, ListError, ResolveError, UpdateError},
+    handlers::{
+        function::{DeleteError, DeployError, ListError, ResolveError, ScaleError, UpdateError},
+        gc::GcError,
+        info::InfoError,
+        logs::LogError,
+        namespace::NamespaceError,
+        secret::SecretError,
+    },
     provider::Provider,
-    types::function::{Deployment, Query, Status},
+    types::{
+        function::{Deployment, Query, Status},
+        gc::GcReport,
+        health::HealthStatus,
+        info::ProviderInfo,
+        logs::LogMessage,
+        namespace::FunctionNamespace,
+        secret::Secret,
+        upstream::Upstream,
+    },
 };
 
 pub struct ContainerdProvider {
     // pub ctr_instance_map: tokio::sync::Mutex<HashMap<Query, FunctionInstance>>,
     database: sled::Db,
+    /// Per namespace secret files, `<data dir>/secrets/<namespace>/<name>`
+    secrets_dir: PathBuf,
+    /// Output of the function instances, see [`ContainerdProvider::instance_log_file`]
+    logs_dir: PathBuf,
+    balancers: Mutex<HashMap<String, Arc<balancer::Balancer>>>,
+    /// Last invocation of every function, see [`idle`]
+    last_invocations: Mutex<HashMap<String, Instant>>,
+    /// Held while a function is paused or brought back, invocations wait for it
+    wake_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Warm pool hits and misses of every function, see [`warm`]
+    warm_stats: Mutex<HashMap<String, warm::WarmStats>>,
+    /// Replica indexes reserved by the warm pool fills in progress, see [`warm`]
+    warm_pending: Mutex<HashMap<String, Vec<u32>>>,
+    scale_to_zero: ScaleToZeroConfig,
+    gc_config: GcConfig,
+    logs_config: LogsConfig,
+    /// Held by the running garbage collection, see [`gc`]
+    gc_lock: tokio::sync::Mutex<()>,
 }
 
 impl ContainerdProvider {
-    pub fn new<P: AsRef<Path>>(path: P) -> Arc<Self> {
-        Arc::new(ContainerdProvider {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Arc<Self>, schema::DatabaseError> {
+        Self::with_scale_to_zero(path, ScaleToZeroConfig::default())
+    }
+
+    pub fn with_scale_to_zero<P: AsRef<Path>>(
+        path: P,
+        scale_to_zero: ScaleToZeroConfig,
+    ) -> Result<Arc<Self>, schema::DatabaseError> {
+        Self::with_config(
+            path,
+            scale_to_zero,
+            GcConfig::default(),
+            LogsConfig::default(),
+        )
+    }
+
+    /// Opens the database in `path`, migrating it from older versions first
+    pub fn with_config<P: AsRef<Path>>(
+        path: P,
+        scale_to_zero: ScaleToZeroConfig,
+        gc_config: GcConfig,
+        logs_config: LogsConfig,
+    ) -> Result<Arc<Self>, schema::DatabaseError> {
+        let secrets_dir = path.as_ref().join("secrets");
+        Ok(Arc::new(ContainerdProvider {
             // ctr_instance_map: tokio::sync::Mutex::new(HashMap::new()),
-            database: sled::open(path).unwrap(),
-        })
+            database: schema::open(path.as_ref(), &secrets_dir)?,
+            secrets_dir,
+            logs_dir: path.as_ref().join("logs"),
+            balancers: Mutex::new(HashMap::new()),
+            last_invocations: Mutex::new(HashMap::new()),
+            wake_locks: Mutex::new(HashMap::new()),
+            warm_stats: Mutex::new(HashMap::new()),
+            warm_pending: Mutex::new(HashMap::new()),
+            scale_to_zero,
+            gc_config,
+            logs_config,
+            gc_lock: tokio::sync::Mutex::new(()),
+        }))
     }
 }
 
 impl Provider for ContainerdProvider {
-    async fn resolve(&self, function: Query) -> Result<actix_http::uri::Builder, ResolveError> {
+    async fn resolve(&self, function: Query) -> Result<Upstream, ResolveError> {
         self._resolve(function).await
     }
 
@@ -46,4 +137,62 @@ impl Provider for ContainerdProvider {
     async fn status(&self, function: Query) -> Result<Status, ResolveError> {
         self._status(function).await
     }
+
+    async fn scale(&self, function: Query, replicas: u32) -> Result<(), ScaleError> {
+        self._scale(function, replicas).await
+    }
+
+    async fn list_secrets(&self, namespace: Option<String>) -> Result<Vec<Secret>, SecretError> {
+        self._list_secrets(namespace).await
+    }
+
+    async fn create_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        self._create_secret(secret).await
+    }
+
+    async fn update_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        self._update_secret(secret).await
+    }
+
+    async fn delete_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        self._delete_secret(secret).await
+    }
+
+    async fn logs(
+        &self,
+        function: Query,
+        since: Option<DateTime<Utc>>,
+        tail: Option<usize>,
+        follow: bool,
+    ) -> Result<BoxStream<'static, LogMessage>, LogError> {
+        self._logs(function, since, tail, follow).await
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>, NamespaceError> {
+        self._list_namespaces().await
+    }
+
+    async fn create_namespace(&self, namespace: FunctionNamespace) -> Result<(), NamespaceError> {
+        self._create_namespace(namespace).await
+    }
+
+    async fn update_namespace(&self, namespace: FunctionNamespace) -> Result<(), NamespaceError> {
+        self._update_namespace(namespace).await
+    }
+
+    async fn delete_namespace(&self, name: String) -> Result<(), NamespaceError> {
+        self._delete_namespace(name).await
+    }
+
+    async fn health(&self) -> HealthStatus {
+        self._health().await
+    }
+
+    async fn info(&self) -> Result<ProviderInfo, InfoError> {
+        self._info().await
+    }
+
+    async fn gc(&self, dry_run: bool) -> Result<GcReport, GcError> {
+        self._gc(dry_run).await
+    }
 }
@@ -0,0 +1,187 @@
+use std::collections::{BTreeSet, HashMap};
+
+use gateway::{
+    handlers::namespace::NamespaceError,
+    types::{function::Query, namespace::FunctionNamespace},
+};
+
+use crate::{
+    consts,
+    impls::{backend, container::function_replica, namespace},
+    provider::ContainerdProvider,
+};
+
+impl From<namespace::NamespaceError> for NamespaceError {
+    fn from(e: namespace::NamespaceError) -> Self {
+        use namespace::NamespaceError::*;
+        log::error!("containerd namespace error: {:?}", e);
+        match e {
+            NotFound => NamespaceError::NotFound("namespace not found".to_string()),
+            AlreadyExists => NamespaceError::Conflict("namespace already exists".to_string()),
+            InvalidArgument(e) => NamespaceError::Invalid(e),
+            FailedPrecondition(e) | Internal(e) => NamespaceError::Internal(e),
+        }
+    }
+}
+
+/// Containerd labels of a namespace, annotations are kept as prefixed labels
+fn namespace_labels(
+    namespace: &FunctionNamespace,
+) -> Result<HashMap<String, String>, NamespaceError> {
+    let mut labels = namespace.labels.clone().unwrap_or_default();
+    if labels.contains_key(consts::NAMESPACE_LABEL) {
+        return Err(NamespaceError::Invalid(format!(
+            "label {} is reserved",
+            consts::NAMESPACE_LABEL
+        )));
+    }
+    for (key, value) in namespace.annotations.iter().flatten() {
+        labels.insert(
+            format!("{}{}", consts::NAMESPACE_ANNOTATION_PREFIX, key),
+            value.clone(),
+        );
+    }
+    labels.insert(
+        consts::NAMESPACE_LABEL.to_string(),
+        consts::NAMESPACE_LABEL_VALUE.to_string(),
+    );
+    Ok(labels)
+}
+
+fn is_managed(labels: &HashMap<String, String>) -> bool {
+    labels.get(consts::NAMESPACE_LABEL).map(String::as_str) == Some(consts::NAMESPACE_LABEL_VALUE)
+}
+
+impl ContainerdProvider {
+    /// Functions can only live in the default namespace or the ones created through the API
+    pub(crate) async fn check_namespace(&self, name: &str) -> Result<(), NamespaceError> {
+        if name == consts::DEFAULT_FUNCTION_NAMESPACE {
+            return Ok(());
+        }
+        match backend().get_namespace(name).await? {
+            Some(namespace) if is_managed(&namespace.labels) => Ok(()),
+            _ => Err(NamespaceError::NotFound(format!(
+                "namespace {} is not created",
+                name
+            ))),
+        }
+    }
+
+    pub(crate) async fn _list_namespaces(&self) -> Result<Vec<String>, NamespaceError> {
+        let mut names: BTreeSet<String> = backend()
+            .list_namespaces()
+            .await?
+            .into_iter()
+            .filter(|namespace| is_managed(&namespace.labels))
+            .map(|namespace| namespace.name)
+            .collect();
+        names.insert(consts::DEFAULT_FUNCTION_NAMESPACE.to_string());
+        Ok(names.into_iter().collect())
+    }
+
+    pub(crate) async fn _create_namespace(
+        &self,
+        namespace: FunctionNamespace,
+    ) -> Result<(), NamespaceError> {
+        if namespace.name == consts::DEFAULT_FUNCTION_NAMESPACE {
+            return Err(NamespaceError::Conflict(format!(
+                "namespace {} already exists",
+                namespace.name
+            )));
+        }
+        let labels = namespace_labels(&namespace)?;
+        match backend().get_namespace(&namespace.name).await? {
+            Some(existing) if is_managed(&existing.labels) => Err(NamespaceError::Conflict(
+                format!("namespace {} already exists", namespace.name),
+            )),
+            // left behind by a deploy before namespaces had to be created, adopt it
+            Some(_) => Ok(backend().update_namespace(&namespace.name, labels).await?),
+            None => Ok(backend().create_namespace(&namespace.name, labels).await?),
+        }
+    }
+
+    pub(crate) async fn _update_namespace(
+        &self,
+        namespace: FunctionNamespace,
+    ) -> Result<(), NamespaceError> {
+        if namespace.name == consts::DEFAULT_FUNCTION_NAMESPACE {
+            return Err(NamespaceError::Invalid(format!(
+                "namespace {} can not be updated",
+                namespace.name
+            )));
+        }
+        let labels = namespace_labels(&namespace)?;
+        self.check_namespace(&namespace.name).await?;
+        Ok(backend().update_namespace(&namespace.name, labels).await?)
+    }
+
+    /// Remove the functions of the namespace with their networks and snapshots,
+    /// then the secrets, logs and images, and finally the containerd namespace
+    pub(crate) async fn _delete_namespace(&self, name: String) -> Result<(), NamespaceError> {
+        if name == consts::DEFAULT_FUNCTION_NAMESPACE {
+            return Err(NamespaceError::Invalid(format!(
+                "namespace {} can not be deleted",
+                name
+            )));
+        }
+        self.check_namespace(&name).await?;
+
+        let containers = backend()
+            .list_container(&name)
+            .await
+            .map_err(|e| NamespaceError::Internal(e.to_string()))?;
+        let functions: BTreeSet<String> = containers
+            .iter()
+            .filter_map(function_replica)
+            .map(|(function, _)| function)
+            .collect();
+        for function in functions {
+            log::info!("Deleting function {} of namespace {}", function, name);
+            self._delete(Query {
+                service: function.clone(),
+                namespace: Some(name.clone()),
+            })
+            .await
+            .map_err(|e| {
+                NamespaceError::Internal(format!("failed to delete function {}: {}", function, e))
+            })?;
+        }
+
+        for dir in [self.secrets_dir.join(&name), self.logs_dir.join(&name)] {
+            match tokio::fs::remove_dir_all(&dir).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::error!("Failed to remove {:?}: {}", dir, e),
+            }
+        }
+
+        backend().remove_images(&name).await?;
+        backend().delete_namespace(&name).await?;
+        log::info!("namespace {} was deleted", name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_labels() {
+        let namespace = FunctionNamespace {
+            name: "team-a".to_string(),
+            labels: Some(HashMap::from([("team".to_string(), "a".to_string())])),
+            annotations: Some(HashMap::from([("owner".to_string(), "ops".to_string())])),
+        };
+        let labels = namespace_labels(&namespace).unwrap();
+        assert!(is_managed(&labels));
+        assert_eq!(labels["team"], "a");
+        assert_eq!(labels["openfaas.annotation/owner"], "ops");
+
+        let reserved = FunctionNamespace {
+            labels: Some(HashMap::from([("openfaas".to_string(), "0".to_string())])),
+            ..namespace
+        };
+        assert!(namespace_labels(&reserved).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use gateway::types::function::Protocol;
+
+use crate::{impls::cni::Endpoint, provider::ContainerdProvider};
+
+pub(crate) const PROTOCOL_TREE: &str = "protocols";
+
+impl ContainerdProvider {
+    /// Protocol the function serves, recorded on deploy, http1 if none
+    pub(crate) fn protocol(&self, function: &Endpoint) -> Protocol {
+        let stored = self
+            .database
+            .open_tree(PROTOCOL_TREE)
+            .and_then(|tree| tree.get(function.to_string()));
+        match stored {
+            Ok(Some(value)) => String::from_utf8_lossy(&value).parse().unwrap_or_else(|e| {
+                log::warn!("Invalid protocol recorded for {}: {}", function, e);
+                Protocol::default()
+            }),
+            Ok(None) => Protocol::default(),
+            Err(e) => {
+                log::error!("Failed to load protocol of {}: {:?}", function, e);
+                Protocol::default()
+            }
+        }
+    }
+
+    pub(crate) fn save_protocol(
+        &self,
+        function: &Endpoint,
+        protocol: Protocol,
+    ) -> Result<(), sled::Error> {
+        self.database
+            .open_tree(PROTOCOL_TREE)?
+            .insert(function.to_string(), protocol.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    pub(crate) fn forget_protocol(&self, function: &Endpoint) {
+        if let Err(e) = self
+            .database
+            .open_tree(PROTOCOL_TREE)
+            .and_then(|tree| tree.remove(function.to_string()))
+        {
+            log::error!("Failed to remove protocol of {}: {:?}", function, e);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Startup reconciliation. A reboot keeps the containers and snapshots in
+//! containerd but loses the tasks and everything under `/var/run`, the
+//! replicas left that way get a new network and task before serving again.
+
+use std::collections::BTreeMap;
+
+use derive_more::Display;
+
+use crate::{
+    impls::{
+        backend,
+        cni::{self, Endpoint},
+        container::{container_port, function_replica, is_legacy_function},
+        task::{is_paused, is_running},
+    },
+    provider::ContainerdProvider,
+};
+
+/// What [`ContainerdProvider::reconcile`] found, one entry per replica
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    /// Replicas running with their network
+    pub healthy: Vec<String>,
+    /// Replicas whose task or network was recreated
+    pub restored: Vec<String>,
+    /// Warm replicas removed for the pool to prepare again
+    pub discarded: Vec<String>,
+    /// Functions scaled to zero, left for their next invocation
+    pub idle: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Task of one replica as containerd reports it
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+enum TaskState {
+    #[display("running")]
+    Running,
+    #[display("paused")]
+    Paused,
+    /// Created and never started, or exited
+    #[display("stopped")]
+    Stopped,
+    #[display("missing")]
+    Missing,
+}
+
+/// What becomes of one replica
+#[derive(Debug, PartialEq, Eq)]
+enum ReplicaAction {
+    Keep,
+    /// A warm replica which can not be started anymore
+    Discard,
+    /// A serving replica which needs a new network and task
+    Restore,
+}
+
+/// Decide from what was found of the replica, `network` tells whether its
+/// network namespace exists and `recorded` whether its address is in the database
+fn replica_action(warm: bool, task: TaskState, network: bool, recorded: bool) -> ReplicaAction {
+    if warm {
+        // prepared replicas hold a created task, anything else is stale
+        return if task != TaskState::Missing && network {
+            ReplicaAction::Keep
+        } else {
+            ReplicaAction::Discard
+        };
+    }
+    // the task may have outlived its network, e.g. /var/run was cleared.
+    // a paused function is resumed by its next invocation as usual
+    match task {
+        TaskState::Running | TaskState::Paused if network && recorded => ReplicaAction::Keep,
+        _ => ReplicaAction::Restore,
+    }
+}
+
+impl ContainerdProvider {
+    /// Bring every function of every namespace back to the state the database
+    /// holds for it, the addresses refreshed along the way
+    pub async fn reconcile(&self) -> ReconcileReport {
+        let mut report = ReconcileReport::default();
+        let namespaces = match self._list_namespaces().await {
+            Ok(namespaces) => namespaces,
+            Err(e) => {
+                log::error!("Reconcile: failed to list namespaces: {}", e);
+                return report;
+            }
+        };
+        for namespace in namespaces {
+            let containers = match backend().list_container(&namespace).await {
+                Ok(containers) => containers,
+                Err(e) => {
+                    log::error!(
+                        "Reconcile: failed to list containers of {}: {:?}",
+                        namespace,
+                        e
+                    );
+                    report.failed.push(namespace);
+                    continue;
+                }
+            };
+            let mut functions = BTreeMap::<String, Vec<_>>::new();
+            for container in containers {
+                if let Some((function, replica)) = function_replica(&container) {
+                    let port = container_port(&container);
+                    functions.entry(function).or_default().push((replica, port));
+                } else if is_legacy_function(&container) {
+                    // adopted at startup before this runs, it failed for this one
+                    log::warn!(
+                        "Reconcile: {} in {} was deployed before replicas and is not adopted yet, left as it is",
+                        container.id,
+                        namespace
+                    );
+                }
+            }
+            for (service, replicas) in functions {
+                let function = Endpoint::new(&service, &namespace);
+                self.reconcile_function(&function, &replicas, &mut report)
+                    .await;
+            }
+        }
+        log::info!(
+            "Reconciled functions: {} healthy, {} restored, {} warm discarded, {} idle, {} failed",
+            report.healthy.len(),
+            report.restored.len(),
+            report.discarded.len(),
+            report.idle.len(),
+            report.failed.len()
+        );
+        report
+    }
+
+    async fn task_state(&self, endpoint: &Endpoint) -> TaskState {
+        match backend().get_task(endpoint).await {
+            Ok(task) if is_running(&task) => TaskState::Running,
+            Ok(task) if is_paused(&task) => TaskState::Paused,
+            Ok(_) => TaskState::Stopped,
+            Err(_) => TaskState::Missing,
+        }
+    }
+
+    async fn reconcile_function(
+        &self,
+        function: &Endpoint,
+        replicas: &[(u32, u16)],
+        report: &mut ReconcileReport,
+    ) {
+        let lock = self.wake_lock(function);
+        let _reconciling = lock.lock().await;
+        if self.scaled_to_zero(function) {
+            log::debug!("Reconcile: {} is scaled to zero", function);
+            report.idle.push(function.to_string());
+            return;
+        }
+        let warm = self.warm_replicas(function).unwrap_or_default();
+        let addresses: BTreeMap<u32, _> = self
+            .instance_addresses(function)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+
+        let mut kept_paused = Vec::new();
+        let mut restored = false;
+        for &(replica, port) in replicas {
+            let endpoint = function.instance(replica);
+            let task = self.task_state(&endpoint).await;
+            let recorded = addresses.contains_key(&replica);
+            let is_warm = warm.iter().any(|(warm, _)| *warm == replica);
+            let action = replica_action(
+                is_warm,
+                task,
+                cni::cni_impl::network_exists(&endpoint),
+                recorded,
+            );
+            match action {
+                ReplicaAction::Keep => {
+                    if !is_warm {
+                        log::debug!("Reconcile: {} is {}", endpoint, task);
+                        report.healthy.push(endpoint.to_string());
+                    }
+                    if task == TaskState::Paused {
+                        kept_paused.push(endpoint);
+                    }
+                }
+                ReplicaAction::Discard => {
+                    log::warn!("Reconcile: discarding stale warm replica {}", endpoint);
+                    match self.remove_instance(function, replica).await {
+                        Ok(()) => report.discarded.push(endpoint.to_string()),
+                        Err(e) => {
+                            log::error!("Reconcile: failed to remove {}: {:?}", endpoint, e);
+                            report.failed.push(endpoint.to_string());
+                        }
+                    }
+                }
+                ReplicaAction::Restore => {
+                    log::warn!(
+                        "Reconcile: restoring {}, task {}, address {}",
+                        endpoint,
+                        task,
+                        if recorded { "recorded" } else { "missing" }
+                    );
+                    match self.restart_instance(function, replica, port).await {
+                        Ok(addr) => {
+                            log::info!("Reconcile: {} restored at {}", endpoint, addr);
+                            report.restored.push(endpoint.to_string());
+                            restored = true;
+                        }
+                        Err(e) => {
+                            log::error!("Reconcile: failed to restore {}: {}", endpoint, e);
+                            self.forget_instance_address(function, replica);
+                            report.failed.push(endpoint.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        // the restored replicas run, the function is not paused anymore and
+        // the replicas still frozen are resumed with them
+        if restored && self.paused(function) {
+            for endpoint in kept_paused {
+                if let Err(e) = backend().resume_task(&endpoint).await {
+                    log::error!("Reconcile: failed to resume {}: {:?}", endpoint, e);
+                }
+            }
+            self.clear_paused(function);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplicaAction, TaskState, replica_action};
+
+    #[test]
+    fn test_replica_action() {
+        use ReplicaAction::*;
+        use TaskState::*;
+
+        // serving replicas
+        assert_eq!(replica_action(false, Running, true, true), Keep);
+        assert_eq!(replica_action(false, Paused, true, true), Keep);
+        // after a reboot: no task, no network
+        assert_eq!(replica_action(false, Missing, false, true), Restore);
+        assert_eq!(replica_action(false, Stopped, true, true), Restore);
+        // /var/run cleared under a running task
+        assert_eq!(replica_action(false, Running, false, true), Restore);
+        assert_eq!(replica_action(false, Paused, false, true), Restore);
+        // running but unknown to the database
+        assert_eq!(replica_action(false, Running, true, false), Restore);
+
+        // warm replicas hold a created task and their network
+        assert_eq!(replica_action(true, Stopped, true, false), Keep);
+        assert_eq!(replica_action(true, Missing, true, false), Discard);
+        assert_eq!(replica_action(true, Stopped, false, false), Discard);
+        assert_eq!(replica_action(true, Missing, false, false), Discard);
+    }
+}
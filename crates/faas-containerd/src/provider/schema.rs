@@ -0,0 +1,238 @@
+//! Layout of the sled database in the data directory. Every concern has its own
+//! tree, the schema version kept in `meta` tells [`open`] which migrations an
+//! older data directory still needs.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use derive_more::Display;
+
+use crate::provider::{
+    function::instance::{decode_address, encode_address},
+    secret::SecretRecord,
+};
+
+/// Version written by this build, bumped with every migration added below
+pub const SCHEMA_VERSION: u32 = 2;
+
+const META_TREE: &str = "meta";
+const VERSION_KEY: &str = "schema_version";
+
+/// Record of every function, keyed `<namespace>-<service>`
+pub const FUNCTIONS_TREE: &str = "functions";
+/// Address of every serving replica, keyed `<namespace>-<service>/<replica>`
+pub const ADDRESSES_TREE: &str = "addresses";
+/// Secrets known to the store, keyed `<namespace>/<name>`, the values stay in files
+pub const SECRETS_TREE: &str = "secrets";
+/// How many times every function was deployed, keyed `<namespace>-<service>`
+pub const REVISIONS_TREE: &str = "revisions";
+
+#[derive(Debug, Display)]
+pub enum DatabaseError {
+    #[display("failed to open database {}: {}", _0.display(), _1)]
+    Open(PathBuf, sled::Error),
+    #[display("database error: {}", _0)]
+    Sled(sled::Error),
+    #[display("corrupt value in tree {} at {:?}: {}", _0, _1, _2)]
+    Corrupt(String, String, String),
+    #[display(
+        "database schema version {} is newer than {} supported by this build",
+        _0,
+        SCHEMA_VERSION
+    )]
+    TooNew(u32),
+    #[display("migration to schema version {} failed: {}", _0, _1)]
+    Migration(u32, String),
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<sled::Error> for DatabaseError {
+    fn from(e: sled::Error) -> Self {
+        DatabaseError::Sled(e)
+    }
+}
+
+/// Open the database under `path` and bring it to [`SCHEMA_VERSION`]
+pub fn open(path: &Path, secrets_dir: &Path) -> Result<sled::Db, DatabaseError> {
+    let db = sled::open(path).map_err(|e| DatabaseError::Open(path.to_path_buf(), e))?;
+    migrate(&db, secrets_dir)?;
+    Ok(db)
+}
+
+/// Directories written before the version was stored are version 1
+pub fn schema_version(db: &sled::Db) -> Result<u32, DatabaseError> {
+    let Some(value) = db.open_tree(META_TREE)?.get(VERSION_KEY)? else {
+        return Ok(1);
+    };
+    <[u8; 4]>::try_from(value.as_ref())
+        .map(u32::from_be_bytes)
+        .map_err(|_| {
+            DatabaseError::Corrupt(
+                META_TREE.to_string(),
+                VERSION_KEY.to_string(),
+                format!("expected 4 bytes, got {}", value.len()),
+            )
+        })
+}
+
+fn migrate(db: &sled::Db, secrets_dir: &Path) -> Result<(), DatabaseError> {
+    let mut version = schema_version(db)?;
+    if version > SCHEMA_VERSION {
+        return Err(DatabaseError::TooNew(version));
+    }
+    while version < SCHEMA_VERSION {
+        let next = version + 1;
+        log::info!("Migrating database to schema version {}", next);
+        let migrated = match next {
+            2 => migrate_v2(db, secrets_dir),
+            _ => unreachable!("no migration to schema version {}", next),
+        };
+        migrated.map_err(|e| DatabaseError::Migration(next, e))?;
+        db.open_tree(META_TREE)?
+            .insert(VERSION_KEY, &next.to_be_bytes())?;
+        db.flush()?;
+        version = next;
+    }
+    Ok(())
+}
+
+/// The addresses leave the default tree for their own, the legacy ones without
+/// a port get the default one. The secrets already on disk are indexed.
+///
+/// Before replicas existed the default tree held one `<namespace>-<service>` key
+/// per function, the address of its only container. It becomes replica 0 and
+/// keeps routing to that container until
+/// [`ContainerdProvider::adopt_legacy_functions`](crate::provider::ContainerdProvider::adopt_legacy_functions)
+/// replaces it at startup with a labelled replica 0, recording the new address.
+fn migrate_v2(db: &sled::Db, secrets_dir: &Path) -> Result<(), String> {
+    let addresses = db.open_tree(ADDRESSES_TREE).map_err(|e| e.to_string())?;
+    for item in db.iter() {
+        let (key, value) = item.map_err(|e| e.to_string())?;
+        let name = String::from_utf8_lossy(&key);
+        let replica_key = if name.contains('/') {
+            name.into_owned()
+        } else {
+            format!("{}/0", name)
+        };
+        match decode_address(&value) {
+            Some(addr) => {
+                addresses
+                    .insert(replica_key, encode_address(addr))
+                    .map_err(|e| e.to_string())?;
+            }
+            None => log::warn!(
+                "Dropping malformed address record {:?} = {:?}",
+                String::from_utf8_lossy(&key),
+                value
+            ),
+        }
+        db.remove(&key).map_err(|e| e.to_string())?;
+    }
+
+    let secrets = db.open_tree(SECRETS_TREE).map_err(|e| e.to_string())?;
+    let namespaces = match std::fs::read_dir(secrets_dir) {
+        Ok(namespaces) => namespaces,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("{}: {}", secrets_dir.display(), e)),
+    };
+    for namespace in namespaces {
+        let namespace = namespace.map_err(|e| e.to_string())?;
+        if !namespace.path().is_dir() {
+            continue;
+        }
+        let files = std::fs::read_dir(namespace.path()).map_err(|e| e.to_string())?;
+        for file in files {
+            let file = file.map_err(|e| e.to_string())?;
+            let metadata = file.metadata().map_err(|e| e.to_string())?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let record = SecretRecord {
+                namespace: namespace.file_name().to_string_lossy().into_owned(),
+                name: file.file_name().to_string_lossy().into_owned(),
+                updated_at: metadata
+                    .modified()
+                    .map(DateTime::<Utc>::from)
+                    .unwrap_or_else(|_| Utc::now()),
+            };
+            secrets
+                .insert(record.key(), record.to_vec())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::*;
+    use crate::{impls::cni::Endpoint, provider::function::instance::scan_addresses};
+
+    /// A data directory as the releases before replicas left it
+    #[test]
+    fn test_migrate_baseline() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert("faasrs-default-echo", &[10, 66, 0, 5]).unwrap();
+        db.insert("team-hello-2", &[10, 66, 0, 7]).unwrap();
+
+        migrate(&db, Path::new("/nonexistent")).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+        assert!(db.is_empty());
+        let addresses = db.open_tree(ADDRESSES_TREE).unwrap();
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(
+            scan_addresses(&addresses, &Endpoint::new("echo", "faasrs-default")).unwrap(),
+            vec![(0, SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 8080)))]
+        );
+        assert_eq!(
+            scan_addresses(&addresses, &Endpoint::new("hello-2", "team")).unwrap(),
+            vec![(0, SocketAddr::from((Ipv4Addr::new(10, 66, 0, 7), 8080)))]
+        );
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert("faasrs-default-echo/0", &[10, 66, 0, 5]).unwrap();
+        db.insert("faasrs-default-echo/1", &[10, 66, 0]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let secrets_dir = dir.path();
+        std::fs::create_dir_all(secrets_dir.join("faasrs-default")).unwrap();
+        std::fs::write(secrets_dir.join("faasrs-default").join("token"), "t").unwrap();
+
+        migrate(&db, secrets_dir).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+        assert!(db.is_empty());
+        let addresses = db.open_tree(ADDRESSES_TREE).unwrap();
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(
+            decode_address(&addresses.get("faasrs-default-echo/0").unwrap().unwrap()),
+            Some(SocketAddr::from((Ipv4Addr::new(10, 66, 0, 5), 8080)))
+        );
+        let secrets = db.open_tree(SECRETS_TREE).unwrap();
+        assert!(secrets.contains_key("faasrs-default/token").unwrap());
+
+        // already current, nothing left to do
+        migrate(&db, secrets_dir).unwrap();
+
+        db.open_tree(META_TREE)
+            .unwrap()
+            .insert(VERSION_KEY, &(SCHEMA_VERSION + 1).to_be_bytes())
+            .unwrap();
+        assert!(matches!(
+            migrate(&db, Path::new("/nonexistent")),
+            Err(DatabaseError::TooNew(_))
+        ));
+        db.open_tree(META_TREE)
+            .unwrap()
+            .insert(VERSION_KEY, "2")
+            .unwrap();
+        assert!(matches!(
+            schema_version(&db),
+            Err(DatabaseError::Corrupt(..))
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use gateway::{handlers::secret::SecretError, types::secret::Secret};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    consts,
+    provider::{ContainerdProvider, schema::SECRETS_TREE},
+};
+
+/// Entry of the secrets tree, the value itself is only in the file
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SecretRecord {
+    pub namespace: String,
+    pub name: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SecretRecord {
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("secret record is serializable")
+    }
+}
+
+/// Secrets are plain files, so their names must not escape the namespace directory
+fn check_name(kind: &str, name: &str) -> Result<(), SecretError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SecretError::Invalid(format!(
+            "invalid {} name {:?}",
+            kind, name
+        )))
+    }
+}
+
+fn internal(e: std::io::Error) -> SecretError {
+    log::error!("Failed to access secret store: {}", e);
+    SecretError::Internal(e.to_string())
+}
+
+fn database(e: sled::Error) -> SecretError {
+    log::error!("Failed to access secrets tree: {:?}", e);
+    SecretError::Internal(e.to_string())
+}
+
+impl ContainerdProvider {
+    /// `<data dir>/secrets/<namespace>`
+    fn secret_namespace_dir(&self, namespace: Option<&str>) -> Result<PathBuf, SecretError> {
+        let namespace = namespace.unwrap_or(consts::DEFAULT_FUNCTION_NAMESPACE);
+        check_name("namespace", namespace)?;
+        Ok(self.secrets_dir.join(namespace))
+    }
+
+    fn secret_path(&self, secret: &Secret) -> Result<PathBuf, SecretError> {
+        check_name("secret", &secret.name)?;
+        Ok(self
+            .secret_namespace_dir(secret.namespace.as_deref())?
+            .join(&secret.name))
+    }
+
+    /// Host files of the secrets used by a function, keyed by secret name
+    pub(crate) fn secret_mounts(
+        &self,
+        namespace: &str,
+        names: &[String],
+    ) -> Result<BTreeMap<String, PathBuf>, SecretError> {
+        let dir = self.secret_namespace_dir(Some(namespace))?;
+        names
+            .iter()
+            .map(|name| {
+                check_name("secret", name)?;
+                let path = dir.join(name);
+                if path.is_file() {
+                    Ok((name.clone(), path))
+                } else {
+                    Err(SecretError::NotFound(format!(
+                        "secret {} not found in namespace {}",
+                        name, namespace
+                    )))
+                }
+            })
+            .collect()
+    }
+
+    fn index_secret(&self, secret: &Secret) -> Result<(), SecretError> {
+        let record = SecretRecord {
+            namespace: secret
+                .namespace
+                .clone()
+                .unwrap_or_else(|| consts::DEFAULT_FUNCTION_NAMESPACE.to_string()),
+            name: secret.name.clone(),
+            updated_at: Utc::now(),
+        };
+        self.database
+            .open_tree(SECRETS_TREE)
+            .and_then(|tree| tree.insert(record.key(), record.to_vec()))
+            .map_err(database)?;
+        Ok(())
+    }
+
+    pub(crate) async fn _list_secrets(
+        &self,
+        namespace: Option<String>,
+    ) -> Result<Vec<Secret>, SecretError> {
+        let name = namespace
+            .as_deref()
+            .unwrap_or(consts::DEFAULT_FUNCTION_NAMESPACE);
+        check_name("namespace", name)?;
+        let tree = self.database.open_tree(SECRETS_TREE).map_err(database)?;
+        let mut secrets = Vec::new();
+        // keys sort by name within the namespace
+        for item in tree.scan_prefix(format!("{}/", name)) {
+            let (key, value) = item.map_err(database)?;
+            match serde_json::from_slice::<SecretRecord>(&value) {
+                Ok(record) => secrets.push(Secret {
+                    name: record.name,
+                    namespace: namespace.clone(),
+                    ..Default::default()
+                }),
+                Err(e) => log::warn!(
+                    "Ignoring invalid secret record {:?}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ),
+            }
+        }
+        Ok(secrets)
+    }
+
+    pub(crate) async fn _create_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        let path = self.secret_path(&secret)?;
+        if tokio::fs::try_exists(&path).await.map_err(internal)? {
+            return Err(SecretError::Conflict(format!(
+                "secret {} already exists",
+                secret.name
+            )));
+        }
+        write_secret(&path, &secret).await?;
+        self.index_secret(&secret)
+    }
+
+    pub(crate) async fn _update_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        let path = self.secret_path(&secret)?;
+        if !tokio::fs::try_exists(&path).await.map_err(internal)? {
+            return Err(SecretError::NotFound(format!(
+                "secret {} not found",
+                secret.name
+            )));
+        }
+        write_secret(&path, &secret).await?;
+        self.index_secret(&secret)
+    }
+
+    pub(crate) async fn _delete_secret(&self, secret: Secret) -> Result<(), SecretError> {
+        let path = self.secret_path(&secret)?;
+        let namespace = secret
+            .namespace
+            .as_deref()
+            .unwrap_or(consts::DEFAULT_FUNCTION_NAMESPACE);
+        // the record goes only once the file can no longer be mounted
+        let removed = match tokio::fs::remove_file(&path).await {
+            Ok(()) => true,
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(internal(e)),
+        };
+        let indexed = self
+            .database
+            .open_tree(SECRETS_TREE)
+            .and_then(|tree| tree.remove(format!("{}/{}", namespace, secret.name)))
+            .map_err(database)?
+            .is_some();
+        if removed || indexed {
+            Ok(())
+        } else {
+            Err(SecretError::NotFound(format!(
+                "secret {} not found",
+                secret.name
+            )))
+        }
+    }
+}
+
+/// Write a temporary file beside the secret and rename it over, a crash never
+/// leaves a truncated secret behind. Running replicas keep the file they had
+/// bind-mounted, the new value reaches them when they are redeployed.
+async fn write_secret(path: &Path, secret: &Secret) -> Result<(), SecretError> {
+    let content = secret
+        .content()
+        .map_err(|e| SecretError::Invalid(format!("invalid rawValue: {}", e)))?;
+    let dir = path
+        .parent()
+        .ok_or_else(|| SecretError::Internal(format!("{:?} has no parent", path)))?;
+    tokio::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .await
+        .map_err(internal)?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dir.join(format!(".{}.tmp", file_name));
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .await?;
+        // mode only applies to new files, a stale temporary file may be left
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+        file.write_all(&content).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, path).await?;
+        // the rename itself is only durable once the directory is
+        tokio::fs::File::open(dir).await?.sync_all().await
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    written.map_err(internal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::test::provider;
+
+    fn secret(name: &str, value: &str) -> Secret {
+        Secret {
+            name: name.to_string(),
+            namespace: Some("faasrs-test".to_string()),
+            value: Some(value.to_string()),
+            raw_value: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_secret_store() {
+        let provider = provider();
+        let dir = provider.dir();
+
+        provider
+            ._create_secret(secret("db-password", "a"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            provider._create_secret(secret("db-password", "b")).await,
+            Err(SecretError::Conflict(_))
+        ));
+        assert!(matches!(
+            provider._create_secret(secret("../escape", "b")).await,
+            Err(SecretError::Invalid(_))
+        ));
+        provider
+            ._update_secret(secret("db-password", "b"))
+            .await
+            .unwrap();
+
+        let path = dir.join("secrets/faasrs-test/db-password");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "b");
+        assert!(!dir.join("secrets/faasrs-test/.db-password.tmp").exists());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let listed = provider
+            ._list_secrets(Some("faasrs-test".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].value.is_none());
+
+        let mounts = provider
+            .secret_mounts("faasrs-test", &["db-password".to_string()])
+            .unwrap();
+        assert_eq!(mounts["db-password"], path);
+        assert!(
+            provider
+                .secret_mounts("faasrs-test", &["missing".to_string()])
+                .is_err()
+        );
+
+        provider
+            ._delete_secret(secret("db-password", ""))
+            .await
+            .unwrap();
+        assert!(matches!(
+            provider._update_secret(secret("db-password", "c")).await,
+            Err(SecretError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,58 @@
+//! Fixtures shared by the tests of the provider
+
+use std::{collections::HashMap, ops::Deref, path::Path, sync::Arc};
+
+use gateway::types::function::Deployment;
+use tempfile::TempDir;
+
+use crate::provider::ContainerdProvider;
+
+/// A provider on its own data directory, removed when it is dropped
+pub(crate) struct TestProvider {
+    // dropped first, the database is closed before its directory goes
+    provider: Arc<ContainerdProvider>,
+    dir: TempDir,
+}
+
+impl TestProvider {
+    pub(crate) fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Deref for TestProvider {
+    type Target = ContainerdProvider;
+
+    fn deref(&self) -> &ContainerdProvider {
+        &self.provider
+    }
+}
+
+pub(crate) fn provider() -> TestProvider {
+    let dir = tempfile::tempdir().unwrap();
+    let provider = ContainerdProvider::new(dir.path()).unwrap();
+    TestProvider { provider, dir }
+}
+
+/// `echo` in the default namespace with `labels`, the other fields are left unset
+pub(crate) fn deployment(labels: &[(&str, &str)]) -> Deployment {
+    Deployment {
+        service: "echo".to_string(),
+        image: "docker.io/library/echo:latest".to_string(),
+        namespace: None,
+        env_process: None,
+        env_vars: None,
+        constraints: None,
+        secrets: None,
+        labels: (!labels.is_empty()).then(|| {
+            labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        }),
+        annotations: None,
+        limits: None,
+        requests: None,
+        read_only_root_filesystem: false,
+    }
+}
@@ -0,0 +1,48 @@
+use gateway::types::function::FunctionTimeouts;
+
+use crate::{impls::cni::Endpoint, provider::ContainerdProvider};
+
+pub(crate) const TIMEOUT_TREE: &str = "timeouts";
+
+impl ContainerdProvider {
+    /// Timeouts recorded for the function on deploy, the gateway defaults if none
+    pub(crate) fn timeouts(&self, function: &Endpoint) -> FunctionTimeouts {
+        let stored = self
+            .database
+            .open_tree(TIMEOUT_TREE)
+            .and_then(|tree| tree.get(function.to_string()));
+        match stored {
+            Ok(Some(value)) => serde_json::from_slice(&value).unwrap_or_else(|e| {
+                log::warn!("Invalid timeouts recorded for {}: {}", function, e);
+                FunctionTimeouts::default()
+            }),
+            Ok(None) => FunctionTimeouts::default(),
+            Err(e) => {
+                log::error!("Failed to load timeouts of {}: {:?}", function, e);
+                FunctionTimeouts::default()
+            }
+        }
+    }
+
+    pub(crate) fn save_timeouts(
+        &self,
+        function: &Endpoint,
+        timeouts: &FunctionTimeouts,
+    ) -> Result<(), sled::Error> {
+        let value = serde_json::to_vec(timeouts).expect("timeouts are serializable");
+        self.database
+            .open_tree(TIMEOUT_TREE)?
+            .insert(function.to_string(), value)?;
+        Ok(())
+    }
+
+    pub(crate) fn forget_timeouts(&self, function: &Endpoint) {
+        if let Err(e) = self
+            .database
+            .open_tree(TIMEOUT_TREE)
+            .and_then(|tree| tree.remove(function.to_string()))
+        {
+            log::error!("Failed to remove timeouts of {}: {:?}", function, e);
+        }
+    }
+}
@@ -0,0 +1,583 @@
+//! Warm pool. The functions labelled `com.faasrs.scale.min-warm=N` keep N
+//! replicas prepared beside the serving ones: snapshot, network, container and
+//! a created task. Scaling out or back from zero takes one of them and only
+//! has to start its task, the pool is topped up again in the background.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use gateway::{
+    handlers::function::DeployError,
+    types::function::{Deployment, WarmPool},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts,
+    impls::{backend, cni::Endpoint},
+    provider::{
+        ContainerdProvider,
+        function::instance::{encode_address, scan_addresses},
+    },
+};
+
+/// Size of the pool of every function having one
+pub(crate) const WARM_POOL_TREE: &str = "warm_pools";
+/// Addresses of the prepared replicas, keyed like the serving ones
+pub(crate) const WARM_TREE: &str = "warm";
+
+/// Keys only hold `<namespace>-<service>`, the record tells them apart
+#[derive(Serialize, Deserialize, Debug)]
+struct PoolRecord {
+    service: String,
+    namespace: String,
+    size: u32,
+}
+
+/// Pool usage since the daemon started
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WarmStats {
+    hits: u64,
+    misses: u64,
+}
+
+/// Read the `com.faasrs.scale.min-warm` label, 0 without a pool
+pub(crate) fn min_warm(config: &Deployment) -> Result<u32, DeployError> {
+    let Some(value) = config
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(consts::MIN_WARM_LABEL))
+    else {
+        return Ok(0);
+    };
+    value.parse::<u32>().map_err(|_| {
+        DeployError::Invalid(format!(
+            "label {} should be a non-negative integer, got {}",
+            consts::MIN_WARM_LABEL,
+            value
+        ))
+    })
+}
+
+impl ContainerdProvider {
+    pub(crate) fn save_min_warm(&self, function: &Endpoint, size: u32) -> Result<(), sled::Error> {
+        let tree = self.database.open_tree(WARM_POOL_TREE)?;
+        if size == 0 {
+            tree.remove(function.to_string())?;
+        } else {
+            let record = PoolRecord {
+                service: function.service.clone(),
+                namespace: function.namespace.clone(),
+                size,
+            };
+            let value = serde_json::to_vec(&record).expect("pool record is serializable");
+            tree.insert(function.to_string(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Size of the pool of the function, 0 without one
+    fn pool_size(&self, function: &Endpoint) -> u32 {
+        self.database
+            .open_tree(WARM_POOL_TREE)
+            .and_then(|tree| tree.get(function.to_string()))
+            .map(|value| {
+                value
+                    .and_then(|value| serde_json::from_slice::<PoolRecord>(&value).ok())
+                    .map_or(0, |record| record.size)
+            })
+            .unwrap_or_else(|e| {
+                log::error!("Failed to load warm pool of {}: {:?}", function, e);
+                0
+            })
+    }
+
+    /// Prepared replicas of the function with the address they will serve on
+    pub(crate) fn warm_replicas(
+        &self,
+        function: &Endpoint,
+    ) -> Result<Vec<(u32, SocketAddr)>, sled::Error> {
+        scan_addresses(&self.database.open_tree(WARM_TREE)?, function)
+    }
+
+    pub(crate) fn record_warm(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: SocketAddr,
+    ) -> Result<(), DeployError> {
+        self.database
+            .open_tree(WARM_TREE)
+            .and_then(|tree| tree.insert(format!("{}/{}", function, replica), encode_address(addr)))
+            .map(|_| ())
+            .map_err(|e| {
+                log::error!("Failed to record warm replica of {}: {:?}", function, e);
+                DeployError::InternalError(e.to_string())
+            })
+    }
+
+    pub(crate) fn forget_warm(&self, function: &Endpoint, replica: u32) {
+        if let Err(e) = self
+            .database
+            .open_tree(WARM_TREE)
+            .and_then(|tree| tree.remove(format!("{}/{}", function, replica)))
+        {
+            log::error!(
+                "Failed to remove warm replica {}/{}: {:?}",
+                function,
+                replica,
+                e
+            );
+        }
+    }
+
+    /// Drop the pool size and counters, the replicas go with the function
+    pub(crate) fn forget_warm_pool(&self, function: &Endpoint) {
+        if let Err(e) = self.save_min_warm(function, 0) {
+            log::error!("Failed to remove warm pool of {}: {:?}", function, e);
+        }
+        self.warm_stats
+            .lock()
+            .unwrap()
+            .remove(&function.to_string());
+    }
+
+    /// Pool of the function as reported by `Status`, `None` without one
+    pub(crate) fn warm_pool(&self, function: &Endpoint) -> Option<WarmPool> {
+        let size = self.pool_size(function);
+        let ready = self.warm_replicas(function).map_or(0, |warm| warm.len());
+        if size == 0 && ready == 0 {
+            return None;
+        }
+        let stats = self
+            .warm_stats
+            .lock()
+            .unwrap()
+            .get(&function.to_string())
+            .copied()
+            .unwrap_or_default();
+        Some(WarmPool {
+            size: size as i32,
+            ready: ready as i32,
+            hits: stats.hits,
+            misses: stats.misses,
+        })
+    }
+
+    fn count_warm(&self, function: &Endpoint, hit: bool) {
+        let mut stats = self.warm_stats.lock().unwrap();
+        let stats = stats.entry(function.to_string()).or_default();
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+
+    /// Start the task of a prepared replica and route to it, `None` when the
+    /// function has no pool or it ran dry
+    pub(crate) async fn take_warm(&self, function: &Endpoint) -> Option<(u32, SocketAddr)> {
+        let (taken, failed) = self
+            .take_warm_with(function, |replica, addr| {
+                self.start_prepared(function, replica, addr)
+            })
+            .await;
+        for replica in failed {
+            if let Err(e) = self.remove_instance(function, replica).await {
+                log::error!(
+                    "Failed to remove warm replica {}/{}: {:?}",
+                    function,
+                    replica,
+                    e
+                );
+            }
+        }
+        taken
+    }
+
+    async fn start_prepared(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: SocketAddr,
+    ) -> Result<(), String> {
+        backend()
+            .start_task(&function.instance(replica))
+            .await
+            .map_err(|e| e.to_string())?;
+        self.record_address(function, replica, addr)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Take up to `count` prepared replicas out of the pool without starting
+    /// them, called under the wake lock. They are started with
+    /// [`Self::start_warm`] or handed back with [`Self::return_warm`].
+    pub(crate) fn claim_warm(&self, function: &Endpoint, count: usize) -> Vec<(u32, SocketAddr)> {
+        if count == 0 || self.pool_size(function) == 0 {
+            return Vec::new();
+        }
+        let warm = self.warm_replicas(function).unwrap_or_else(|e| {
+            log::error!("Failed to load warm replicas of {}: {:?}", function, e);
+            Vec::new()
+        });
+        if warm.len() < count {
+            self.count_warm(function, false);
+        }
+        let claimed: Vec<_> = warm.into_iter().take(count).collect();
+        for (replica, _) in &claimed {
+            self.forget_warm(function, *replica);
+        }
+        claimed
+    }
+
+    /// Start a replica claimed from the pool
+    pub(crate) async fn start_warm(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: SocketAddr,
+    ) -> Result<(), String> {
+        self.start_prepared(function, replica, addr).await?;
+        log::info!("Took warm replica {} of {}", replica, function);
+        self.count_warm(function, true);
+        Ok(())
+    }
+
+    /// Put claimed replicas that were never started back into the pool
+    pub(crate) fn return_warm(&self, function: &Endpoint, claimed: &[(u32, SocketAddr)]) {
+        for (replica, addr) in claimed {
+            if let Err(e) = self.record_warm(function, *replica, *addr) {
+                log::error!(
+                    "Failed to return warm replica {}/{}: {}",
+                    function,
+                    replica,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Hand the prepared replicas to `start` in order until one comes up, also
+    /// returns the ones it failed on for the caller to discard
+    async fn take_warm_with<S, F>(
+        &self,
+        function: &Endpoint,
+        start: S,
+    ) -> (Option<(u32, SocketAddr)>, Vec<u32>)
+    where
+        S: Fn(u32, SocketAddr) -> F,
+        F: Future<Output = Result<(), String>>,
+    {
+        let mut failed = Vec::new();
+        if self.pool_size(function) == 0 {
+            return (None, failed);
+        }
+        loop {
+            let warm = self.warm_replicas(function).unwrap_or_else(|e| {
+                log::error!("Failed to load warm replicas of {}: {:?}", function, e);
+                Vec::new()
+            });
+            let Some(&(replica, addr)) = warm.first() else {
+                self.count_warm(function, false);
+                return (None, failed);
+            };
+            self.forget_warm(function, replica);
+            match start(replica, addr).await {
+                Ok(()) => {
+                    log::info!("Took warm replica {} of {}", replica, function);
+                    self.count_warm(function, true);
+                    return (Some((replica, addr)), failed);
+                }
+                Err(e) => {
+                    log::warn!("Discarding warm replica {}/{}: {}", function, replica, e);
+                    failed.push(replica);
+                }
+            }
+        }
+    }
+
+    /// Replica indexes reserved for the pool of the function, not created yet
+    pub(crate) fn pending_warm(&self, function: &Endpoint) -> Vec<u32> {
+        self.warm_pending
+            .lock()
+            .unwrap()
+            .get(&function.to_string())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Reserve the `count` lowest indexes neither in `used` nor already reserved,
+    /// called under the wake lock
+    fn reserve_warm(&self, function: &Endpoint, used: &[u32], count: usize) -> Vec<u32> {
+        let mut pending = self.warm_pending.lock().unwrap();
+        let pending = pending.entry(function.to_string()).or_default();
+        let mut reserved = Vec::with_capacity(count);
+        let mut replica = 0;
+        while reserved.len() < count {
+            if !used.contains(&replica) && !pending.contains(&replica) {
+                pending.push(replica);
+                reserved.push(replica);
+            }
+            replica += 1;
+        }
+        reserved
+    }
+
+    fn release_warm(&self, function: &Endpoint, replica: u32) {
+        let mut pending = self.warm_pending.lock().unwrap();
+        if let Some(reserved) = pending.get_mut(&function.to_string()) {
+            reserved.retain(|reserved| *reserved != replica);
+            if reserved.is_empty() {
+                pending.remove(&function.to_string());
+            }
+        }
+    }
+
+    /// Release the reservation of a prepared replica and add it to the pool,
+    /// called under the wake lock. `false` if the pool does not want it anymore,
+    /// the function was deleted or its pool shrunk meanwhile.
+    fn publish_warm(
+        &self,
+        function: &Endpoint,
+        replica: u32,
+        addr: SocketAddr,
+    ) -> Result<bool, String> {
+        self.release_warm(function, replica);
+        let ready = self
+            .warm_replicas(function)
+            .map_err(|e| e.to_string())?
+            .len();
+        if ready >= self.pool_size(function) as usize {
+            return Ok(false);
+        }
+        self.record_warm(function, replica, addr)
+            .map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    /// Prepare replicas until the pool of the function is full again. The
+    /// indexes are reserved under the wake lock, the replicas are created
+    /// without it and published under it again.
+    pub(crate) async fn fill_warm_pool(&self, function: &Endpoint) -> Result<(), String> {
+        let lock = self.wake_lock(function);
+        let (template, reserved) = {
+            let _reserving = lock.lock().await;
+            let size = self.pool_size(function) as usize;
+            let ready = self
+                .warm_replicas(function)
+                .map_err(|e| e.to_string())?
+                .len()
+                + self.pending_warm(function).len();
+            if ready >= size {
+                return Ok(());
+            }
+            let containers = backend()
+                .list_function_containers(function)
+                .await
+                .map_err(|e| e.to_string())?;
+            let Some(template) = containers.into_iter().next() else {
+                // deleted meanwhile
+                return Ok(());
+            };
+            let used = self.instance_replicas(function).await?;
+            (template, self.reserve_warm(function, &used, size - ready))
+        };
+
+        let mut reserved = reserved.into_iter();
+        let mut failure = None;
+        for replica in reserved.by_ref() {
+            let prepared = match self.replica_like(function, &template, replica) {
+                Ok(metadata) => self
+                    .create_warm_instance(&metadata)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            let _publishing = lock.lock().await;
+            let addr = match prepared {
+                Ok(addr) => addr,
+                Err(e) => {
+                    self.release_warm(function, replica);
+                    failure = Some(e);
+                    break;
+                }
+            };
+            let published = self.publish_warm(function, replica, addr);
+            if matches!(published, Ok(true)) {
+                continue;
+            }
+            log::info!(
+                "Warm replica {}/{} is not needed anymore",
+                function,
+                replica
+            );
+            if let Err(e) = self.remove_instance(function, replica).await {
+                log::error!(
+                    "Failed to remove warm replica {}/{}: {:?}",
+                    function,
+                    replica,
+                    e
+                );
+            }
+            if let Err(e) = published {
+                failure = Some(e);
+                break;
+            }
+        }
+        if let Some(e) = failure {
+            // the indexes left were never used
+            let _releasing = lock.lock().await;
+            for replica in reserved {
+                self.release_warm(function, replica);
+            }
+            return Err(e);
+        }
+        log::info!("Warm pool of {} filled", function);
+        Ok(())
+    }
+
+    /// Top up the pools taken from every `interval`, for as long as the daemon runs
+    pub fn spawn_warm_pool_controller(self: &Arc<Self>) {
+        let provider = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(consts::WARM_POOL_CHECK_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                provider.fill_warm_pools().await;
+            }
+        });
+    }
+
+    async fn fill_warm_pools(&self) {
+        let records: Vec<PoolRecord> = match self.database.open_tree(WARM_POOL_TREE) {
+            Ok(tree) => tree
+                .iter()
+                .values()
+                .filter_map(|value| value.ok())
+                .filter_map(|value| serde_json::from_slice(&value).ok())
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to load warm pools: {:?}", e);
+                return;
+            }
+        };
+        for record in records {
+            let function = Endpoint::new(&record.service, &record.namespace);
+            if let Err(e) = self.fill_warm_pool(&function).await {
+                log::error!("Failed to fill warm pool of {}: {}", function, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::Mutex,
+    };
+
+    use crate::{
+        consts,
+        impls::cni::Endpoint,
+        provider::test::{deployment, provider},
+    };
+
+    fn addr(last: u8) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::new(10, 66, 0, last), 8080))
+    }
+
+    #[tokio::test]
+    async fn test_warm_pool() {
+        let provider = provider();
+        let function = Endpoint::new("echo", "faasrs-default");
+        provider.save_min_warm(&function, 3).unwrap();
+
+        // refill: replicas 0 and 1 serve, 2 is ready, two more are reserved past them
+        provider.record_warm(&function, 2, addr(2)).unwrap();
+        let reserved = provider.reserve_warm(&function, &[0, 1, 2], 2);
+        assert_eq!(reserved, vec![3, 4]);
+        // a concurrent fill or scale up does not get them
+        assert_eq!(provider.reserve_warm(&function, &[0, 1, 2], 1), vec![5]);
+        provider.release_warm(&function, 5);
+        assert_eq!(provider.pending_warm(&function), vec![3, 4]);
+        assert!(provider.publish_warm(&function, 3, addr(3)).unwrap());
+        assert_eq!(provider.pending_warm(&function), vec![4]);
+
+        // discard: the pool shrank while replica 4 was prepared
+        provider.save_min_warm(&function, 2).unwrap();
+        assert!(!provider.publish_warm(&function, 4, addr(4)).unwrap());
+        assert!(provider.pending_warm(&function).is_empty());
+        assert_eq!(
+            provider.warm_replicas(&function).unwrap(),
+            vec![(2, addr(2)), (3, addr(3))]
+        );
+
+        // take: replica 2 fails to start and is handed back for removal, 3 is taken
+        let started = Mutex::new(Vec::new());
+        let (taken, failed) = provider
+            .take_warm_with(&function, |replica, _| {
+                started.lock().unwrap().push(replica);
+                async move {
+                    if replica == 2 {
+                        Err("task failed".to_string())
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await;
+        assert_eq!(taken, Some((3, addr(3))));
+        assert_eq!(failed, vec![2]);
+        assert_eq!(*started.lock().unwrap(), vec![2, 3]);
+        assert!(provider.warm_replicas(&function).unwrap().is_empty());
+
+        // dry, counted as a miss
+        let (taken, failed) = provider
+            .take_warm_with(&function, |_, _| async { Ok(()) })
+            .await;
+        assert_eq!((taken, failed), (None, vec![]));
+        let pool = provider.warm_pool(&function).unwrap();
+        assert_eq!(
+            (pool.size, pool.ready, pool.hits, pool.misses),
+            (2, 0, 1, 1)
+        );
+
+        // claim: a scale up failing before starting them hands the replicas back
+        provider.record_warm(&function, 7, addr(7)).unwrap();
+        provider.record_warm(&function, 8, addr(8)).unwrap();
+        let claimed = provider.claim_warm(&function, 1);
+        assert_eq!(claimed, vec![(7, addr(7))]);
+        assert_eq!(
+            provider.warm_replicas(&function).unwrap(),
+            vec![(8, addr(8))]
+        );
+        provider.return_warm(&function, &claimed);
+        assert_eq!(
+            provider.warm_replicas(&function).unwrap(),
+            vec![(7, addr(7)), (8, addr(8))]
+        );
+        // more than the pool has, counted as a miss
+        assert_eq!(provider.claim_warm(&function, 3).len(), 2);
+        assert_eq!(provider.warm_pool(&function).unwrap().misses, 2);
+
+        // no pool, nothing is taken nor counted
+        provider.forget_warm_pool(&function);
+        provider.record_warm(&function, 6, addr(6)).unwrap();
+        let (taken, _) = provider
+            .take_warm_with(&function, |_, _| async { Ok(()) })
+            .await;
+        assert_eq!(taken, None);
+    }
+
+    #[test]
+    fn test_min_warm() {
+        assert_eq!(super::min_warm(&deployment(&[])).unwrap(), 0);
+        assert_eq!(
+            super::min_warm(&deployment(&[(consts::MIN_WARM_LABEL, "2")])).unwrap(),
+            2
+        );
+        assert!(super::min_warm(&deployment(&[(consts::MIN_WARM_LABEL, "-1")])).is_err());
+        assert!(super::min_warm(&deployment(&[(consts::MIN_WARM_LABEL, "two")])).is_err());
+    }
+}
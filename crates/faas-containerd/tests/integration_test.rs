@@ -1,17 +1,37 @@
 use actix_web::App;
 use actix_web::http::StatusCode;
 use actix_web::test;
-use faas_containerd::consts::DEFAULT_FAASDRS_DATA_DIR;
-use gateway::bootstrap::config_app;
+use faas_containerd::config::Config;
+use gateway::bootstrap::{AppState, config_app};
 use serde_json::json;
 
 #[actix_web::test]
 #[ignore]
 async fn test_handlers_in_order() {
     dotenv::dotenv().ok();
-    faas_containerd::init_backend().await;
-    let provider = faas_containerd::provider::ContainerdProvider::new(DEFAULT_FAASDRS_DATA_DIR);
-    let app = test::init_service(App::new().configure(config_app(provider))).await;
+    let mut config = Config::load().unwrap();
+    config.gateway.enable_health = true;
+    faas_containerd::init_backend(&config).await;
+    let provider =
+        faas_containerd::provider::ContainerdProvider::new(&config.containerd.data_dir).unwrap();
+    let app = test::init_service(
+        App::new().configure(config_app(provider, AppState::new(config.gateway).unwrap())),
+    )
+    .await;
+
+    // test healthz, containerd, database and cni should all be up
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // test system info
+    let req = test::TestRequest::get().uri("/system/info").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let response_body = test::read_body(resp).await;
+    let response_json: serde_json::Value = serde_json::from_slice(&response_body).unwrap();
+    assert_eq!(response_json["provider"], "faas-containerd");
+    assert_eq!(response_json["orchestration"], "containerd");
 
     // test proxy no-found-function in namespace 'faasrs-test-namespace'
     let req = test::TestRequest::get()
@@ -49,6 +69,40 @@ async fn test_handlers_in_order() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
+    // test deploy into a namespace which is not created yet
+    let req = test::TestRequest::post()
+        .uri("/system/functions")
+        .set_json(json!({
+            "service": "test-function",
+            "image": "hub.scutosc.cn/dolzhuying/echo:latest",
+            "namespace": "faasrs-test-namespace"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // test create namespace 'faasrs-test-namespace'
+    let req = test::TestRequest::post()
+        .uri("/system/namespace/")
+        .set_json(json!({
+            "name": "faasrs-test-namespace",
+            "labels": { "team": "faasrs" }
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let req = test::TestRequest::get()
+        .uri("/system/namespaces")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let response_body = test::read_body(resp).await;
+    let response_json: serde_json::Value = serde_json::from_slice(&response_body).unwrap();
+    let namespaces = response_json.as_array().unwrap();
+    assert!(namespaces.contains(&json!("faasrs-test-namespace")));
+    assert!(namespaces.contains(&json!("faasrs-default")));
+
     // test deploy test-function in namespace 'faasrs-test-namespace'
     let req = test::TestRequest::post()
         .uri("/system/functions")
@@ -147,4 +201,11 @@ async fn test_handlers_in_order() {
     let response_body = test::read_body(resp).await;
     let response_str = std::str::from_utf8(&response_body).unwrap();
     assert!(response_str.contains("function test-function was deleted successfully"));
+
+    // test delete namespace 'faasrs-test-namespace'
+    let req = test::TestRequest::delete()
+        .uri("/system/namespace/faasrs-test-namespace")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
 }
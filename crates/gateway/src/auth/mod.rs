@@ -0,0 +1,202 @@
+use std::{io, path::Path};
+
+use actix_web::{Error, dev::ServiceRequest, http::header::HeaderValue, web};
+use actix_web_httpauth::{
+    extractors::{
+        AuthenticationError,
+        basic::{BasicAuth, Config},
+    },
+    headers::authorization::{Basic, Scheme},
+};
+
+use crate::bootstrap::AppState;
+
+const BASIC_AUTH_USER_FILE: &str = "basic-auth-user";
+const BASIC_AUTH_PASSWORD_FILE: &str = "basic-auth-password";
+
+/// Realm sent back in the `WWW-Authenticate` challenge
+pub const BASIC_AUTH_REALM: &str = "faasd-rs";
+
+#[derive(Debug, Clone)]
+pub struct BasicAuthCredentials {
+    pub user: String,
+    pub password: String,
+}
+
+impl BasicAuthCredentials {
+    /// Read `basic-auth-user` and `basic-auth-password` from the secret mount path
+    pub fn read_from<P: AsRef<Path>>(secret_mount_path: P) -> io::Result<Self> {
+        let read = |name: &str| -> io::Result<String> {
+            let path = secret_mount_path.as_ref().join(name);
+            let value = std::fs::read_to_string(&path).map_err(|e| {
+                log::error!("Failed to read basic auth secret {:?}: {}", path, e);
+                e
+            })?;
+            let value = value.trim().to_string();
+            if value.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("basic auth secret {:?} is empty", path),
+                ));
+            }
+            Ok(value)
+        };
+        Ok(Self {
+            user: read(BASIC_AUTH_USER_FILE)?,
+            password: read(BASIC_AUTH_PASSWORD_FILE)?,
+        })
+    }
+
+    pub(crate) fn matches(&self, user: &str, password: &str) -> bool {
+        // evaluate both sides so the response time does not reveal which one failed
+        let user_ok = constant_time_eq(self.user.as_bytes(), user.as_bytes());
+        let password_ok = constant_time_eq(self.password.as_bytes(), password.as_bytes());
+        user_ok & password_ok
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Validator for `HttpAuthentication::basic`, checks the request against the
+/// credentials loaded into [`AppState`] at startup
+pub async fn validator(req: ServiceRequest, auth: BasicAuth) -> Result<ServiceRequest, Error> {
+    let expected = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|state| state.credentials.clone());
+    let Some(expected) = expected else {
+        // auth is not enabled
+        return Ok(req);
+    };
+
+    let password = auth.password().map(|p| p.as_ref()).unwrap_or_default();
+    if expected.matches(auth.user_id(), password) {
+        Ok(req)
+    } else {
+        log::warn!("Basic auth failed for {} {}", req.method(), req.path());
+        let config = req.app_data::<Config>().cloned().unwrap_or_default();
+        Err(AuthenticationError::from(config).into())
+    }
+}
+
+/// Check the `Authorization` header of an invocation, the h2c front has no
+/// middleware so the proxy checks its callers itself
+pub(crate) fn authorize(
+    expected: &BasicAuthCredentials,
+    authorization: Option<&HeaderValue>,
+) -> Result<(), Error> {
+    let basic = authorization.and_then(|value| Basic::parse(value).ok());
+    match basic {
+        Some(basic)
+            if expected.matches(
+                basic.user_id(),
+                basic.password().map(|p| p.as_ref()).unwrap_or_default(),
+            ) =>
+        {
+            Ok(())
+        }
+        _ => {
+            let config = Config::default().realm(BASIC_AUTH_REALM);
+            Err(AuthenticationError::from(config).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bootstrap::AppState, types::config::FaaSConfig};
+
+    use actix_web::{App, HttpResponse, http::header};
+    use actix_web_httpauth::middleware::HttpAuthentication;
+
+    fn write_secrets(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(BASIC_AUTH_USER_FILE), "admin\n").unwrap();
+        std::fs::write(dir.join(BASIC_AUTH_PASSWORD_FILE), "secret\n").unwrap();
+    }
+
+    #[test]
+    fn test_read_credentials() {
+        let dir = std::env::temp_dir().join(format!("faasrs-auth-read-{}", std::process::id()));
+        write_secrets(&dir);
+        let credentials = BasicAuthCredentials::read_from(&dir).unwrap();
+        assert_eq!(credentials.user, "admin");
+        assert_eq!(credentials.password, "secret");
+        assert!(BasicAuthCredentials::read_from(dir.join("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_authorize() {
+        let expected = BasicAuthCredentials {
+            user: "admin".to_string(),
+            password: "secret".to_string(),
+        };
+        let header = |value| HeaderValue::from_static(value);
+        assert!(authorize(&expected, Some(&header("Basic YWRtaW46c2VjcmV0"))).is_ok());
+        assert!(authorize(&expected, Some(&header("Basic YWRtaW46d3Jvbmc="))).is_err());
+        assert!(authorize(&expected, Some(&header("Bearer YWRtaW46c2VjcmV0"))).is_err());
+        let err = authorize(&expected, None).unwrap_err();
+        let resp = err.error_response();
+        assert_eq!(resp.status(), 401);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[actix_web::test]
+    async fn test_basic_auth() {
+        use actix_web::test;
+
+        let dir = std::env::temp_dir().join(format!("faasrs-auth-mw-{}", std::process::id()));
+        write_secrets(&dir);
+        let config = FaaSConfig {
+            enable_basic_auth: true,
+            secret_mount_path: dir.to_string_lossy().to_string(),
+            ..FaaSConfig::new()
+        };
+        let state = AppState::new(config).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .app_data(Config::default().realm(BASIC_AUTH_REALM))
+                .service(
+                    web::scope("/system")
+                        .wrap(HttpAuthentication::basic(validator))
+                        .route("/functions", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/system/functions")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        // base64("admin:wrong")
+        let req = test::TestRequest::get()
+            .uri("/system/functions")
+            .insert_header((header::AUTHORIZATION, "Basic YWRtaW46d3Jvbmc="))
+            .to_request();
+        // rejected by the validator, the server turns the error into the 401 response
+        let err = test::try_call_service(&app, req).await.unwrap_err();
+        let resp = err.error_response();
+        assert_eq!(resp.status(), 401);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        // base64("admin:secret")
+        let req = test::TestRequest::get()
+            .uri("/system/functions")
+            .insert_header((header::AUTHORIZATION, "Basic YWRtaW46c2VjcmV0"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+}
@@ -1,31 +1,58 @@
+use actix_http::HttpService;
+use actix_service::{
+    Service, ServiceFactory, ServiceFactoryExt, boxed, fn_factory, fn_service, map_config,
+};
 use actix_web::{
-    App, HttpServer,
-    dev::Server,
+    App,
+    dev::{AppConfig, Server},
+    middleware::{Condition, from_fn},
     web::{self, ServiceConfig},
 };
+use actix_web_httpauth::{extractors::basic, middleware::HttpAuthentication};
+
+use std::{rc::Rc, sync::Arc};
 
-use std::{collections::HashMap, sync::Arc};
+use tokio::net::TcpStream;
 
 use crate::{
-    handlers::{self, proxy::PROXY_DISPATCH_PATH},
-    // metrics::HttpMetrics,
+    auth::{self, BasicAuthCredentials},
+    handlers::{
+        self,
+        proxy::{FUNCTION_SCOPE, PROXY_DISPATCH_PATH},
+    },
+    metrics::{self, HttpMetrics},
     provider::Provider,
+    proxy::{
+        self,
+        h2::H2Pool,
+        h2c::{self, H2cFront},
+    },
     types::config::FaaSConfig,
 };
 
-pub fn config_app<P: Provider>(provider: Arc<P>) -> impl FnOnce(&mut ServiceConfig) {
-    // let _registry = Registry::new();
-
+pub fn config_app<P: Provider>(
+    provider: Arc<P>,
+    state: AppState,
+) -> impl FnOnce(&mut ServiceConfig) {
     let provider = web::Data::from(provider);
-    let app_state = web::Data::new(AppState {
-        // metrics: HttpMetrics::new(),
-        credentials: None,
-    });
+    let auth_system = state.credentials.is_some();
+    let enable_health = state.config.enable_health;
+    let app_state = web::Data::new(state);
     move |cfg: &mut ServiceConfig| {
+        // called once per worker, each worker gets its own connection pool
+        let proxy_client = web::Data::new(proxy::builder::new_proxy_client(&app_state.config));
+        let h2_pool = web::Data::new(H2Pool::new(app_state.config.get_connect_timeout()));
         cfg.app_data(app_state)
+            .app_data(proxy_client)
+            .app_data(h2_pool)
             .app_data(provider)
+            .app_data(basic::Config::default().realm(auth::BASIC_AUTH_REALM))
             .service(
                 web::scope("/system")
+                    .wrap(Condition::new(
+                        auth_system,
+                        HttpAuthentication::basic(auth::validator),
+                    ))
                     .service(
                         web::resource("/functions")
                             .route(web::get().to(handlers::function::list::<P>))
@@ -36,55 +63,146 @@ pub fn config_app<P: Provider>(provider: Arc<P>) -> impl FnOnce(&mut ServiceConf
                     .service(
                         web::resource("/function/{functionName}")
                             .route(web::get().to(handlers::function::status::<P>)),
-                    ), //         .service(
-                       //             web::resource("/scale-function/{name}")
-                       //                 .route(web::post().to(handlers::scale_function)),
-                       //         )
-                       //         .service(web::resource("/info").route(web::get().to(handlers::info)))
-                       //         .service(
-                       //             web::resource("/secrets")
-                       //                 .route(web::get().to(handlers::secrets))
-                       //                 .route(web::post().to(handlers::secrets))
-                       //                 .route(web::put().to(handlers::secrets))
-                       //                 .route(web::delete().to(handlers::secrets)),
-                       //         )
-                       //         .service(web::resource("/logs").route(web::get().to(handlers::logs)))
-                       //         .service(
-                       //             web::resource("/namespaces")
-                       //                 .route(web::get().to(handlers::list_namespaces))
-                       //                 .route(web::post().to(handlers::mutate_namespace)),
-                       //         ),
-                       // )
+                    )
+                    .service(
+                        web::resource("/scale-function/{functionName}")
+                            .route(web::post().to(handlers::function::scale::<P>)),
+                    )
+                    .service(web::resource("/info").route(web::get().to(handlers::info::info::<P>)))
+                    .service(web::resource("/gc").route(web::post().to(handlers::gc::gc::<P>)))
+                    .service(
+                        web::resource("/secrets")
+                            .route(web::get().to(handlers::secret::list::<P>))
+                            .route(web::post().to(handlers::secret::create::<P>))
+                            .route(web::put().to(handlers::secret::update::<P>))
+                            .route(web::delete().to(handlers::secret::delete::<P>)),
+                    )
+                    .service(web::resource("/logs").route(web::get().to(handlers::logs::logs::<P>)))
+                    .service(
+                        web::resource("/namespaces")
+                            .route(web::get().to(handlers::namespace::list::<P>)),
+                    )
+                    .service(
+                        web::resource("/namespace/")
+                            .route(web::post().to(handlers::namespace::create::<P>)),
+                    )
+                    .service(
+                        web::resource("/namespace/{name}")
+                            .route(web::put().to(handlers::namespace::update::<P>))
+                            .route(web::delete().to(handlers::namespace::delete::<P>)),
+                    ),
             )
-            .service(web::scope("/function").service(
-                web::resource(PROXY_DISPATCH_PATH).route(web::to(handlers::proxy::proxy::<P>)),
-            ));
-        // .route("/metrics", web::get().to(handlers::telemetry))
-        // .route("/healthz", web::get().to(handlers::health));
+            .service(
+                // the invocations are authorized by the proxy, as on the h2c front
+                web::scope(FUNCTION_SCOPE)
+                    .service(
+                        web::resource(["", "/"]).route(web::to(handlers::proxy::missing_function)),
+                    )
+                    .service(
+                        web::resource(PROXY_DISPATCH_PATH)
+                            .route(web::to(handlers::proxy::proxy::<P>)),
+                    ),
+            )
+            .route("/metrics", web::get().to(handlers::telemetry::telemetry));
+        if enable_health {
+            cfg.route("/healthz", web::get().to(handlers::health::health::<P>));
+        }
     }
 }
 
 //应用程序状态，存储共享的数据，如配置、指标、认证信息等，为业务函数提供支持
 #[derive(Clone)]
-#[allow(dead_code)]
-struct AppState {
-    // config: FaaSConfig,   //应用程序的配置，用于识别是否开启Basic Auth等
-    // metrics: HttpMetrics, //用于监视http请求的持续时间和总数
-    // metrics: HttpMetrics, //用于监视http请求的持续时间和总数
-    credentials: Option<HashMap<String, String>>, //当有认证信息的时候，获取认证信息
+pub struct AppState {
+    pub(crate) config: FaaSConfig, //应用程序的配置，用于识别是否开启Basic Auth等
+    pub(crate) metrics: HttpMetrics, //用于监视http请求的持续时间和总数
+    pub(crate) credentials: Option<BasicAuthCredentials>, //当有认证信息的时候，获取认证信息
+}
+
+impl AppState {
+    /// 如果启用了Basic Auth，从 `secret_mount_path` 读取认证凭证
+    pub fn new(config: FaaSConfig) -> std::io::Result<Self> {
+        for warning in config.ignored_settings() {
+            log::warn!("{}", warning);
+        }
+        let credentials = if config.enable_basic_auth {
+            let credentials = BasicAuthCredentials::read_from(&config.secret_mount_path)?;
+            log::info!(
+                "Basic auth enabled, credentials loaded from {}",
+                config.secret_mount_path
+            );
+            Some(credentials)
+        } else {
+            None
+        };
+        Ok(Self {
+            config,
+            metrics: HttpMetrics::new(),
+            credentials,
+        })
+    }
 }
 
 // this is a blocking serve function
-pub fn serve<P: Provider>(provider: Arc<P>) -> std::io::Result<Server> {
-    log::info!("Checking config file");
-    let config = FaaSConfig::new();
+pub fn serve<P: Provider>(provider: Arc<P>, config: FaaSConfig) -> std::io::Result<Server> {
     let port = config.tcp_port.unwrap_or(8080);
+    let listener = std::net::TcpListener::bind(("0.0.0.0", port))?;
+    serve_on(listener, provider, config)
+}
+
+/// [`serve`] on a socket bound by the caller
+pub(crate) fn serve_on<P: Provider>(
+    listener: std::net::TcpListener,
+    provider: Arc<P>,
+    config: FaaSConfig,
+) -> std::io::Result<Server> {
+    let read_timeout = config.get_read_timeout();
 
     // 如果启用了Basic Auth，从指定路径读取认证凭证并存储在应用程序状态中
-    // TODO: Authentication Logic
+    let state = AppState::new(config)?;
 
-    let server = HttpServer::new(move || App::new().configure(config_app(provider.clone())))
-        .bind(("0.0.0.0", port))?
+    // actix can not send trailers, the connections opening with the HTTP/2 preface
+    // go to the h2c front, the others to the actix app as HttpServer would do
+    let local_addr = listener.local_addr()?;
+    let server = Server::build()
+        .listen("faasrs", listener, move || {
+            let app = || {
+                App::new()
+                    .wrap(from_fn(metrics::track_http))
+                    .configure(config_app(provider.clone(), state.clone()))
+            };
+            let http = HttpService::build()
+                .client_request_timeout(read_timeout)
+                .local_addr(local_addr)
+                .finish(map_config(app(), |_| AppConfig::default()))
+                .tcp();
+            // the front serves the requests other than the invocations with its own app
+            let front_app = map_config(app(), |_| AppConfig::default())
+                .map(|response| response.map_into_boxed_body().into());
+            let (provider, state) = (provider.clone(), state.clone());
+            fn_factory(move || {
+                let http = http.new_service(());
+                let front_app = front_app.new_service(());
+                let (provider, state) = (provider.clone(), state.clone());
+                async move {
+                    let http = Rc::new(http.await?);
+                    let front_app = boxed::rc_service(front_app.await?);
+                    let front = Rc::new(H2cFront::new(provider, state, front_app));
+                    Ok::<_, ()>(fn_service(move |io: TcpStream| {
+                        let (http, front) = (http.clone(), front.clone());
+                        async move {
+                            let h2c = tokio::time::timeout(read_timeout, h2c::is_h2c(&io)).await;
+                            if matches!(h2c, Ok(true)) {
+                                front.serve(io).await;
+                                return Ok(());
+                            }
+                            http.call(io).await.map_err(|e| {
+                                log::debug!("HTTP connection failed: {:?}", e);
+                            })
+                        }
+                    }))
+                }
+            })
+        })?
         .run();
 
     Ok(server)
@@ -96,7 +214,7 @@ mod tests {
 
     use crate::handlers::proxy::{PROXY_DISPATCH_PATH, ProxyQuery};
 
-    use actix_web::{App, HttpResponse, Responder, test, web};
+    use actix_web::{App, HttpRequest, HttpResponse, Responder, test, web};
 
     async fn dispatcher(any: web::Path<String>) -> impl Responder {
         let meta = ProxyQuery::from_str(&any).unwrap();
@@ -172,4 +290,39 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 404);
     }
+
+    async fn raw_dispatcher(req: HttpRequest) -> impl Responder {
+        let meta = ProxyQuery::from_request(&req).unwrap();
+        HttpResponse::Ok().body(format!("{}|{}", meta.query.service, meta.path))
+    }
+
+    #[actix_web::test]
+    async fn test_proxy_raw_path_and_query() {
+        let app =
+            test::init_service(App::new().service(
+                web::scope("/function").service(
+                    web::resource(PROXY_DISPATCH_PATH).route(web::get().to(raw_dispatcher)),
+                ),
+            ))
+            .await;
+
+        let cases = [
+            ("/function/service", "service|"),
+            ("/function/service?", "service|/?"),
+            ("/function/service/?", "service|/?"),
+            ("/function/service/path?a=1&b=", "service|/path?a=1&b="),
+            ("/function/service/a%2Fb%2Fc", "service|/a%2Fb%2Fc"),
+            ("/function/service/%2F", "service|/%2F"),
+            (
+                "/function/service/caf%C3%A9?q=%E2%9C%93",
+                "service|/caf%C3%A9?q=%E2%9C%93",
+            ),
+            ("/function/service/%25?%26=%3D", "service|/%25?%26=%3D"),
+        ];
+        for (uri, expected) in cases {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_and_read_body(&app, req).await;
+            assert_eq!(resp, expected, "{}", uri);
+        }
+    }
 }
@@ -76,6 +76,40 @@ pub async fn status<P: Provider>(
     Ok(HttpResponse::Ok().json(status))
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaleServiceRequest {
+    service_name: Option<String>,
+    service_namespace: Option<String>,
+    replicas: u32,
+}
+
+pub async fn scale<P: Provider>(
+    provider: web::Data<P>,
+    name: web::Path<String>,
+    info: web::Query<StatusParam>,
+    body: web::Json<ScaleServiceRequest>,
+) -> Result<HttpResponse, ScaleError> {
+    let service = name.into_inner();
+    if body.service_name.as_ref().is_some_and(|s| *s != service) {
+        return Err(ScaleError::Invalid(format!(
+            "service name in body does not match {}",
+            service
+        )));
+    }
+    let query = Query {
+        service: service.clone(),
+        namespace: body.0.service_namespace.or(info.namespace.clone()),
+    };
+    let replicas = body.0.replicas;
+    (*provider).scale(query, replicas).await.map(|()| {
+        HttpResponse::Accepted().body(format!(
+            "function {} was scaled to {} replicas",
+            service, replicas
+        ))
+    })
+}
+
 // TODO: 为 Errors 添加错误信息
 
 #[derive(Debug, Display)]
@@ -124,6 +158,16 @@ pub enum UpdateError {
     NotFound(String),
 }
 
+#[derive(Debug, Display)]
+pub enum ScaleError {
+    #[display("Invalid: {}", _0)]
+    Invalid(String),
+    #[display("NotFound: {}", _0)]
+    NotFound(String),
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
 impl ResponseError for DeployError {
     fn status_code(&self) -> StatusCode {
         match self {
@@ -171,3 +215,13 @@ impl ResponseError for UpdateError {
         }
     }
 }
+
+impl ResponseError for ScaleError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScaleError::Invalid(_) => StatusCode::BAD_REQUEST,
+            ScaleError::NotFound(_) => StatusCode::NOT_FOUND,
+            ScaleError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
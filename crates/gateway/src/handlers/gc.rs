@@ -0,0 +1,39 @@
+use actix_http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use derive_more::derive::Display;
+use serde::Deserialize;
+
+use crate::provider::Provider;
+
+#[derive(Debug, Deserialize)]
+pub struct GcParam {
+    /// Only report what would be removed
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub async fn gc<P: Provider>(
+    provider: web::Data<P>,
+    param: web::Query<GcParam>,
+) -> Result<HttpResponse, GcError> {
+    let report = (*provider).gc(param.dry_run).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Debug, Display)]
+pub enum GcError {
+    /// Another collection is still running
+    #[display("Conflict: {}", _0)]
+    Conflict(String),
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
+impl ResponseError for GcError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            GcError::Conflict(_) => StatusCode::CONFLICT,
+            GcError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use actix_web::{HttpResponse, web};
+
+use crate::provider::Provider;
+
+/// `GET /healthz`, 200 when every component of the provider is healthy, 503 otherwise
+pub async fn health<P: Provider>(provider: web::Data<P>) -> HttpResponse {
+    let status = (*provider).health().await;
+    if status.healthy {
+        HttpResponse::Ok().json(status)
+    } else {
+        log::warn!("Health check failed: {:?}", status);
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
@@ -0,0 +1,24 @@
+use actix_http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use derive_more::derive::Display;
+
+use crate::provider::Provider;
+
+pub async fn info<P: Provider>(provider: web::Data<P>) -> Result<HttpResponse, InfoError> {
+    let info = (*provider).info().await?;
+    Ok(HttpResponse::Ok().json(info))
+}
+
+#[derive(Debug, Display)]
+pub enum InfoError {
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
+impl ResponseError for InfoError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InfoError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::provider::Provider;
+use crate::types::function::Query;
+use actix_http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use chrono::{DateTime, Utc};
+use derive_more::derive::Display;
+use futures::StreamExt;
+use serde::Deserialize;
+
+// 参考 https://github.com/openfaas/faas-provider/blob/master/logs/handler.go
+
+#[derive(Debug, Deserialize)]
+pub struct LogParam {
+    name: String,
+    namespace: Option<String>,
+    /// RFC 3339 time, only return the lines written after it
+    since: Option<String>,
+    /// Only return the last `tail` lines
+    tail: Option<usize>,
+    #[serde(default)]
+    follow: bool,
+}
+
+/// 以 NDJSON 流的形式返回函数日志，`follow` 时持续输出新日志直到客户端断开
+pub async fn logs<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Query<LogParam>,
+) -> Result<HttpResponse, LogError> {
+    let LogParam {
+        name,
+        namespace,
+        since,
+        tail,
+        follow,
+    } = info.into_inner();
+    if name.is_empty() {
+        return Err(LogError::Invalid("name is required".to_string()));
+    }
+    let since = since
+        .map(|since| {
+            DateTime::parse_from_rfc3339(&since)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| LogError::Invalid(format!("invalid since {}: {}", since, e)))
+        })
+        .transpose()?;
+    let query = Query {
+        service: name,
+        namespace,
+    };
+
+    let messages = (*provider).logs(query, since, tail, follow).await?;
+    let body = messages.map(|message| {
+        let mut line = serde_json::to_vec(&message)?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(web::Bytes::from(line))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body))
+}
+
+#[derive(Debug, Display)]
+pub enum LogError {
+    #[display("Invalid: {}", _0)]
+    Invalid(String),
+    #[display("NotFound: {}", _0)]
+    NotFound(String),
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
+impl ResponseError for LogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LogError::Invalid(_) => StatusCode::BAD_REQUEST,
+            LogError::NotFound(_) => StatusCode::NOT_FOUND,
+            LogError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -1,5 +1,12 @@
 pub mod function;
+pub mod gc;
+pub mod health;
+pub mod info;
+pub mod logs;
+pub mod namespace;
 pub mod proxy;
+pub mod secret;
+pub mod telemetry;
 
 #[derive(Debug, thiserror::Error)]
 pub struct FaasError {
@@ -0,0 +1,80 @@
+use crate::provider::Provider;
+use crate::types::namespace::FunctionNamespace;
+use actix_http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use derive_more::derive::Display;
+
+// 参考 https://github.com/openfaas/faas-provider/blob/master/serve.go 中 namespace 相关路由
+
+pub async fn list<P: Provider>(provider: web::Data<P>) -> Result<HttpResponse, NamespaceError> {
+    (*provider)
+        .list_namespaces()
+        .await
+        .map(|namespaces| HttpResponse::Ok().json(namespaces))
+}
+
+pub async fn create<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Json<FunctionNamespace>,
+) -> Result<HttpResponse, NamespaceError> {
+    let name = info.0.name.clone();
+    (*provider)
+        .create_namespace(info.0)
+        .await
+        .map(|()| HttpResponse::Created().body(format!("namespace {} was created", name)))
+}
+
+pub async fn update<P: Provider>(
+    provider: web::Data<P>,
+    name: web::Path<String>,
+    info: web::Json<FunctionNamespace>,
+) -> Result<HttpResponse, NamespaceError> {
+    let name = name.into_inner();
+    let mut namespace = info.into_inner();
+    if namespace.name.is_empty() {
+        namespace.name = name.clone();
+    } else if namespace.name != name {
+        return Err(NamespaceError::Invalid(format!(
+            "namespace name {} does not match the path {}",
+            namespace.name, name
+        )));
+    }
+    (*provider)
+        .update_namespace(namespace)
+        .await
+        .map(|()| HttpResponse::Accepted().body(format!("namespace {} was updated", name)))
+}
+
+pub async fn delete<P: Provider>(
+    provider: web::Data<P>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, NamespaceError> {
+    let name = name.into_inner();
+    (*provider)
+        .delete_namespace(name.clone())
+        .await
+        .map(|()| HttpResponse::Accepted().body(format!("namespace {} was deleted", name)))
+}
+
+#[derive(Debug, Display)]
+pub enum NamespaceError {
+    #[display("Invalid: {}", _0)]
+    Invalid(String),
+    #[display("NotFound: {}", _0)]
+    NotFound(String),
+    #[display("Conflict: {}", _0)]
+    Conflict(String),
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
+impl ResponseError for NamespaceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            NamespaceError::Invalid(_) => StatusCode::BAD_REQUEST,
+            NamespaceError::NotFound(_) => StatusCode::NOT_FOUND,
+            NamespaceError::Conflict(_) => StatusCode::CONFLICT,
+            NamespaceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -1,10 +1,30 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Instant};
 
-use actix_http::Method;
-use actix_web::{HttpRequest, HttpResponse, error::ErrorMethodNotAllowed, web};
+use actix_http::{Method, StatusCode};
+use actix_web::{
+    HttpRequest, HttpResponse,
+    error::{ErrorBadRequest, ErrorMethodNotAllowed},
+    http::header::{self, HeaderValue},
+    web,
+};
 
-use crate::{provider::Provider, proxy::proxy_handler::proxy_request, types::function::Query};
+use crate::{
+    auth,
+    bootstrap::AppState,
+    provider::Provider,
+    proxy::{
+        h2::{H2Pool, proxy_h2_request},
+        proxy_handler::proxy_request,
+        upgrade::{is_websocket_upgrade, proxy_upgrade},
+    },
+    types::{
+        function::{DEFAULT_FUNCTION_NAMESPACE, Query},
+        upstream::Upstream,
+    },
+};
 
+/// Scope of the invocations, the functions are dispatched under it
+pub const FUNCTION_SCOPE: &str = "/function";
 pub const PROXY_DISPATCH_PATH: &str = "/{any:.+}";
 
 pub struct ProxyQuery {
@@ -34,34 +54,131 @@ impl FromStr for ProxyQuery {
     }
 }
 
+impl ProxyQuery {
+    /// Parse the invocation as received, the path suffix keeps its percent-encoding
+    /// and the query string is appended verbatim, an empty one included
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        // the dispatcher may be mounted under a scope, e.g. `/function`
+        let prefix = req.match_pattern().and_then(|pattern| {
+            pattern
+                .strip_suffix(PROXY_DISPATCH_PATH)
+                .map(|prefix| prefix.len())
+        })?;
+        let path = req.uri().path().get(prefix..)?;
+        Self::from_raw(path, req.uri().query())
+    }
+
+    /// Parse `/{function}{path}` and the query string as they are on the wire
+    pub fn from_raw(path: &str, query: Option<&str>) -> Option<Self> {
+        let raw = path.strip_prefix('/').filter(|path| !path.is_empty())?;
+        let mut meta = ProxyQuery::from_str(raw).ok()?;
+        if let Some(query) = query {
+            if meta.path.is_empty() {
+                meta.path.push('/');
+            }
+            meta.path = format!("{}?{}", meta.path, query);
+        }
+        Some(meta)
+    }
+}
+
+/// `function_name` of the invocation metrics, `echo` and `echo.faasrs-default`
+/// are the same function
+pub(crate) fn function_label(function: &Query) -> String {
+    let namespace = function
+        .namespace
+        .as_deref()
+        .unwrap_or(DEFAULT_FUNCTION_NAMESPACE);
+    format!("{}.{}", function.service, namespace)
+}
+
+/// `/function` and `/function/` name no function to invoke
+pub async fn missing_function() -> actix_web::Result<HttpResponse> {
+    Err(ErrorBadRequest("function name is required"))
+}
+
+/// One invocation, whichever server it came in on: the caller is authorized,
+/// the function resolved and `forward` relays the request to it. Every
+/// invocation that passed authorization is counted with its outcome.
+pub(crate) async fn invoke<P: Provider, R>(
+    provider: &P,
+    state: &AppState,
+    method: &Method,
+    authorization: Option<&HeaderValue>,
+    function: Query,
+    forward: impl AsyncFnOnce(Upstream) -> actix_web::Result<R>,
+    status: impl FnOnce(&R) -> StatusCode,
+) -> actix_web::Result<R> {
+    if state.config.enable_function_auth
+        && let Some(credentials) = &state.credentials
+    {
+        auth::authorize(credentials, authorization).inspect_err(|_| {
+            log::warn!("Basic auth failed for {} {}", method, function.service);
+        })?;
+    }
+    log::trace!("proxy query: {:?}", function);
+    let function_name = function_label(&function);
+    let started = Instant::now();
+    let resp = match *method {
+        Method::POST
+        | Method::PUT
+        | Method::DELETE
+        | Method::GET
+        | Method::PATCH
+        | Method::HEAD
+        | Method::OPTIONS => match provider.resolve(function).await {
+            Ok(upstream) => {
+                log::trace!("upstream: {:?}", upstream);
+                forward(upstream).await
+            }
+            // counted like the other failed invocations
+            Err(e) => Err(ErrorMethodNotAllowed(format!("Invalid function name {e}"))),
+        },
+        _ => Err(ErrorMethodNotAllowed("Method not allowed")),
+    };
+
+    let code = match &resp {
+        Ok(resp) => status(resp),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    state
+        .metrics
+        .observe_invocation(&function_name, code.as_u16(), started);
+    resp
+}
+
 // 主要参考源码的响应设置
 pub async fn proxy<P: Provider>(
     req: HttpRequest,
     payload: web::Payload,
     provider: web::Data<P>,
-    any: web::Path<String>,
+    state: web::Data<AppState>,
+    client: web::Data<awc::Client>,
+    h2_pool: web::Data<H2Pool>,
 ) -> actix_web::Result<HttpResponse> {
-    let meta = ProxyQuery::from_str(&any).map_err(|_| {
-        log::error!("Failed to parse path: {}", any);
+    let meta = ProxyQuery::from_request(&req).ok_or_else(|| {
+        log::error!("Failed to parse path: {}", req.uri());
         ErrorMethodNotAllowed("Invalid path")
     })?;
-    let function = meta.query;
-    log::trace!("proxy query: {:?}", function);
-    match *req.method() {
-        Method::POST
-        | Method::PUT
-        | Method::DELETE
-        | Method::GET
-        | Method::PATCH
-        | Method::HEAD
-        | Method::OPTIONS => {
-            let upstream = provider
-                .resolve(function)
-                .await
-                .map_err(|e| ErrorMethodNotAllowed(format!("Invalid function name {e}")))?;
-            log::trace!("upstream: {:?}", upstream);
-            proxy_request(&req, payload, upstream, &meta.path).await
+    let path = &meta.path;
+    let forward = async |upstream: Upstream| {
+        let default_timeout = state.config.write_timeout;
+        if is_websocket_upgrade(&req) {
+            proxy_upgrade(&req, payload, upstream, path, default_timeout).await
+        } else if upstream.protocol.is_http2() {
+            proxy_h2_request(&h2_pool, &req, payload, upstream, path, default_timeout).await
+        } else {
+            proxy_request(&client, &req, payload, upstream, path).await
         }
-        _ => Err(ErrorMethodNotAllowed("Method not allowed")),
-    }
+    };
+    invoke(
+        provider.get_ref(),
+        &state,
+        req.method(),
+        req.headers().get(header::AUTHORIZATION),
+        meta.query,
+        forward,
+        HttpResponse::status,
+    )
+    .await
 }
@@ -0,0 +1,79 @@
+use crate::provider::Provider;
+use crate::types::secret::Secret;
+use actix_http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, web};
+use derive_more::derive::Display;
+use serde::Deserialize;
+
+// 参考 https://github.com/openfaas/faas/blob/7803ea1861f2a22adcbcfa8c79ed539bc6506d5b/api-docs/spec.openapi.yml 中 /system/secrets 部分
+
+#[derive(Debug, Deserialize)]
+pub struct SecretParam {
+    namespace: Option<String>,
+}
+
+/// 只返回名称，不返回内容
+pub async fn list<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Query<SecretParam>,
+) -> Result<HttpResponse, SecretError> {
+    (*provider)
+        .list_secrets(info.into_inner().namespace)
+        .await
+        .map(|secrets| HttpResponse::Ok().json(secrets))
+}
+
+pub async fn create<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Json<Secret>,
+) -> Result<HttpResponse, SecretError> {
+    let name = info.0.name.clone();
+    (*provider)
+        .create_secret(info.0)
+        .await
+        .map(|()| HttpResponse::Created().body(format!("secret {} was created", name)))
+}
+
+pub async fn update<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Json<Secret>,
+) -> Result<HttpResponse, SecretError> {
+    let name = info.0.name.clone();
+    (*provider)
+        .update_secret(info.0)
+        .await
+        .map(|()| HttpResponse::Ok().body(format!("secret {} was updated", name)))
+}
+
+pub async fn delete<P: Provider>(
+    provider: web::Data<P>,
+    info: web::Json<Secret>,
+) -> Result<HttpResponse, SecretError> {
+    (*provider)
+        .delete_secret(info.0)
+        .await
+        .map(|()| HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Display)]
+pub enum SecretError {
+    #[display("Invalid: {}", _0)]
+    Invalid(String),
+    #[display("NotFound: {}", _0)]
+    NotFound(String),
+    #[display("Conflict: {}", _0)]
+    Conflict(String),
+    #[display("Internal: {}", _0)]
+    Internal(String),
+}
+
+impl ResponseError for SecretError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SecretError::Invalid(_) => StatusCode::BAD_REQUEST,
+            SecretError::NotFound(_) => StatusCode::NOT_FOUND,
+            SecretError::Conflict(_) => StatusCode::CONFLICT,
+            SecretError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use actix_web::{HttpResponse, error::ErrorInternalServerError, web};
+use prometheus::{Encoder, TextEncoder};
+
+use crate::bootstrap::AppState;
+
+/// `GET /metrics`, prometheus text exposition of the gateway registry
+pub async fn telemetry(state: web::Data<AppState>) -> actix_web::Result<HttpResponse> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&state.metrics.registry.gather(), &mut buffer)
+        .map_err(|e| {
+            log::error!("Failed to encode metrics: {}", e);
+            ErrorInternalServerError("Failed to encode metrics")
+        })?;
+    Ok(HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer))
+}
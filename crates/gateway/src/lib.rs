@@ -1,6 +1,7 @@
+pub mod auth;
 pub mod bootstrap;
 pub mod handlers;
-// pub mod metrics;
+pub mod metrics;
 pub mod provider;
 pub mod proxy;
 pub mod types;
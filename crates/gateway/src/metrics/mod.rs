@@ -1,14 +1,27 @@
-use lazy_static::lazy_static;
-use prometheus::{self, register_histogram_vec, register_int_counter_vec};
+use std::time::Instant;
 
-lazy_static! {
-    pub static ref HTTP_METRICS: HttpMetrics = HttpMetrics::new();
-}
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+use crate::bootstrap::AppState;
 
+/// Collectors exposed on `GET /metrics`, all registered into a dedicated registry
+/// instead of the prometheus default one
 #[derive(Clone)]
 pub struct HttpMetrics {
-    pub request_duration: prometheus::HistogramVec,
-    pub requests_total: prometheus::IntCounterVec,
+    pub registry: Registry,
+    pub request_duration: HistogramVec,
+    pub requests_total: IntCounterVec,
+    /// OpenFaaS compatible `gateway_function_invocation_total{function_name,code}`
+    pub function_invocation_total: IntCounterVec,
+    /// OpenFaaS compatible `gateway_functions_seconds{function_name,code}`
+    pub function_seconds: HistogramVec,
 }
 
 impl Default for HttpMetrics {
@@ -19,19 +32,143 @@ impl Default for HttpMetrics {
 
 impl HttpMetrics {
     pub fn new() -> Self {
-        Self {
-            request_duration: register_histogram_vec!(
+        let registry = Registry::new();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
                 "http_request_duration_seconds",
                 "Request duration in seconds",
-                &["method", "path", "status"]
-            )
-            .unwrap(),
-            requests_total: register_int_counter_vec!(
-                "http_requests_total",
-                "Total number of HTTP requests",
-                &["method", "path", "status"]
-            )
-            .unwrap(),
+            ),
+            &["method", "path", "status"],
+        )
+        .unwrap();
+        let requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total number of HTTP requests"),
+            &["method", "path", "status"],
+        )
+        .unwrap();
+        let function_invocation_total = IntCounterVec::new(
+            Opts::new(
+                "gateway_function_invocation_total",
+                "Function metrics: invocations",
+            ),
+            &["function_name", "code"],
+        )
+        .unwrap();
+        let function_seconds = HistogramVec::new(
+            HistogramOpts::new("gateway_functions_seconds", "Function time taken"),
+            &["function_name", "code"],
+        )
+        .unwrap();
+
+        // names are unique inside this registry, registration can not fail
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(requests_total.clone())).unwrap();
+        registry
+            .register(Box::new(function_invocation_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(function_seconds.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            request_duration,
+            requests_total,
+            function_invocation_total,
+            function_seconds,
         }
     }
+
+    pub fn observe_invocation(&self, function_name: &str, code: u16, started: Instant) {
+        let code = code.to_string();
+        let labels = [function_name, code.as_str()];
+        self.function_invocation_total
+            .with_label_values(&labels)
+            .inc();
+        self.function_seconds
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    /// `path` is the route pattern, not the path requested
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, started: Instant) {
+        let status = status.to_string();
+        let labels = [method, path, status.as_str()];
+        self.requests_total.with_label_values(&labels).inc();
+        self.request_duration
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
+
+/// Middleware recording duration and count of every HTTP request,
+/// labelled by the matched route pattern to keep the cardinality bounded
+pub async fn track_http(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let metrics = req
+        .app_data::<web::Data<AppState>>()
+        .map(|state| state.metrics.clone());
+    let method = req.method().to_string();
+
+    let resp = next.call(req).await;
+
+    if let Some(metrics) = metrics {
+        let (path, status) = match &resp {
+            Ok(resp) => (resp.request().match_pattern(), resp.status()),
+            Err(e) => (None, e.as_response_error().status_code()),
+        };
+        let path = path.unwrap_or_else(|| "unmatched".to_string());
+        metrics.observe_request(&method, &path, status.as_u16(), started);
+    }
+
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use actix_web::{App, HttpResponse, middleware::from_fn, test, web};
+
+    use crate::{bootstrap::AppState, handlers::telemetry::telemetry, types::config::FaaSConfig};
+
+    #[actix_web::test]
+    async fn test_metrics_endpoint() {
+        let state = AppState::new(FaaSConfig::new()).unwrap();
+        state
+            .metrics
+            .observe_invocation("echo.faasrs-default", 200, Instant::now());
+
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(super::track_http))
+                .app_data(web::Data::new(state))
+                .route("/hello/{name}", web::get().to(HttpResponse::Ok))
+                .route("/metrics", web::get().to(telemetry)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/hello/world").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(
+            body.contains(
+                r#"http_requests_total{method="GET",path="/hello/{name}",status="200"} 1"#
+            )
+        );
+        assert!(body.contains("http_request_duration_seconds_bucket"));
+        assert!(body.contains(
+            r#"gateway_function_invocation_total{code="200",function_name="echo.faasrs-default"} 1"#
+        ));
+        assert!(body.contains("gateway_functions_seconds_count"));
+    }
 }
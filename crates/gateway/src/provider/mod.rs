@@ -1,14 +1,33 @@
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+
 use crate::{
-    handlers::function::{DeleteError, DeployError, ListError, ResolveError, UpdateError},
-    types::function::{Deployment, Query, Status},
+    handlers::{
+        function::{DeleteError, DeployError, ListError, ResolveError, ScaleError, UpdateError},
+        gc::GcError,
+        info::InfoError,
+        logs::LogError,
+        namespace::NamespaceError,
+        secret::SecretError,
+    },
+    types::{
+        function::{Deployment, Query, Status},
+        gc::GcReport,
+        health::HealthStatus,
+        info::ProviderInfo,
+        logs::LogMessage,
+        namespace::FunctionNamespace,
+        secret::Secret,
+        upstream::Upstream,
+    },
 };
 
 pub trait Provider: Send + Sync + 'static {
-    /// Should return a valid upstream url
+    /// Should return a valid upstream url, picked among the instances of the function
     fn resolve(
         &self,
         function: Query,
-    ) -> impl std::future::Future<Output = Result<actix_http::uri::Builder, ResolveError>> + Send;
+    ) -> impl std::future::Future<Output = Result<Upstream, ResolveError>> + Send;
 
     // `/system/functions` endpoint
 
@@ -42,4 +61,90 @@ pub trait Provider: Send + Sync + 'static {
         &self,
         function: Query,
     ) -> impl std::future::Future<Output = Result<Status, ResolveError>> + Send;
+
+    // `/system/scale-function/{functionName}` endpoint
+    /// Set the number of running instances of a function
+    fn scale(
+        &self,
+        function: Query,
+        replicas: u32,
+    ) -> impl std::future::Future<Output = Result<(), ScaleError>> + Send;
+
+    // `/system/secrets` endpoint
+
+    /// List the secrets of a namespace, without their values
+    fn list_secrets(
+        &self,
+        namespace: Option<String>,
+    ) -> impl std::future::Future<Output = Result<Vec<Secret>, SecretError>> + Send;
+
+    /// Create a new secret
+    fn create_secret(
+        &self,
+        secret: Secret,
+    ) -> impl std::future::Future<Output = Result<(), SecretError>> + Send;
+
+    /// Replace the value of an existing secret
+    fn update_secret(
+        &self,
+        secret: Secret,
+    ) -> impl std::future::Future<Output = Result<(), SecretError>> + Send;
+
+    /// Delete a secret
+    fn delete_secret(
+        &self,
+        secret: Secret,
+    ) -> impl std::future::Future<Output = Result<(), SecretError>> + Send;
+
+    // `/system/logs` endpoint
+    /// Get the output of a function, lines written after `since` and at most the last `tail`
+    /// ones. With `follow` the stream keeps going with new lines until it is dropped.
+    fn logs(
+        &self,
+        function: Query,
+        since: Option<DateTime<Utc>>,
+        tail: Option<usize>,
+        follow: bool,
+    ) -> impl std::future::Future<Output = Result<BoxStream<'static, LogMessage>, LogError>> + Send;
+
+    // `/system/namespaces` and `/system/namespace/{name}` endpoints
+
+    /// List the namespaces functions can be deployed to
+    fn list_namespaces(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<String>, NamespaceError>> + Send;
+
+    /// Create a namespace, functions can only be deployed to created namespaces
+    fn create_namespace(
+        &self,
+        namespace: FunctionNamespace,
+    ) -> impl std::future::Future<Output = Result<(), NamespaceError>> + Send;
+
+    /// Replace the labels and annotations of a namespace
+    fn update_namespace(
+        &self,
+        namespace: FunctionNamespace,
+    ) -> impl std::future::Future<Output = Result<(), NamespaceError>> + Send;
+
+    /// Delete a namespace together with all of its functions
+    fn delete_namespace(
+        &self,
+        name: String,
+    ) -> impl std::future::Future<Output = Result<(), NamespaceError>> + Send;
+
+    // `/healthz` endpoint
+    /// Check the components the provider depends on
+    fn health(&self) -> impl std::future::Future<Output = HealthStatus> + Send;
+
+    // `/system/info` endpoint
+    /// Get the name, version and orchestration details of the provider
+    fn info(&self) -> impl std::future::Future<Output = Result<ProviderInfo, InfoError>> + Send;
+
+    // `/system/gc` endpoint
+    /// Remove the resources left behind by functions and replicas that are gone,
+    /// with `dry_run` only report them
+    fn gc(
+        &self,
+        dry_run: bool,
+    ) -> impl std::future::Future<Output = Result<GcReport, GcError>> + Send;
 }
@@ -1,32 +1,243 @@
-use actix_web::{HttpRequest, http::Uri, web};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
-//根据URL和原始请求来构建转发请求，并对请求头进行处理
-pub fn create_proxy_request(
-    req: &HttpRequest,
-    uri: Uri,
-    payload: web::Payload,
-) -> awc::SendClientRequest {
-    let proxy_client = awc::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .finish();
+use actix_http::body::SizedStream;
+use actix_web::{
+    HttpRequest,
+    error::PayloadError,
+    http::{
+        Uri,
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+    },
+    web::Bytes,
+};
+use futures::Stream;
+
+use crate::types::config::FaaSConfig;
+
+/// Pooled upstream client, kept alive connections are reused across invocations.
+/// awc clients are not `Send`, so every worker builds its own in `config_app`.
+///
+/// awc only bounds the total number of connections of the pool, which is taken
+/// from `max_idle_conns`; `max_idle_conns_per_host` is reported as ignored at startup.
+pub fn new_proxy_client(config: &FaaSConfig) -> awc::Client {
+    let connector = awc::Connector::new()
+        .timeout(config.get_connect_timeout())
+        .limit(config.get_max_idle_conns())
+        .conn_keep_alive(config.get_idle_conn_timeout());
+    awc::Client::builder()
+        .connector(connector)
+        // the gateway must answer within its write timeout, so must the function
+        .timeout(config.write_timeout)
+        .finish()
+}
 
-    let mut proxy_req = proxy_client.request(req.method().clone(), uri);
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Hop-by-hop headers of RFC 7230 section 6.1, they only concern a single connection
+/// and are never forwarded, whatever the direction
+const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Whether `name` must be dropped by the proxy, either a well known hop-by-hop header
+/// or one listed in the `Connection` header values of the message
+pub fn is_hop_by_hop<'a>(
+    name: &HeaderName,
+    connection: impl IntoIterator<Item = &'a HeaderValue>,
+) -> bool {
+    HOP_BY_HOP_HEADERS.contains(name)
+        || connection
+            .into_iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case(name.as_str()))
+}
+
+/// Declared length of the body, the proxied body is streamed with the same framing
+pub fn content_length(value: Option<&HeaderValue>) -> Option<u64> {
+    value
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
 
-    for header in req.headers() {
-        proxy_req = proxy_req.insert_header(header);
+/// actix keeps its own header map, the HTTP/2 side works with the one of `http`
+pub fn to_http_headers(headers: &HeaderMap) -> http::HeaderMap {
+    let mut map = http::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        map.append(name.clone(), value.clone());
     }
+    map
+}
 
-    if req.headers().get("X-Forwarded-Host").is_none() {
-        if let Some(host) = req.headers().get("Host") {
-            proxy_req = proxy_req.insert_header(("X-Forwarded-Host", host));
-        }
+/// A `Forwarded` parameter value, quoted unless it is a plain token (RFC 7239 section 4)
+fn forwarded_value(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// The element this gateway appends to the `Forwarded` header
+fn forwarded_element(peer: Option<SocketAddr>, host: Option<&str>, proto: &str) -> String {
+    let mut element = Vec::new();
+    if let Some(peer) = peer {
+        let node = match peer.ip() {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        };
+        element.push(format!("for={}", forwarded_value(&node)));
     }
+    if let Some(host) = host {
+        element.push(format!("host={}", forwarded_value(host)));
+    }
+    element.push(format!("proto={}", forwarded_value(proto)));
+    element.join(";")
+}
 
-    if req.headers().get("X-Forwarded-For").is_none() {
-        if let Some(remote_addr) = req.peer_addr() {
-            proxy_req = proxy_req.insert_header(("X-Forwarded-For", remote_addr.to_string()));
+/// Headers sent to the function: the end-to-end ones of the caller plus the
+/// `X-Forwarded-*` and `Forwarded` headers. Host is set from the upstream address
+/// and the framing headers from the body, so neither is included.
+///
+/// `host` is the authority the caller asked for, `proto` the scheme it used.
+pub fn forward_headers(
+    headers: &http::HeaderMap,
+    peer: Option<SocketAddr>,
+    host: Option<&str>,
+    proto: &str,
+) -> http::HeaderMap {
+    let mut forwarded = http::HeaderMap::new();
+    for (name, value) in headers {
+        if is_hop_by_hop(name, headers.get_all(header::CONNECTION))
+            || name == header::HOST
+            || name == header::CONTENT_LENGTH
+        {
+            continue;
         }
+        forwarded.append(name.clone(), value.clone());
+    }
+
+    if !headers.contains_key(X_FORWARDED_HOST)
+        && let Some(value) = host.and_then(|host| HeaderValue::try_from(host).ok())
+    {
+        forwarded.insert(X_FORWARDED_HOST, value);
+    }
+
+    if !headers.contains_key(X_FORWARDED_FOR)
+        && let Some(peer) = peer
+        && let Ok(value) = HeaderValue::try_from(peer.to_string())
+    {
+        forwarded.insert(X_FORWARDED_FOR, value);
+    }
+
+    if !headers.contains_key(X_FORWARDED_PROTO)
+        && let Ok(value) = HeaderValue::try_from(proto)
+    {
+        forwarded.insert(X_FORWARDED_PROTO, value);
     }
 
-    proxy_req.send_stream(payload)
+    // keep the elements added by the proxies in front of us
+    let element = forwarded_element(peer, host, proto);
+    let value = headers
+        .get_all(header::FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .chain(std::iter::once(element.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::try_from(value) {
+        forwarded.insert(header::FORWARDED, value);
+    }
+    forwarded
+}
+
+/// [`forward_headers`] of a request received by actix
+pub fn proxy_headers(req: &HttpRequest) -> http::HeaderMap {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    forward_headers(
+        &to_http_headers(req.headers()),
+        req.peer_addr(),
+        host,
+        req.connection_info().scheme(),
+    )
+}
+
+//根据URL和原始请求来构建转发请求，并对请求头进行处理
+//`timeout` 覆盖客户端默认的响应超时
+pub fn create_proxy_request<S>(
+    client: &awc::Client,
+    req: &HttpRequest,
+    uri: Uri,
+    payload: S,
+    timeout: Option<Duration>,
+) -> awc::SendClientRequest
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+{
+    // the body is relayed as received, the function negotiates the encoding with the caller
+    let mut proxy_req = client.request(req.method().clone(), uri).no_decompress();
+    if let Some(timeout) = timeout {
+        proxy_req = proxy_req.timeout(timeout);
+    }
+    for (name, value) in proxy_headers(req).iter() {
+        proxy_req = proxy_req.append_header((name.clone(), value.clone()));
+    }
+
+    match content_length(req.headers().get(header::CONTENT_LENGTH)) {
+        Some(length) => proxy_req.send_body(SizedStream::new(length, payload)),
+        None => proxy_req.send_stream(payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("keep-alive, X-Session"),
+        );
+        let connection = headers.get_all(header::CONNECTION);
+        assert!(is_hop_by_hop(
+            &header::TRANSFER_ENCODING,
+            connection.clone()
+        ));
+        assert!(is_hop_by_hop(&header::CONNECTION, connection.clone()));
+        assert!(is_hop_by_hop(
+            &HeaderName::from_static("x-session"),
+            connection.clone()
+        ));
+        assert!(!is_hop_by_hop(&header::CONTENT_TYPE, connection.clone()));
+        assert!(!is_hop_by_hop(&header::SET_COOKIE, connection.clone()));
+    }
+
+    #[test]
+    fn test_forwarded_value() {
+        assert_eq!(forwarded_value("http"), "http");
+        assert_eq!(forwarded_value("10.0.0.1"), "10.0.0.1");
+        assert_eq!(forwarded_value("[::1]"), "\"[::1]\"");
+        assert_eq!(forwarded_value("example.com:8080"), "\"example.com:8080\"");
+    }
 }
@@ -0,0 +1,290 @@
+//! HTTP/2 prior knowledge towards the functions serving `h2c` or `grpc`.
+//!
+//! awc only negotiates HTTP/2 over TLS, so these connections are driven with the
+//! h2 crate directly, which also carries the trailers gRPC reports its status in.
+
+use std::{cell::RefCell, collections::HashMap, io, pin::pin, time::Duration};
+
+use actix_web::{
+    HttpRequest, HttpResponse,
+    error::{ErrorInternalServerError, PayloadError},
+    http::{
+        StatusCode,
+        header::{self, HeaderValue},
+    },
+    web::{self, Bytes},
+};
+use futures::{Stream, StreamExt};
+use h2::{RecvStream, SendStream, client::SendRequest};
+use tokio::{net::TcpStream, time::Instant};
+
+use crate::{
+    proxy::{
+        builder::{content_length, is_hop_by_hop, proxy_headers, to_http_headers},
+        proxy_handler::{DURATION_HEADER, duration_seconds, gateway_timeout, until_deadline},
+    },
+    types::{function::Protocol, upstream::Upstream},
+};
+
+/// One piece of an HTTP/2 message body
+#[derive(Debug)]
+pub enum Frame {
+    Data(Bytes),
+    Trailers(http::HeaderMap),
+}
+
+/// Multiplexed connections to the instances, one per address, kept per worker
+pub struct H2Pool {
+    conns: RefCell<HashMap<String, SendRequest<Bytes>>>,
+    connect_timeout: Duration,
+}
+
+impl H2Pool {
+    pub fn new(connect_timeout: Duration) -> Self {
+        H2Pool {
+            conns: RefCell::new(HashMap::new()),
+            connect_timeout,
+        }
+    }
+
+    /// A connection ready to open a new stream, a broken one is replaced
+    async fn connection(&self, authority: &str) -> io::Result<SendRequest<Bytes>> {
+        let pooled = self.conns.borrow().get(authority).cloned();
+        if let Some(conn) = pooled {
+            match conn.ready().await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => {
+                    log::debug!("HTTP/2 connection to {} is gone: {}", authority, e);
+                    self.conns.borrow_mut().remove(authority);
+                }
+            }
+        }
+
+        let tcp = tokio::time::timeout(self.connect_timeout, TcpStream::connect(authority))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        tcp.set_nodelay(true)?;
+        let (conn, connection) = h2::client::handshake(tcp).await.map_err(io::Error::other)?;
+        let task_authority = authority.to_string();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = connection.await {
+                log::debug!("HTTP/2 connection to {} closed: {}", task_authority, e);
+            }
+        });
+        self.conns
+            .borrow_mut()
+            .insert(authority.to_string(), conn.clone());
+        conn.ready().await.map_err(io::Error::other)
+    }
+
+    /// Open a stream on the instance, `body` is sent while the response is awaited,
+    /// which bidirectional gRPC streams rely on
+    pub async fn send<S, E>(
+        &self,
+        head: http::Request<()>,
+        body: S,
+        read_deadline: Option<Instant>,
+    ) -> io::Result<http::Response<RecvStream>>
+    where
+        S: Stream<Item = Result<Frame, E>> + 'static,
+        E: std::fmt::Display + 'static,
+    {
+        let authority = head
+            .uri()
+            .authority()
+            .map(|authority| authority.to_string())
+            .unwrap_or_default();
+        let mut conn = self.connection(&authority).await?;
+        let (response, stream) = conn.send_request(head, false).map_err(io::Error::other)?;
+        actix_web::rt::spawn(pump(stream, body, read_deadline));
+        response.await.map_err(io::Error::other)
+    }
+}
+
+/// The frames of a received body, data first and the trailers last
+pub fn frames(recv: RecvStream) -> impl Stream<Item = Result<Frame, h2::Error>> + 'static {
+    futures::stream::unfold(Some(recv), |recv| async move {
+        let mut recv = recv?;
+        match recv.data().await {
+            Some(Ok(data)) => {
+                // hand the window back, the data is forwarded right away
+                let _ = recv.flow_control().release_capacity(data.len());
+                Some((Ok(Frame::Data(data)), Some(recv)))
+            }
+            Some(Err(e)) => Some((Err(e), None)),
+            None => match recv.trailers().await {
+                Ok(Some(trailers)) => Some((Ok(Frame::Trailers(trailers)), None)),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            },
+        }
+    })
+}
+
+/// The data of the frames as an actix payload, for the side that has no trailers
+pub fn into_payload<S>(frames: S) -> impl Stream<Item = Result<Bytes, PayloadError>> + 'static
+where
+    S: Stream<Item = Result<Frame, h2::Error>> + 'static,
+{
+    frames.filter_map(|frame| async move {
+        match frame {
+            Ok(Frame::Data(data)) => Some(Ok(data)),
+            Ok(Frame::Trailers(trailers)) => {
+                log::trace!("Dropping trailers for a HTTP/1 peer: {:?}", trailers);
+                None
+            }
+            Err(e) => Some(Err(PayloadError::Io(io::Error::other(e)))),
+        }
+    })
+}
+
+/// Send `body` on `stream` honouring the flow control window of the peer, the stream
+/// is reset if the body fails or is not over by `deadline`
+pub async fn pump<S, E>(mut stream: SendStream<Bytes>, body: S, deadline: Option<Instant>)
+where
+    S: Stream<Item = Result<Frame, E>>,
+    E: std::fmt::Display,
+{
+    let mut body = pin!(body);
+    loop {
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, body.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    log::debug!("HTTP/2 body not over in time");
+                    stream.send_reset(h2::Reason::CANCEL);
+                    return;
+                }
+            },
+            None => body.next().await,
+        };
+        let result = match next {
+            Some(Ok(Frame::Data(data))) => send_data(&mut stream, data).await,
+            Some(Ok(Frame::Trailers(trailers))) => {
+                if let Err(e) = stream.send_trailers(trailers) {
+                    log::debug!("Failed to send trailers: {}", e);
+                }
+                return;
+            }
+            Some(Err(e)) => {
+                log::debug!("HTTP/2 body failed: {}", e);
+                stream.send_reset(h2::Reason::CANCEL);
+                return;
+            }
+            None => {
+                if let Err(e) = stream.send_data(Bytes::new(), true) {
+                    log::debug!("Failed to end HTTP/2 stream: {}", e);
+                }
+                return;
+            }
+        };
+        if let Err(e) = result {
+            log::debug!("Failed to send HTTP/2 data: {}", e);
+            return;
+        }
+    }
+}
+
+async fn send_data(stream: &mut SendStream<Bytes>, mut data: Bytes) -> Result<(), h2::Error> {
+    while !data.is_empty() {
+        stream.reserve_capacity(data.len());
+        let granted = std::future::poll_fn(|cx| stream.poll_capacity(cx))
+            .await
+            .ok_or(h2::Error::from(h2::Reason::STREAM_CLOSED))??;
+        stream.send_data(data.split_to(granted.min(data.len())), false)?;
+    }
+    Ok(())
+}
+
+/// Headers of a request to a HTTP/2 function, gRPC needs `te: trailers` even though
+/// TE is hop-by-hop
+pub fn h2_headers(
+    mut headers: http::HeaderMap,
+    caller: &http::HeaderMap,
+    protocol: Protocol,
+) -> http::HeaderMap {
+    let trailers = caller
+        .get_all(header::TE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("trailers"));
+    if trailers || protocol == Protocol::Grpc {
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    }
+    headers
+}
+
+/// Proxy a request received by actix to a HTTP/2 function. actix can not send
+/// trailers, they are dropped here; HTTP/2 callers are served by [`super::h2c`].
+pub async fn proxy_h2_request(
+    pool: &H2Pool,
+    req: &HttpRequest,
+    payload: web::Payload,
+    upstream: Upstream,
+    path_and_query: &str,
+    default_timeout: Duration,
+) -> actix_web::Result<HttpResponse> {
+    let started = Instant::now();
+    let Upstream {
+        uri,
+        inflight,
+        timeouts,
+        protocol,
+    } = upstream;
+    let uri = uri.path_and_query(path_and_query).build().map_err(|e| {
+        log::error!("Failed to build URI: {}", e);
+        ErrorInternalServerError("Failed to build URI")
+    })?;
+    log::trace!("Proxying request over HTTP/2 to: {}", uri);
+
+    let mut head = http::Request::builder()
+        .method(req.method().clone())
+        .uri(uri)
+        .version(http::Version::HTTP_2)
+        .body(())
+        .map_err(|e| {
+            log::error!("Failed to build HTTP/2 request: {}", e);
+            ErrorInternalServerError("Failed to create proxy request")
+        })?;
+    let caller = to_http_headers(req.headers());
+    *head.headers_mut() = h2_headers(proxy_headers(req), &caller, protocol);
+
+    let read_deadline = timeouts.read.map(|read| started + read);
+    let exec_deadline = timeouts.exec.map(|exec| started + exec);
+    let body = payload.map(|chunk| chunk.map(Frame::Data));
+    let response_timeout = timeouts.response_timeout().unwrap_or(default_timeout);
+    let response =
+        match tokio::time::timeout(response_timeout, pool.send(head, body, read_deadline)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                log::error!("Failed to create proxy request: {}", e);
+                return Err(ErrorInternalServerError("Failed to create proxy request"));
+            }
+            Err(_) => return Ok(gateway_timeout(started)),
+        };
+
+    let (parts, recv) = response.into_parts();
+    let status = StatusCode::from_u16(parts.status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut client_resp = HttpResponse::build(status);
+    for (name, value) in parts.headers.iter() {
+        if is_hop_by_hop(name, parts.headers.get_all(header::CONNECTION))
+            || name == header::CONTENT_LENGTH
+        {
+            continue;
+        }
+        client_resp.append_header((name.clone(), value.clone()));
+    }
+    if let Some(length) = content_length(parts.headers.get(header::CONTENT_LENGTH)) {
+        client_resp.no_chunking(length);
+    }
+    client_resp.insert_header((DURATION_HEADER, duration_seconds(started)));
+
+    let body = into_payload(frames(recv));
+    Ok(
+        client_resp.streaming(until_deadline(body, exec_deadline).map(move |chunk| {
+            let _ = &inflight;
+            chunk
+        })),
+    )
+}
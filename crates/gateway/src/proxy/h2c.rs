@@ -0,0 +1,448 @@
+//! HTTP/2 cleartext front. actix can not send trailers, so the connections opening
+//! with the HTTP/2 preface are served here instead and `h2c` or `grpc` functions
+//! answer their callers end to end, trailers included.
+//!
+//! The invocations under `/function/` are relayed here, every other request is
+//! handed to the same actix app as the HTTP/1 connections.
+
+use std::{io, net::SocketAddr, os::fd::AsFd, pin::Pin, rc::Rc, sync::Arc};
+
+use actix_http::{
+    BoxedPayloadStream, Payload,
+    body::{BodySize, BoxBody, MessageBody, SizedStream},
+};
+use actix_service::{Service, boxed::RcService};
+use actix_web::{http::header, web::Bytes};
+use awc::error::SendRequestError;
+use futures::StreamExt;
+use h2::{RecvStream, server::SendResponse};
+use http::{HeaderName, HeaderValue, Request, Response, StatusCode, request::Parts};
+use tokio::{io::Interest, net::TcpStream, time::Instant};
+
+use crate::{
+    bootstrap::AppState,
+    handlers::proxy::{self, FUNCTION_SCOPE, PROXY_DISPATCH_PATH, ProxyQuery},
+    provider::Provider,
+    proxy::{
+        builder::{content_length, forward_headers, is_hop_by_hop, new_proxy_client},
+        h2::{Frame, H2Pool, frames, h2_headers, into_payload, pump},
+        proxy_handler::{DURATION_HEADER, duration_seconds, until_deadline},
+    },
+    types::upstream::Upstream,
+};
+
+/// Sent first by every HTTP/2 client with prior knowledge (RFC 9113 section 3.4)
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The actix app of the worker, the same one serving the HTTP/1 connections
+pub type AppService =
+    RcService<actix_http::Request, actix_http::Response<BoxBody>, actix_web::Error>;
+
+/// Whether the connection opens with the HTTP/2 preface. The bytes are only
+/// peeked, they are read again by whoever serves the connection.
+pub async fn is_h2c(io: &TcpStream) -> bool {
+    // tokio peeks only asynchronously, a duplicate of the socket is peeked inside try_io
+    let Ok(peeker) = io
+        .as_fd()
+        .try_clone_to_owned()
+        .map(std::net::TcpStream::from)
+    else {
+        return false;
+    };
+    let mut buf = [0; PREFACE.len()];
+    loop {
+        if io.readable().await.is_err() {
+            return false;
+        }
+        let peeked = io.try_io(Interest::READABLE, || {
+            let n = peeker.peek(&mut buf)?;
+            if n > 0 && n < PREFACE.len() && buf[..n] == PREFACE[..n] {
+                // the preface was split, wait until more of it arrived
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(n)
+        });
+        match peeked {
+            Ok(n) => return n == PREFACE.len() && buf[..] == *PREFACE,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// The front of one worker, the invocations go through [`proxy::invoke`] like the actix ones
+pub struct H2cFront<P> {
+    provider: Arc<P>,
+    state: AppState,
+    app: AppService,
+    pool: H2Pool,
+    client: awc::Client,
+}
+
+impl<P: Provider> H2cFront<P> {
+    pub fn new(provider: Arc<P>, state: AppState, app: AppService) -> Self {
+        let pool = H2Pool::new(state.config.get_connect_timeout());
+        let client = new_proxy_client(&state.config);
+        H2cFront {
+            provider,
+            state,
+            app,
+            pool,
+            client,
+        }
+    }
+
+    /// Serve the streams of the connection until the caller goes away
+    pub async fn serve(self: Rc<Self>, io: TcpStream) {
+        let peer = io.peer_addr().ok();
+        let mut conn = match h2::server::handshake(io).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::debug!("HTTP/2 handshake with {:?} failed: {}", peer, e);
+                return;
+            }
+        };
+        while let Some(accepted) = conn.accept().await {
+            match accepted {
+                Ok((request, respond)) => {
+                    let front = self.clone();
+                    actix_web::rt::spawn(
+                        async move { front.dispatch(request, respond, peer).await },
+                    );
+                }
+                Err(e) => {
+                    log::debug!("HTTP/2 connection with {:?} failed: {}", peer, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        request: Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        peer: Option<SocketAddr>,
+    ) {
+        let started = Instant::now();
+        let (parts, body) = request.into_parts();
+        let meta = parts
+            .uri
+            .path()
+            .strip_prefix(FUNCTION_SCOPE)
+            .and_then(|path| ProxyQuery::from_raw(path, parts.uri.query()));
+        let Some(meta) = meta else {
+            self.forward_app(parts, body, respond, peer).await;
+            return;
+        };
+
+        // the app records the other requests in track_http, the pattern is the one it would match
+        let method = parts.method.to_string();
+        let status = self.invoke(parts, body, &mut respond, meta, peer).await;
+        let pattern = format!("{}{}", FUNCTION_SCOPE, PROXY_DISPATCH_PATH);
+        self.state
+            .metrics
+            .observe_request(&method, &pattern, status.as_u16(), started.into_std());
+    }
+
+    async fn invoke(
+        &self,
+        parts: Parts,
+        body: RecvStream,
+        respond: &mut SendResponse<Bytes>,
+        meta: ProxyQuery,
+        peer: Option<SocketAddr>,
+    ) -> StatusCode {
+        let method = parts.method.clone();
+        let authorization = parts.headers.get(header::AUTHORIZATION).cloned();
+        let path = &meta.path;
+        let forward = async |upstream: Upstream| {
+            Ok(if upstream.protocol.is_http2() {
+                self.forward_h2(parts, body, respond, upstream, path, peer)
+                    .await
+            } else {
+                self.forward_http1(parts, body, respond, upstream, path, peer)
+                    .await
+            })
+        };
+        let invoked = proxy::invoke(
+            self.provider.as_ref(),
+            &self.state,
+            &method,
+            authorization.as_ref(),
+            meta.query,
+            forward,
+            |status| *status,
+        )
+        .await;
+        match invoked {
+            Ok(status) => status,
+            // answered as the actix handler would
+            Err(e) => send_app_response(respond, e.error_response().into()).await,
+        }
+    }
+
+    /// Hand the stream to the actix app, it answers as it would on HTTP/1
+    async fn forward_app(
+        &self,
+        parts: Parts,
+        body: RecvStream,
+        mut respond: SendResponse<Bytes>,
+        peer: Option<SocketAddr>,
+    ) {
+        let payload: BoxedPayloadStream = Box::pin(into_payload(frames(body)));
+        let mut req = actix_http::Request::with_payload(Payload::from(payload));
+        let head = req.head_mut();
+        head.method = parts.method;
+        head.uri = parts.uri;
+        head.version = http::Version::HTTP_2;
+        head.headers = parts.headers.into();
+        head.peer_addr = peer;
+
+        let response = match self.app.call(req).await {
+            Ok(response) => response,
+            Err(e) => actix_http::Response::from(e),
+        };
+        send_app_response(&mut respond, response).await;
+    }
+
+    /// Stream to stream, the trailers of both sides are relayed
+    async fn forward_h2(
+        &self,
+        parts: Parts,
+        body: RecvStream,
+        respond: &mut SendResponse<Bytes>,
+        upstream: Upstream,
+        path_and_query: &str,
+        peer: Option<SocketAddr>,
+    ) -> StatusCode {
+        let started = Instant::now();
+        let Upstream {
+            uri,
+            inflight,
+            timeouts,
+            protocol,
+        } = upstream;
+        let head = uri.path_and_query(path_and_query).build().and_then(|uri| {
+            Request::builder()
+                .method(parts.method.clone())
+                .uri(uri)
+                .version(http::Version::HTTP_2)
+                .body(())
+        });
+        let mut head = match head {
+            Ok(head) => head,
+            Err(e) => {
+                log::error!("Failed to build HTTP/2 request: {}", e);
+                return internal_error(respond);
+            }
+        };
+        *head.headers_mut() = h2_headers(caller_headers(&parts, peer), &parts.headers, protocol);
+        log::trace!("Proxying h2c stream to: {}", head.uri());
+
+        let read_deadline = timeouts.read.map(|read| started + read);
+        let exec_deadline = timeouts.exec.map(|exec| started + exec);
+        let response_timeout = timeouts
+            .response_timeout()
+            .unwrap_or(self.state.config.write_timeout);
+        let response = match tokio::time::timeout(
+            response_timeout,
+            self.pool.send(head, frames(body), read_deadline),
+        )
+        .await
+        {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                log::error!("Failed to create proxy request: {}", e);
+                return internal_error(respond);
+            }
+            Err(_) => return timed_out(respond, started),
+        };
+
+        let (resp, recv) = response.into_parts();
+        let end_of_stream = recv.is_end_stream();
+        let head = response_head(resp.status, &resp.headers, started);
+        match respond.send_response(head, end_of_stream) {
+            Ok(stream) if !end_of_stream => pump(stream, frames(recv), exec_deadline).await,
+            Ok(_) => {}
+            Err(e) => log::debug!("Failed to send HTTP/2 response: {}", e),
+        }
+        // the instance stays busy until the body is done
+        drop(inflight);
+        resp.status
+    }
+
+    /// A HTTP/1 function behind a HTTP/2 caller, it has no trailers to relay
+    async fn forward_http1(
+        &self,
+        parts: Parts,
+        body: RecvStream,
+        respond: &mut SendResponse<Bytes>,
+        upstream: Upstream,
+        path_and_query: &str,
+        peer: Option<SocketAddr>,
+    ) -> StatusCode {
+        let started = Instant::now();
+        let Upstream {
+            uri,
+            inflight,
+            timeouts,
+            ..
+        } = upstream;
+        let uri = match uri.path_and_query(path_and_query).build() {
+            Ok(uri) => uri,
+            Err(e) => {
+                log::error!("Failed to build URI: {}", e);
+                return internal_error(respond);
+            }
+        };
+        log::trace!("Proxying h2c stream to: {}", uri);
+
+        let exec_deadline = timeouts.exec.map(|exec| started + exec);
+        let read_deadline = timeouts.read.map(|read| started + read);
+        let mut proxy_req = self
+            .client
+            .request(parts.method.clone(), uri)
+            .no_decompress();
+        if let Some(timeout) = timeouts.response_timeout() {
+            proxy_req = proxy_req.timeout(timeout);
+        }
+        for (name, value) in caller_headers(&parts, peer).iter() {
+            proxy_req = proxy_req.append_header((name.clone(), value.clone()));
+        }
+        let payload = until_deadline(into_payload(frames(body)), read_deadline);
+        let send = match content_length(parts.headers.get(header::CONTENT_LENGTH)) {
+            Some(length) => proxy_req.send_body(SizedStream::new(length, payload)),
+            None => proxy_req.send_stream(payload),
+        };
+        let proxy_resp = match send.await {
+            Ok(resp) => resp,
+            Err(SendRequestError::Timeout) => return timed_out(respond, started),
+            Err(e) if read_deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                log::debug!("Request body not received in time: {}", e);
+                return timed_out(respond, started);
+            }
+            Err(e) => {
+                log::error!("Failed to create proxy request: {}", e);
+                return internal_error(respond);
+            }
+        };
+
+        let status = proxy_resp.status();
+        let mut headers = http::HeaderMap::new();
+        for (name, value) in proxy_resp.headers() {
+            headers.append(name.clone(), value.clone());
+        }
+        let head = response_head(status, &headers, started);
+        match respond.send_response(head, false) {
+            Ok(stream) => {
+                let body = until_deadline(proxy_resp, exec_deadline);
+                pump(stream, body.map(|chunk| chunk.map(Frame::Data)), None).await
+            }
+            Err(e) => log::debug!("Failed to send HTTP/2 response: {}", e),
+        }
+        drop(inflight);
+        status
+    }
+}
+
+/// [`forward_headers`] of a HTTP/2 request, its authority is the host it asked for
+fn caller_headers(parts: &Parts, peer: Option<SocketAddr>) -> http::HeaderMap {
+    let host = parts
+        .uri
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| {
+            parts
+                .headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+        });
+    forward_headers(&parts.headers, peer, host, "http")
+}
+
+/// Head relayed to the caller, the body keeps the length the function declared
+fn response_head(status: StatusCode, headers: &http::HeaderMap, started: Instant) -> Response<()> {
+    let mut head = Response::new(());
+    *head.status_mut() = status;
+    for (name, value) in headers {
+        if is_hop_by_hop(name, headers.get_all(header::CONNECTION)) {
+            continue;
+        }
+        head.headers_mut().append(name.clone(), value.clone());
+    }
+    if let Ok(value) = HeaderValue::try_from(duration_seconds(started)) {
+        head.headers_mut().insert(duration_header(), value);
+    }
+    head
+}
+
+fn duration_header() -> HeaderName {
+    HeaderName::from_bytes(DURATION_HEADER.as_bytes()).expect("valid header name")
+}
+
+/// Relay a response of the actix side, returns its status for the metrics
+async fn send_app_response(
+    respond: &mut SendResponse<Bytes>,
+    response: actix_http::Response<BoxBody>,
+) -> StatusCode {
+    let (response, mut body) = response.into_parts();
+    let status = response.status();
+    let mut head = Response::new(());
+    *head.status_mut() = status;
+    for (name, value) in response.headers() {
+        // HTTP/2 has no connection specific headers
+        if is_hop_by_hop(name, response.headers().get_all(header::CONNECTION)) {
+            continue;
+        }
+        head.headers_mut().append(name.clone(), value.clone());
+    }
+    let end_of_stream = matches!(body.size(), BodySize::None | BodySize::Sized(0));
+    match respond.send_response(head, end_of_stream) {
+        Ok(stream) if !end_of_stream => {
+            let body = futures::stream::poll_fn(move |cx| Pin::new(&mut body).poll_next(cx));
+            pump(stream, body.map(|chunk| chunk.map(Frame::Data)), None).await
+        }
+        Ok(_) => {}
+        Err(e) => log::debug!("Failed to send HTTP/2 response: {}", e),
+    }
+    status
+}
+
+/// Answer the stream with a small body, returns the status for the metrics
+fn reply(respond: &mut SendResponse<Bytes>, status: StatusCode, body: Bytes) -> StatusCode {
+    let mut head = Response::new(());
+    *head.status_mut() = status;
+    send_head(respond, head, body);
+    status
+}
+
+fn send_head(respond: &mut SendResponse<Bytes>, head: Response<()>, body: Bytes) {
+    match respond.send_response(head, body.is_empty()) {
+        Ok(mut stream) if !body.is_empty() => {
+            if let Err(e) = stream.send_data(body, true) {
+                log::debug!("Failed to send HTTP/2 response: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => log::debug!("Failed to send HTTP/2 response: {}", e),
+    }
+}
+
+fn internal_error(respond: &mut SendResponse<Bytes>) -> StatusCode {
+    reply(
+        respond,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Bytes::from_static(b"Failed to create proxy request"),
+    )
+}
+
+fn timed_out(respond: &mut SendResponse<Bytes>, started: Instant) -> StatusCode {
+    let mut head = Response::new(());
+    *head.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+    if let Ok(value) = HeaderValue::try_from(duration_seconds(started)) {
+        head.headers_mut().insert(duration_header(), value);
+    }
+    send_head(respond, head, Bytes::from_static(b"function timed out"));
+    StatusCode::GATEWAY_TIMEOUT
+}
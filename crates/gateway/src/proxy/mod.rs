@@ -1,4 +1,7 @@
 pub mod builder;
+pub mod h2;
+pub mod h2c;
 pub mod proxy_handler;
-// #[cfg(test)]
-// mod test;
+#[cfg(test)]
+mod test;
+pub mod upgrade;
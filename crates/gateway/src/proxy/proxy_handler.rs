@@ -1,28 +1,175 @@
 // use crate::handlers::invoke_resolver::InvokeResolver;
-use crate::proxy::builder::create_proxy_request;
+use crate::{
+    proxy::builder::{content_length, create_proxy_request, is_hop_by_hop},
+    types::upstream::Upstream,
+};
 
-use actix_web::{HttpRequest, HttpResponse, error::ErrorInternalServerError, web};
+use actix_web::{
+    HttpRequest, HttpResponse,
+    error::{ErrorInternalServerError, PayloadError},
+    http::header,
+    web::{self, Bytes},
+};
+use awc::error::SendRequestError;
+use futures::{Stream, StreamExt};
+use tokio::time::Instant;
 
+/// Reported on every proxied response, like the OpenFaaS gateway does
+pub const DURATION_HEADER: &str = "X-Duration-Seconds";
+
+/// Forward the request to the picked instance, `path_and_query` is sent as is
+/// so it must already be percent-encoded
 pub async fn proxy_request(
+    client: &awc::Client,
     req: &HttpRequest,
     payload: web::Payload,
-    upstream: actix_http::uri::Builder,
-    path: &str,
+    upstream: Upstream,
+    path_and_query: &str,
 ) -> actix_web::Result<HttpResponse> {
-    let uri = upstream.path_and_query(path).build().map_err(|e| {
+    let started = Instant::now();
+    let Upstream {
+        uri,
+        inflight,
+        timeouts,
+        ..
+    } = upstream;
+    let uri = uri.path_and_query(path_and_query).build().map_err(|e| {
         log::error!("Failed to build URI: {}", e);
         ErrorInternalServerError("Failed to build URI")
     })?;
     log::trace!("Proxying request to: {}", uri);
-    // Handle the error conversion explicitly
-    let proxy_resp = create_proxy_request(req, uri, payload).await.map_err(|e| {
-        log::error!("Failed to create proxy request: {}", e);
-        ErrorInternalServerError("Failed to create proxy request")
-    })?;
+
+    // exec bounds the whole invocation, write only the wait for the response head
+    let exec_deadline = timeouts.exec.map(|exec| started + exec);
+    let read_deadline = timeouts.read.map(|read| started + read);
+    let response_timeout = timeouts.response_timeout();
+
+    let payload = until_deadline(payload, read_deadline);
+    let proxy_resp = match create_proxy_request(client, req, uri, payload, response_timeout).await {
+        Ok(resp) => resp,
+        Err(SendRequestError::Timeout) => return Ok(gateway_timeout(started)),
+        // the body upload was cut by the read timeout
+        Err(e) if read_deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+            log::debug!("Request body not received in time: {}", e);
+            return Ok(gateway_timeout(started));
+        }
+        Err(e) => {
+            log::error!("Failed to create proxy request: {}", e);
+            return Err(ErrorInternalServerError("Failed to create proxy request"));
+        }
+    };
 
     // Now create an HttpResponse from the proxy response
     let mut client_resp = HttpResponse::build(proxy_resp.status());
+    for (name, value) in proxy_resp.headers() {
+        if is_hop_by_hop(name, proxy_resp.headers().get_all(header::CONNECTION))
+            || name == header::CONTENT_LENGTH
+        {
+            continue;
+        }
+        client_resp.append_header((name.clone(), value.clone()));
+    }
+    // keep the framing of the function, chunked only if it was
+    if let Some(length) = content_length(proxy_resp.headers().get(header::CONTENT_LENGTH)) {
+        client_resp.no_chunking(length);
+    }
+    client_resp.insert_header((DURATION_HEADER, duration_seconds(started)));
+
+    // Stream the response body, the instance stays busy until the body is done
+    let body = until_deadline(proxy_resp, exec_deadline);
+    Ok(client_resp.streaming(body.map(move |chunk| {
+        let _ = &inflight;
+        chunk
+    })))
+}
+
+pub(crate) fn gateway_timeout(started: Instant) -> HttpResponse {
+    HttpResponse::GatewayTimeout()
+        .insert_header((DURATION_HEADER, duration_seconds(started)))
+        .body("function timed out")
+}
+
+pub(crate) fn duration_seconds(started: Instant) -> String {
+    format!("{:.6}", started.elapsed().as_secs_f64())
+}
+
+/// Fails the stream with `TimedOut` once `deadline` has passed, even if it stalls
+pub(crate) fn until_deadline<S>(
+    stream: S,
+    deadline: Option<Instant>,
+) -> impl Stream<Item = Result<Bytes, PayloadError>> + 'static
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+{
+    futures::stream::unfold(
+        (Box::pin(stream), false),
+        move |(mut stream, expired)| async move {
+            if expired {
+                return None;
+            }
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, stream.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        let e = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+                        return Some((Err(PayloadError::Io(e)), (stream, true)));
+                    }
+                },
+                None => stream.next().await,
+            };
+            next.map(|item| (item, (stream, false)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{App, http, test};
+
+    use super::*;
+    use crate::types::function::FunctionTimeouts;
+
+    #[actix_web::test]
+    async fn test_write_timeout_returns_gateway_timeout() {
+        // the instance takes longer to answer than the write timeout allows
+        let server = actix_web::HttpServer::new(|| {
+            App::new().route(
+                "/slow",
+                web::get().to(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    HttpResponse::Ok().finish()
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let app = test::init_service(App::new().route(
+            "/function/slow",
+            web::get().to(move |req: HttpRequest, payload: web::Payload| async move {
+                let uri = http::Uri::builder()
+                    .scheme("http")
+                    .authority(addr.to_string());
+                let upstream = Upstream::new(uri).with_timeouts(FunctionTimeouts {
+                    write: Some(Duration::from_millis(200)),
+                    ..Default::default()
+                });
+                proxy_request(&awc::Client::default(), &req, payload, upstream, "/slow").await
+            }),
+        ))
+        .await;
 
-    // Stream the response body
-    Ok(client_resp.streaming(proxy_resp))
+        let req = test::TestRequest::get().uri("/function/slow").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::GATEWAY_TIMEOUT);
+        assert!(resp.headers().contains_key(DURATION_HEADER));
+        handle.stop(false).await;
+    }
 }
@@ -1,14 +1,301 @@
-use crate::handlers::proxy::proxy;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{
+    bootstrap::{AppState, serve_on},
+    handlers::{
+        function::{DeleteError, DeployError, ListError, ResolveError, ScaleError, UpdateError},
+        gc::GcError,
+        info::InfoError,
+        logs::LogError,
+        namespace::NamespaceError,
+        proxy::{PROXY_DISPATCH_PATH, missing_function, proxy},
+        secret::SecretError,
+    },
+    provider::Provider,
+    proxy::{h2::H2Pool, proxy_handler::DURATION_HEADER},
+    types::{
+        config::FaaSConfig,
+        function::{Deployment, Protocol, Query, Status},
+        gc::GcReport,
+        health::HealthStatus,
+        info::ProviderInfo,
+        logs::LogMessage,
+        namespace::FunctionNamespace,
+        secret::Secret,
+        upstream::Upstream,
+    },
+};
 use actix_web::{
-    App, HttpRequest, HttpResponse, Responder, http,
+    App, HttpRequest, HttpResponse, Responder,
+    dev::ServerHandle,
+    http,
     test::{self},
     web::{self, Bytes},
 };
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream::BoxStream};
+
+/// Returned by the calls the proxy never makes
+const UNSUPPORTED: &str = "not supported by the stub provider";
+
+/// Never deployed on the stub provider
+const MISSING_FUNCTION: &str = "missing";
+
+/// Resolves every function but [`MISSING_FUNCTION`] to `upstream`, nothing else
+/// is needed by the proxy
+struct StubProvider {
+    upstream: SocketAddr,
+    protocol: Protocol,
+}
+
+impl Provider for StubProvider {
+    async fn resolve(&self, function: Query) -> Result<Upstream, ResolveError> {
+        if function.service == MISSING_FUNCTION {
+            return Err(ResolveError::NotFound(function.service));
+        }
+        Ok(Upstream::new(
+            http::Uri::builder()
+                .scheme("http")
+                .authority(self.upstream.to_string()),
+        )
+        .with_protocol(self.protocol))
+    }
+    async fn list(&self, _: String) -> Result<Vec<Status>, ListError> {
+        Ok(Vec::new())
+    }
+    async fn deploy(&self, _: Deployment) -> Result<(), DeployError> {
+        Err(DeployError::InternalError(UNSUPPORTED.to_string()))
+    }
+    async fn update(&self, _: Deployment) -> Result<(), UpdateError> {
+        Err(UpdateError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn delete(&self, _: Query) -> Result<(), DeleteError> {
+        Err(DeleteError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn status(&self, _: Query) -> Result<Status, ResolveError> {
+        Err(ResolveError::NotFound(UNSUPPORTED.to_string()))
+    }
+    async fn scale(&self, _: Query, _: u32) -> Result<(), ScaleError> {
+        Err(ScaleError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn list_secrets(&self, _: Option<String>) -> Result<Vec<Secret>, SecretError> {
+        Ok(Vec::new())
+    }
+    async fn create_secret(&self, _: Secret) -> Result<(), SecretError> {
+        Err(SecretError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn update_secret(&self, _: Secret) -> Result<(), SecretError> {
+        Err(SecretError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn delete_secret(&self, _: Secret) -> Result<(), SecretError> {
+        Err(SecretError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn logs(
+        &self,
+        _: Query,
+        _: Option<DateTime<Utc>>,
+        _: Option<usize>,
+        _: bool,
+    ) -> Result<BoxStream<'static, LogMessage>, LogError> {
+        Ok(futures::stream::empty().boxed())
+    }
+    async fn list_namespaces(&self) -> Result<Vec<String>, NamespaceError> {
+        Ok(Vec::new())
+    }
+    async fn create_namespace(&self, _: FunctionNamespace) -> Result<(), NamespaceError> {
+        Err(NamespaceError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn update_namespace(&self, _: FunctionNamespace) -> Result<(), NamespaceError> {
+        Err(NamespaceError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn delete_namespace(&self, _: String) -> Result<(), NamespaceError> {
+        Err(NamespaceError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn health(&self) -> HealthStatus {
+        HealthStatus::new()
+    }
+    async fn info(&self) -> Result<ProviderInfo, InfoError> {
+        Err(InfoError::Internal(UNSUPPORTED.to_string()))
+    }
+    async fn gc(&self, dry_run: bool) -> Result<GcReport, GcError> {
+        Ok(GcReport {
+            dry_run,
+            ..Default::default()
+        })
+    }
+}
+
+/// Answers with the request headers it received, one `name: value` per line
+async fn echo_headers(req: HttpRequest) -> HttpResponse {
+    let mut lines: Vec<_> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value.to_str().unwrap()))
+        .collect();
+    lines.sort();
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .append_header((http::header::SET_COOKIE, "a=1"))
+        .append_header((http::header::SET_COOKIE, "b=2"))
+        .insert_header((http::header::CACHE_CONTROL, "no-store"))
+        .body(lines.join("\n"))
+}
+
+/// A function answering with the path and query it received
+async fn start_upstream() -> (SocketAddr, ServerHandle) {
+    let server = actix_web::HttpServer::new(|| {
+        App::new()
+            .route("/headers", web::to(echo_headers))
+            .default_service(web::to(|req: HttpRequest| async move {
+                HttpResponse::Ok().body(req.uri().to_string())
+            }))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    (addr, handle)
+}
+
+macro_rules! proxy_app {
+    ($upstream:expr) => {
+        proxy_app!($upstream, Protocol::Http1)
+    };
+    ($upstream:expr, $protocol:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    AppState::new(FaaSConfig::default()).unwrap(),
+                ))
+                .app_data(web::Data::new(awc::Client::default()))
+                .app_data(web::Data::new(H2Pool::new(Duration::from_secs(1))))
+                .app_data(web::Data::from(Arc::new(StubProvider {
+                    upstream: $upstream,
+                    protocol: $protocol,
+                })))
+                .service(
+                    web::scope("/function")
+                        .service(web::resource(["", "/"]).route(web::to(missing_function)))
+                        .service(
+                            web::resource(PROXY_DISPATCH_PATH)
+                                .route(web::to(proxy::<StubProvider>)),
+                        ),
+                ),
+        )
+    };
+}
 
 #[actix_web::test]
-#[ignore]
 async fn test_proxy_handler_success() {
-    todo!()
+    let (addr, handle) = start_upstream().await;
+    let app = proxy_app!(addr).await;
+
+    let req = test::TestRequest::post()
+        .uri("/function/echo/sub/path")
+        .set_payload(Bytes::from_static(b"hello"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(resp.headers().contains_key(DURATION_HEADER));
+    assert_eq!(test::read_body(resp).await, "/sub/path");
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn test_forward_path_and_query() {
+    let (addr, handle) = start_upstream().await;
+    let app = proxy_app!(addr).await;
+
+    let cases = [
+        ("/function/echo", "/"),
+        ("/function/echo/", "/"),
+        ("/function/echo?x=1", "/?x=1"),
+        ("/function/echo.ns/a?x=1&y=2", "/a?x=1&y=2"),
+        ("/function/echo/a/b?", "/a/b?"),
+        ("/function/echo/a%2Fb/c", "/a%2Fb/c"),
+        (
+            "/function/echo/%E4%BD%A0%E5%A5%BD?q=%C3%A9",
+            "/%E4%BD%A0%E5%A5%BD?q=%C3%A9",
+        ),
+        ("/function/echo/a%20b?x=%2F%3F&y", "/a%20b?x=%2F%3F&y"),
+    ];
+    for (path, expected) in cases {
+        let req = test::TestRequest::get().uri(path).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK, "{}", path);
+        assert_eq!(test::read_body(resp).await, expected, "{}", path);
+    }
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn test_header_forwarding() {
+    let (addr, handle) = start_upstream().await;
+    let app = proxy_app!(addr).await;
+
+    let req = test::TestRequest::post()
+        .uri("/function/echo/headers")
+        .peer_addr("192.0.2.7:40000".parse().unwrap())
+        .insert_header((http::header::HOST, "gateway.local:8080"))
+        .insert_header((http::header::CONNECTION, "keep-alive, x-hop"))
+        .insert_header(("x-hop", "1"))
+        .insert_header(("keep-alive", "timeout=5"))
+        .insert_header((http::header::TE, "trailers"))
+        .insert_header((http::header::FORWARDED, "for=198.51.100.1"))
+        .append_header(("x-multi", "a"))
+        .append_header(("x-multi", "b"))
+        .insert_header((http::header::CONTENT_LENGTH, 4))
+        .set_payload(Bytes::from_static(b"body"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // end-to-end response headers are kept
+    let headers = resp.headers();
+    assert_eq!(
+        headers.get(http::header::CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(headers.get_all(http::header::SET_COOKIE).count(), 2);
+    assert_eq!(
+        headers.get(http::header::CACHE_CONTROL).unwrap(),
+        "no-store"
+    );
+    assert!(headers.get(http::header::TRANSFER_ENCODING).is_none());
+
+    let body = test::read_body(resp).await;
+    let received = std::str::from_utf8(&body).unwrap();
+    let lines: Vec<_> = received.lines().collect();
+    for expected in [
+        "content-length: 4",
+        "x-multi: a",
+        "x-multi: b",
+        "x-forwarded-host: gateway.local:8080",
+        "x-forwarded-proto: http",
+        "forwarded: for=198.51.100.1, for=192.0.2.7;host=\"gateway.local:8080\";proto=http",
+    ] {
+        assert!(
+            lines.contains(&expected),
+            "{} not in\n{}",
+            expected,
+            received
+        );
+    }
+    for dropped in ["x-hop", "keep-alive", "te", "transfer-encoding"] {
+        assert!(
+            !lines
+                .iter()
+                .any(|line| line.starts_with(&format!("{}:", dropped))),
+            "{} forwarded\n{}",
+            dropped,
+            received
+        );
+    }
+    assert!(!lines.contains(&"host: gateway.local:8080"));
+    handle.stop(false).await;
 }
 
 #[actix_web::test]
@@ -70,32 +357,32 @@ async fn test_path_parsing() {
 
 #[actix_web::test]
 async fn test_invalid_method() {
-    let app = test::init_service(
-        App::new().route("/function/{name}{path:/?.*}", web::to(proxy)),
-    )
-    .await;
+    let (addr, handle) = start_upstream().await;
+    let app = proxy_app!(addr).await;
 
     let req = test::TestRequest::with_uri("/function/test-service/path")
         .method(http::Method::from_bytes(b"INVALID").unwrap())
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+    handle.stop(false).await;
 }
 
 #[actix_web::test]
 async fn test_empty_func_name() {
-    let app = test::init_service(
-        App::new().route("/function{name:/?}{path:/?.*}", web::to(proxy)),
-    )
-    .await;
+    let (addr, handle) = start_upstream().await;
+    let app = proxy_app!(addr).await;
 
-    let req = test::TestRequest::post()
-        .uri("/function")
-        .insert_header((http::header::CONTENT_TYPE, "application/json"))
-        .set_payload(Bytes::from_static(b"{\"key\":\"value\"}"))
-        .to_request();
-    let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    for path in ["/function", "/function/"] {
+        let req = test::TestRequest::post()
+            .uri(path)
+            .insert_header((http::header::CONTENT_TYPE, "application/json"))
+            .set_payload(Bytes::from_static(b"{\"key\":\"value\"}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST, "{}", path);
+    }
+    handle.stop(false).await;
 }
 
 async fn var_handler(req: HttpRequest) -> impl Responder {
@@ -106,3 +393,325 @@ async fn var_handler(req: HttpRequest) -> impl Responder {
         vars.get("params").unwrap_or("")
     ))
 }
+
+#[actix_web::test]
+async fn test_websocket_passthrough() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // a function switching protocols and echoing every byte it receives
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = listener.local_addr().unwrap();
+    actix_web::rt::spawn(async move {
+        let (mut conn, _) = listener.accept().await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(conn.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_lowercase();
+        assert!(head.starts_with("get /ws?room=1 http/1.1\r\n"), "{}", head);
+        assert!(head.contains("upgrade: websocket\r\n"), "{}", head);
+        assert!(head.contains("x-forwarded-proto: http\r\n"), "{}", head);
+        conn.write_all(
+            b"HTTP/1.1 101 Switching Protocols\r\n\
+              upgrade: websocket\r\n\
+              connection: Upgrade\r\n\
+              sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+        )
+        .await
+        .unwrap();
+        let (mut reader, mut writer) = conn.split();
+        tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+    });
+
+    let server =
+        actix_web::HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(
+                    AppState::new(FaaSConfig::default()).unwrap(),
+                ))
+                .app_data(web::Data::new(awc::Client::default()))
+                .app_data(web::Data::new(H2Pool::new(Duration::from_secs(1))))
+                .app_data(web::Data::from(Arc::new(StubProvider {
+                    upstream,
+                    protocol: Protocol::Http1,
+                })))
+                .service(web::scope("/function").service(
+                    web::resource(PROXY_DISPATCH_PATH).route(web::to(proxy::<StubProvider>)),
+                ))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let gateway = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let mut conn = tokio::net::TcpStream::connect(gateway).await.unwrap();
+    conn.write_all(
+        b"GET /function/chat/ws?room=1 HTTP/1.1\r\n\
+          host: gateway\r\n\
+          connection: Upgrade\r\n\
+          upgrade: websocket\r\n\
+          sec-websocket-version: 13\r\n\
+          sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+    )
+    .await
+    .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(conn.read_u8().await.unwrap());
+    }
+    let head = String::from_utf8(head).unwrap().to_lowercase();
+    assert!(head.starts_with("http/1.1 101"), "{}", head);
+    assert!(head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+
+    for message in [&b"ping"[..], b"\x81\x05hello"] {
+        conn.write_all(message).await.unwrap();
+        let mut echoed = vec![0; message.len()];
+        conn.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, message);
+    }
+    drop(conn);
+    handle.stop(false).await;
+}
+
+/// A gRPC-like function over HTTP/2 prior knowledge, it answers with the path and
+/// body it received and reports `grpc-status` in the trailers, the methods named
+/// `Fail` report an internal error
+async fn start_h2_upstream() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    actix_web::rt::spawn(async move {
+        while let Ok((io, _)) = listener.accept().await {
+            actix_web::rt::spawn(async move {
+                let mut conn = h2::server::handshake(io).await.unwrap();
+                while let Some(Ok((request, mut respond))) = conn.accept().await {
+                    actix_web::rt::spawn(async move {
+                        let (parts, mut body) = request.into_parts();
+                        let mut received = parts.uri.path().as_bytes().to_vec();
+                        received.push(b' ');
+                        while let Some(data) = body.data().await {
+                            let data = data.unwrap();
+                            let _ = body.flow_control().release_capacity(data.len());
+                            received.extend_from_slice(&data);
+                        }
+                        let te = parts.headers.get(http::header::TE).cloned();
+                        let response = ::http::Response::builder()
+                            .header(http::header::CONTENT_TYPE, "application/grpc")
+                            .body(())
+                            .unwrap();
+                        let mut stream = respond.send_response(response, false).unwrap();
+                        stream.send_data(Bytes::from(received), false).unwrap();
+                        let mut trailers = ::http::HeaderMap::new();
+                        if parts.uri.path().ends_with("/Fail") {
+                            trailers.insert("grpc-status", "13".parse().unwrap());
+                            trailers.insert("grpc-message", "boom".parse().unwrap());
+                        } else {
+                            trailers.insert("grpc-status", "0".parse().unwrap());
+                        }
+                        if let Some(te) = te {
+                            trailers.insert("x-te", te);
+                        }
+                        stream.send_trailers(trailers).unwrap();
+                    });
+                }
+            });
+        }
+    });
+    addr
+}
+
+#[actix_web::test]
+async fn test_h2_function_http1_caller() {
+    let addr = start_h2_upstream().await;
+    let app = proxy_app!(addr, Protocol::Grpc).await;
+
+    let req = test::TestRequest::post()
+        .uri("/function/greeter/pkg.Greeter/Hello")
+        .set_payload(Bytes::from_static(b"ping"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(resp.headers().contains_key(DURATION_HEADER));
+    assert_eq!(
+        resp.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "application/grpc"
+    );
+    // the trailers can not reach a HTTP/1 caller, the data does
+    assert_eq!(test::read_body(resp).await, "/pkg.Greeter/Hello ping");
+}
+
+/// The gateway as `serve` runs it, HTTP/1 and h2c on the same socket
+async fn start_gateway(upstream: SocketAddr, protocol: Protocol) -> (SocketAddr, ServerHandle) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = FaaSConfig {
+        enable_health: true,
+        ..Default::default()
+    };
+    let provider = Arc::new(StubProvider { upstream, protocol });
+    let server = serve_on(listener, provider, config).unwrap();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    (addr, handle)
+}
+
+async fn h2c_client(gateway: SocketAddr) -> h2::client::SendRequest<Bytes> {
+    let io = tokio::net::TcpStream::connect(gateway).await.unwrap();
+    let (client, conn) = h2::client::handshake(io).await.unwrap();
+    actix_web::rt::spawn(async move {
+        let _ = conn.await;
+    });
+    client.ready().await.unwrap()
+}
+
+/// Send a gRPC call through the h2c front, returns the status, the data and the trailers
+async fn h2c_call(
+    client: &mut h2::client::SendRequest<Bytes>,
+    uri: String,
+) -> (http::StatusCode, Vec<u8>, ::http::HeaderMap) {
+    let request = ::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(http::header::TE, "trailers")
+        .body(())
+        .unwrap();
+    let (response, mut stream) = client.send_request(request, false).unwrap();
+    stream.send_data(Bytes::from_static(b"ping"), true).unwrap();
+    let response = response.await.unwrap();
+    let status = response.status();
+    assert!(response.headers().contains_key(DURATION_HEADER));
+
+    let mut body = response.into_body();
+    let mut received = Vec::new();
+    while let Some(data) = body.data().await {
+        received.extend_from_slice(&data.unwrap());
+    }
+    let trailers = body.trailers().await.unwrap().unwrap();
+    (status, received, trailers)
+}
+
+/// GET `path` on the h2c connection, returns the status and the body
+async fn h2c_get(
+    client: &mut h2::client::SendRequest<Bytes>,
+    uri: String,
+) -> (http::StatusCode, String) {
+    let request = ::http::Request::builder().uri(uri).body(()).unwrap();
+    let (response, _) = client.send_request(request, true).unwrap();
+    let response = response.await.unwrap();
+    let status = response.status();
+    let mut body = response.into_body();
+    let mut received = Vec::new();
+    while let Some(data) = body.data().await {
+        received.extend_from_slice(&data.unwrap());
+    }
+    (status, String::from_utf8(received).unwrap())
+}
+
+#[actix_web::test]
+async fn test_h2c_front_relays_trailers() {
+    let upstream = start_h2_upstream().await;
+    let (gateway, handle) = start_gateway(upstream, Protocol::Grpc).await;
+    let mut client = h2c_client(gateway).await;
+
+    let uri = format!("http://{}/function/greeter/pkg.Greeter/Hello", gateway);
+    let (status, received, trailers) = h2c_call(&mut client, uri).await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(received, b"/pkg.Greeter/Hello ping");
+    assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+    assert_eq!(trailers.get("x-te").unwrap(), "trailers");
+
+    // a failed call is still a 200, the error is in the trailers
+    let uri = format!("http://{}/function/greeter/pkg.Greeter/Fail", gateway);
+    let (status, received, trailers) = h2c_call(&mut client, uri).await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(received, b"/pkg.Greeter/Fail ping");
+    assert_eq!(trailers.get("grpc-status").unwrap(), "13");
+    assert_eq!(trailers.get("grpc-message").unwrap(), "boom");
+
+    // the other routes are served by the app, as on HTTP/1
+    let (status, body) = h2c_get(
+        &mut client,
+        format!("http://{}/system/functions?namespace=default", gateway),
+    )
+    .await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert_eq!(body, "[]");
+    let (status, _) = h2c_get(&mut client, format!("http://{}/healthz", gateway)).await;
+    assert_eq!(status, http::StatusCode::OK);
+    let (status, _) = h2c_get(&mut client, format!("http://{}/function/", gateway)).await;
+    assert_eq!(status, http::StatusCode::BAD_REQUEST);
+
+    // and the invocations are counted like the HTTP/1 ones
+    let (status, body) = h2c_get(&mut client, format!("http://{}/metrics", gateway)).await;
+    assert_eq!(status, http::StatusCode::OK);
+    assert!(body.contains(
+        r#"http_requests_total{method="POST",path="/function/{any:.+}",status="200"} 2"#
+    ));
+    assert!(
+        body.contains(
+            r#"http_requests_total{method="GET",path="/system/functions",status="200"} 1"#
+        )
+    );
+    assert!(body.contains(
+        r#"gateway_function_invocation_total{code="200",function_name="greeter.faasrs-default"} 2"#
+    ));
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn test_http1_fallback() {
+    let (upstream, upstream_handle) = start_upstream().await;
+    let (gateway, handle) = start_gateway(upstream, Protocol::Http1).await;
+    let client = awc::Client::default();
+
+    // a request shorter than the preface is not held back waiting for more bytes
+    let mut resp = client
+        .get(format!("http://{}/healthz", gateway))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    resp.body().await.unwrap();
+
+    let mut resp = client
+        .post(format!("http://{}/function/echo/sub/path?a=1", gateway))
+        .send_body("hello")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.version(), http::Version::HTTP_11);
+    assert_eq!(resp.body().await.unwrap(), "/sub/path?a=1");
+
+    // the same function with its namespace spelled out, and one that is not there
+    let resp = client
+        .post(format!("http://{}/function/echo.faasrs-default", gateway))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let resp = client
+        .post(format!("http://{}/function/{}", gateway, MISSING_FUNCTION))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+
+    let mut resp = client
+        .get(format!("http://{}/metrics", gateway))
+        .send()
+        .await
+        .unwrap();
+    let body = resp.body().await.unwrap();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains(
+        r#"gateway_function_invocation_total{code="200",function_name="echo.faasrs-default"} 2"#
+    ));
+    assert!(body.contains(
+        r#"gateway_function_invocation_total{code="405",function_name="missing.faasrs-default"} 1"#
+    ));
+
+    handle.stop(false).await;
+    upstream_handle.stop(false).await;
+}
@@ -0,0 +1,228 @@
+//! WebSocket passthrough. The handshake is forwarded to the function over a plain
+//! TCP connection, once it switched protocols the bytes are piped both ways.
+//!
+//! actix only hands out the raw bytes after the head for `Upgrade: websocket`
+//! requests, the other upgrades go through [`proxy_request`] as plain requests.
+//!
+//! [`proxy_request`]: super::proxy_handler::proxy_request
+
+use std::{io, time::Duration};
+
+use actix_web::{
+    HttpRequest, HttpResponse,
+    error::{ErrorBadGateway, ErrorInternalServerError, PayloadError},
+    http::{
+        StatusCode,
+        header::{self, HeaderName, HeaderValue},
+    },
+    web::{self, Bytes, BytesMut},
+};
+use futures::StreamExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::Instant,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    proxy::{
+        builder::{content_length, is_hop_by_hop, proxy_headers},
+        proxy_handler::{DURATION_HEADER, duration_seconds, gateway_timeout, until_deadline},
+    },
+    types::upstream::Upstream,
+};
+
+/// Upper bound of the response head of the function
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Upper bound of the body of a refused handshake
+const MAX_REFUSAL_BODY: u64 = 1024 * 1024;
+
+/// Whether the request is a WebSocket handshake
+pub fn is_websocket_upgrade(req: &HttpRequest) -> bool {
+    let has_token = |name: HeaderName, token: &str| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    has_token(header::CONNECTION, "upgrade") && has_token(header::UPGRADE, "websocket")
+}
+
+/// Forward the handshake, then tunnel the connection until either side closes it.
+/// `default_timeout` bounds the handshake when the function sets no timeout.
+pub async fn proxy_upgrade(
+    req: &HttpRequest,
+    payload: web::Payload,
+    upstream: Upstream,
+    path_and_query: &str,
+    default_timeout: Duration,
+) -> actix_web::Result<HttpResponse> {
+    let started = Instant::now();
+    let Upstream {
+        uri,
+        inflight,
+        timeouts,
+        ..
+    } = upstream;
+    let uri = uri.path_and_query(path_and_query).build().map_err(|e| {
+        log::error!("Failed to build URI: {}", e);
+        ErrorInternalServerError("Failed to build URI")
+    })?;
+    let authority = uri
+        .authority()
+        .ok_or_else(|| ErrorInternalServerError("Upstream has no authority"))?
+        .to_string();
+    let path_and_query = uri.path_and_query().map_or("/", |pq| pq.as_str());
+    log::trace!("Upgrading connection to: {}", uri);
+
+    let head = request_head(req, &authority, path_and_query);
+    let handshake = async {
+        let mut stream = TcpStream::connect(&authority).await?;
+        stream.write_all(&head).await?;
+        let (resp, rest) = read_response_head(&mut stream).await?;
+        Ok::<_, io::Error>((stream, resp, rest))
+    };
+    let handshake_timeout = timeouts.response_timeout().unwrap_or(default_timeout);
+    let (mut stream, resp, rest) = match tokio::time::timeout(handshake_timeout, handshake).await {
+        Ok(Ok(handshake)) => handshake,
+        Ok(Err(e)) => {
+            log::error!("Failed to upgrade connection to {}: {}", authority, e);
+            return Err(ErrorBadGateway("Failed to upgrade connection"));
+        }
+        Err(_) => return Ok(gateway_timeout(started)),
+    };
+
+    let mut client_resp = HttpResponse::build(resp.status);
+    for (name, value) in resp.headers.iter() {
+        if is_hop_by_hop(name, resp.headers.get_all(header::CONNECTION))
+            || name == header::CONTENT_LENGTH
+        {
+            continue;
+        }
+        client_resp.append_header((name.clone(), value.clone()));
+    }
+    client_resp.insert_header((DURATION_HEADER, duration_seconds(started)));
+
+    if resp.status != StatusCode::SWITCHING_PROTOCOLS {
+        // the function refused the handshake, relay its answer
+        let length = content_length(resp.headers.get(header::CONTENT_LENGTH)).unwrap_or(0);
+        if length > MAX_REFUSAL_BODY {
+            return Err(ErrorBadGateway("Upgrade refusal too large"));
+        }
+        let mut body = rest.to_vec();
+        body.truncate(length as usize);
+        let missing = length as usize - body.len();
+        if missing > 0 {
+            let mut tail = vec![0; missing];
+            stream.read_exact(&mut tail).await.map_err(|e| {
+                log::error!("Failed to read upgrade refusal: {}", e);
+                ErrorBadGateway("Failed to upgrade connection")
+            })?;
+            body.extend_from_slice(&tail);
+        }
+        return Ok(client_resp.body(body));
+    }
+
+    let protocol = resp
+        .headers
+        .get(header::UPGRADE)
+        .cloned()
+        .unwrap_or(HeaderValue::from_static("websocket"));
+    client_resp.upgrade(protocol);
+
+    // the exec timeout bounds the whole session, both directions included
+    let deadline = timeouts.exec.map(|exec| started + exec);
+    let (reader, mut writer) = stream.into_split();
+    let mut upload = Box::pin(until_deadline(payload, deadline));
+    actix_web::rt::spawn(async move {
+        while let Some(Ok(chunk)) = upload.next().await {
+            if writer.write_all(&chunk).await.is_err() {
+                return;
+            }
+        }
+        let _ = writer.shutdown().await;
+    });
+
+    let download = futures::stream::once(async move { Ok::<_, io::Error>(rest) })
+        .chain(ReaderStream::new(reader))
+        .map(|chunk| chunk.map_err(PayloadError::Io));
+    // the instance stays busy until the session is over
+    Ok(
+        client_resp.streaming(until_deadline(download, deadline).map(move |chunk| {
+            let _ = &inflight;
+            chunk
+        })),
+    )
+}
+
+/// HTTP/1.1 head of the handshake sent to the function
+fn request_head(req: &HttpRequest, authority: &str, path_and_query: &str) -> Vec<u8> {
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nhost: {}\r\n",
+        req.method(),
+        path_and_query,
+        authority
+    )
+    .into_bytes();
+    // Upgrade and Connection are hop-by-hop, the handshake needs them back
+    let mut headers = proxy_headers(req);
+    for name in [header::UPGRADE, header::CONNECTION] {
+        for value in req.headers().get_all(&name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    for (name, value) in headers.iter() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+struct ResponseHead {
+    status: StatusCode,
+    headers: header::HeaderMap,
+}
+
+/// Read the response head of the function, with the bytes already received after it
+async fn read_response_head(stream: &mut TcpStream) -> io::Result<(ResponseHead, Bytes)> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut buf = BytesMut::with_capacity(4096);
+    loop {
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut resp = httparse::Response::new(&mut headers);
+        match resp.parse(&buf).map_err(|e| invalid(e.to_string()))? {
+            httparse::Status::Complete(len) => {
+                let status = StatusCode::from_u16(resp.code.unwrap_or_default())
+                    .map_err(|e| invalid(e.to_string()))?;
+                let mut map = header::HeaderMap::new();
+                for h in resp.headers.iter() {
+                    let name = HeaderName::from_bytes(h.name.as_bytes())
+                        .map_err(|e| invalid(e.to_string()))?;
+                    let value =
+                        HeaderValue::from_bytes(h.value).map_err(|e| invalid(e.to_string()))?;
+                    map.append(name, value);
+                }
+                let rest = buf.split_off(len).freeze();
+                return Ok((
+                    ResponseHead {
+                        status,
+                        headers: map,
+                    },
+                    rest,
+                ));
+            }
+            httparse::Status::Partial if buf.len() > MAX_HEAD_SIZE => {
+                return Err(invalid("response head too large".to_string()));
+            }
+            httparse::Status::Partial => {}
+        }
+    }
+}
@@ -1,20 +1,47 @@
 use std::time::Duration;
 
+use serde::{Deserialize, Deserializer};
+
 const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_MAX_IDLE_CONNS: usize = 1024;
+const DEFAULT_MAX_IDLE_CONNS_PER_HOST: usize = 10;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_IDLE_CONN_TIMEOUT: Duration = Duration::from_secs(90);
 
-#[derive(Debug, Clone)]
+/// Gateway settings, the `[gateway]` table of the daemon configuration file.
+/// Timeouts are given in seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct FaaSConfig {
     pub tcp_port: Option<u16>,
+    #[serde(deserialize_with = "duration_secs")]
     pub read_timeout: Duration,
+    #[serde(deserialize_with = "duration_secs")]
     pub write_timeout: Duration,
+    /// Time allowed to connect to a function instance
+    #[serde(deserialize_with = "duration_secs")]
+    pub connect_timeout: Duration,
+    /// How long an unused upstream connection is kept in the pool
+    #[serde(deserialize_with = "duration_secs")]
+    pub idle_conn_timeout: Duration,
     pub enable_health: bool,
     pub enable_basic_auth: bool,
+    /// Also require basic auth on `/function/*`, only effective with `enable_basic_auth`
+    pub enable_function_auth: bool,
     pub secret_mount_path: String,
+    /// Connections to all the function instances together
     pub max_idle_conns: usize,
+    /// Not enforced, the upstream client only bounds the connections of all
+    /// hosts together through `max_idle_conns`
     pub max_idle_conns_per_host: usize,
 }
 
+/// Accept both `10` and `0.5` as a number of seconds
+pub fn duration_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+}
+
 impl Default for FaaSConfig {
     fn default() -> Self {
         Self::new()
@@ -27,13 +54,43 @@ impl FaaSConfig {
             tcp_port: None,
             read_timeout: Duration::from_secs(10),
             write_timeout: Duration::from_secs(10),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            idle_conn_timeout: DEFAULT_IDLE_CONN_TIMEOUT,
             enable_health: false,
             enable_basic_auth: false,
+            enable_function_auth: false,
             secret_mount_path: String::from("/var/openfaas/secrets"),
             max_idle_conns: 0,
-            max_idle_conns_per_host: 10,
+            max_idle_conns_per_host: DEFAULT_MAX_IDLE_CONNS_PER_HOST,
+        }
+    }
+
+    /// Settings accepted for compatibility but without effect, to be logged
+    pub fn ignored_settings(&self) -> Vec<String> {
+        let mut ignored = Vec::new();
+        if self.max_idle_conns_per_host != DEFAULT_MAX_IDLE_CONNS_PER_HOST {
+            ignored.push(format!(
+                "max_idle_conns_per_host = {} is ignored, the upstream pool is only bounded by max_idle_conns",
+                self.max_idle_conns_per_host
+            ));
+        }
+        ignored
+    }
+
+    /// Reject the values the gateway can not start with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tcp_port == Some(0) {
+            return Err("tcp_port must not be 0".to_string());
+        }
+        if self.write_timeout.is_zero() {
+            return Err("write_timeout must be positive".to_string());
         }
+        if self.enable_basic_auth && self.secret_mount_path.is_empty() {
+            return Err("secret_mount_path is required with enable_basic_auth".to_string());
+        }
+        Ok(())
     }
+
     pub fn get_read_timeout(&self) -> Duration {
         if self.read_timeout <= Duration::from_secs(0) {
             DEFAULT_READ_TIMEOUT
@@ -42,6 +99,22 @@ impl FaaSConfig {
         }
     }
 
+    pub fn get_connect_timeout(&self) -> Duration {
+        if self.connect_timeout.is_zero() {
+            DEFAULT_CONNECT_TIMEOUT
+        } else {
+            self.connect_timeout
+        }
+    }
+
+    pub fn get_idle_conn_timeout(&self) -> Duration {
+        if self.idle_conn_timeout.is_zero() {
+            DEFAULT_IDLE_CONN_TIMEOUT
+        } else {
+            self.idle_conn_timeout
+        }
+    }
+
     pub fn get_max_idle_conns(&self) -> usize {
         if self.max_idle_conns < 1 {
             DEFAULT_MAX_IDLE_CONNS
@@ -1,9 +1,26 @@
 // https://github.com/openfaas/faas/blob/7803ea1861f2a22adcbcfa8c79ed539bc6506d5b/api-docs/spec.openapi.yml
 
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    time::Duration,
+};
 
+use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
+/// Namespace of the functions deployed or invoked without one
+pub const DEFAULT_FUNCTION_NAMESPACE: &str = "faasrs-default";
+
+/// Time allowed to receive the request body from the caller
+pub const READ_TIMEOUT_ANNOTATION: &str = "com.openfaas.timeout.read";
+/// Time allowed for the function to start answering
+pub const WRITE_TIMEOUT_ANNOTATION: &str = "com.openfaas.timeout.write";
+/// Upper bound of a whole invocation, streaming the response body included
+pub const EXEC_TIMEOUT_ANNOTATION: &str = "com.openfaas.timeout.exec";
+/// Protocol the function serves, see [`Protocol`]
+pub const PROTOCOL_ANNOTATION: &str = "com.faasrs.protocol";
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Deployment {
@@ -69,6 +86,23 @@ pub struct Usage {
     pub total_memory_bytes: Option<f64>,
 }
 
+/// Replicas prepared ahead so scaling out only starts a task
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmPool {
+    /// Replicas the pool is kept at
+    pub size: i32,
+
+    /// Replicas prepared right now
+    pub ready: i32,
+
+    /// Scale outs served from the pool
+    pub hits: u64,
+
+    /// Scale outs that found the pool empty
+    pub misses: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Status {
@@ -118,9 +152,25 @@ pub struct Status {
     /// The current available amount of replicas
     pub available_replicas: Option<i32>,
 
+    /// Replicas frozen while the function is idle, resumed by the next invocation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_replicas: Option<i32>,
+
+    /// Prepared replicas waiting to serve, for the functions keeping a warm pool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warm_pool: Option<WarmPool>,
+
     /// The time read back from the faas backend's data store for when the function or its container was created
     pub created_at: Option<String>,
 
+    /// Digest of the manifest the image resolved to when the function was deployed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
+
+    /// Address of every serving replica, by replica index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<BTreeMap<u32, String>>,
+
     /// Usage statistics for the function
     pub usage: Option<Usage>,
 }
@@ -147,7 +197,7 @@ impl FromStr for Query {
         } else {
             Self {
                 service: function_name.to_string(),
-                namespace: Some("default".to_string()),
+                namespace: Some(DEFAULT_FUNCTION_NAMESPACE.to_string()),
             }
         })
     }
@@ -161,6 +211,178 @@ pub struct Delete {
     pub namespace: String,
 }
 
+/// Invocation timeouts of a function, unset ones fall back to the gateway defaults
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionTimeouts {
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+    pub exec: Option<Duration>,
+}
+
+impl FunctionTimeouts {
+    /// How long to wait for the response head, exec also bounds it
+    pub fn response_timeout(&self) -> Option<Duration> {
+        match (self.write, self.exec) {
+            (Some(write), Some(exec)) => Some(write.min(exec)),
+            (write, exec) => write.or(exec),
+        }
+    }
+
+    /// Read the `com.openfaas.timeout.*` annotations, e.g. `30s`, `1m30s`, `500ms` or `15`
+    pub fn from_annotations(annotations: Option<&HashMap<String, String>>) -> Result<Self, String> {
+        let get = |key: &str| -> Result<Option<Duration>, String> {
+            annotations
+                .and_then(|annotations| annotations.get(key))
+                .map(|value| {
+                    parse_duration(value)
+                        .map_err(|e| format!("annotation {} = {:?}: {}", key, value, e))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            read: get(READ_TIMEOUT_ANNOTATION)?,
+            write: get(WRITE_TIMEOUT_ANNOTATION)?,
+            exec: get(EXEC_TIMEOUT_ANNOTATION)?,
+        })
+    }
+}
+
+/// Protocol spoken by the instances of a function
+#[derive(Serialize, Deserialize, Display, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    #[display("http1")]
+    Http1,
+    /// HTTP/2 over cleartext with prior knowledge
+    #[display("h2c")]
+    H2c,
+    /// h2c, the response trailers carry the status of the call
+    #[display("grpc")]
+    Grpc,
+}
+
+impl Protocol {
+    /// Read the `com.faasrs.protocol` annotation, http1 if unset
+    pub fn from_annotations(annotations: Option<&HashMap<String, String>>) -> Result<Self, String> {
+        annotations
+            .and_then(|annotations| annotations.get(PROTOCOL_ANNOTATION))
+            .map_or(Ok(Protocol::default()), |value| value.parse())
+    }
+
+    pub fn is_http2(&self) -> bool {
+        matches!(self, Protocol::H2c | Protocol::Grpc)
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http1" => Ok(Protocol::Http1),
+            "h2c" => Ok(Protocol::H2c),
+            "grpc" => Ok(Protocol::Grpc),
+            _ => Err(format!(
+                "annotation {} = {:?}: expected http1, h2c or grpc",
+                PROTOCOL_ANNOTATION, s
+            )),
+        }
+    }
+}
+
+/// Go style durations such as `1h`, `1m30s` or `250ms`, a bare number is in seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let total = match value.parse::<f64>() {
+        Ok(secs) => Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())?,
+        Err(_) => parse_units(value)?,
+    };
+    if total.is_zero() {
+        return Err("duration must be positive".to_string());
+    }
+    Ok(total)
+}
+
+/// Sum of the `<number><unit>` parts of `value`
+fn parse_units(value: &str) -> Result<Duration, String> {
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or("missing unit")?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().map_err(|_| "invalid number")?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let scale = match unit {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(format!("unknown unit {:?}", unit)),
+        };
+        total += Duration::try_from_secs_f64(number * scale).map_err(|e| e.to_string())?;
+        rest = tail;
+    }
+    Ok(total)
+}
+
 const fn default_read_only_root_filesystem() -> bool {
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("15"), Ok(Duration::from_secs(15)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("0.0").is_err());
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_protocol_from_annotations() {
+        assert_eq!(Protocol::from_annotations(None), Ok(Protocol::Http1));
+        let annotations = HashMap::from([(PROTOCOL_ANNOTATION.to_string(), "grpc".to_string())]);
+        let protocol = Protocol::from_annotations(Some(&annotations)).unwrap();
+        assert_eq!(protocol, Protocol::Grpc);
+        assert!(protocol.is_http2());
+        assert_eq!(protocol.to_string().parse(), Ok(protocol));
+
+        let annotations = HashMap::from([(PROTOCOL_ANNOTATION.to_string(), "h3".to_string())]);
+        assert!(Protocol::from_annotations(Some(&annotations)).is_err());
+    }
+
+    #[test]
+    fn test_timeouts_from_annotations() {
+        let annotations = HashMap::from([
+            (WRITE_TIMEOUT_ANNOTATION.to_string(), "5m".to_string()),
+            (EXEC_TIMEOUT_ANNOTATION.to_string(), "10m".to_string()),
+        ]);
+        let timeouts = FunctionTimeouts::from_annotations(Some(&annotations)).unwrap();
+        assert_eq!(timeouts.read, None);
+        assert_eq!(timeouts.write, Some(Duration::from_secs(300)));
+        assert_eq!(timeouts.exec, Some(Duration::from_secs(600)));
+        assert_eq!(
+            FunctionTimeouts::from_annotations(None),
+            Ok(FunctionTimeouts::default())
+        );
+
+        let annotations =
+            HashMap::from([(READ_TIMEOUT_ANNOTATION.to_string(), "soon".to_string())]);
+        assert!(FunctionTimeouts::from_annotations(Some(&annotations)).is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// Response of `POST /system/gc`, everything found orphaned. With `dry_run`
+/// nothing was removed, otherwise the entries are what got removed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// Containers of functions the provider no longer knows, `<namespace>/<id>`
+    pub containers: Vec<String>,
+    /// Snapshots no container uses anymore, `<namespace>/<key>`
+    pub snapshots: Vec<String>,
+    /// Network namespaces of replicas that are gone
+    pub netns: Vec<String>,
+    /// IP addresses still leased to replicas that are gone
+    pub ipam_leases: Vec<String>,
+    /// Database records of functions and replicas that are gone, `<tree>/<key>`
+    pub records: Vec<String>,
+    /// Resources that could not be removed, the others were
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
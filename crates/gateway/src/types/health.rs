@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Result of `GET /healthz`, the overall state plus a breakdown per component
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ComponentHealth {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Default for HealthStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthStatus {
+    pub fn new() -> Self {
+        Self {
+            healthy: true,
+            components: BTreeMap::new(),
+        }
+    }
+
+    /// Record the check result of a component, any failure marks the whole status unhealthy
+    pub fn add<E: std::fmt::Display>(&mut self, component: &str, result: Result<(), E>) {
+        let health = match result {
+            Ok(()) => ComponentHealth {
+                healthy: true,
+                message: None,
+            },
+            Err(e) => {
+                self.healthy = false;
+                ComponentHealth {
+                    healthy: false,
+                    message: Some(e.to_string()),
+                }
+            }
+        };
+        self.components.insert(component.to_string(), health);
+    }
+}
@@ -0,0 +1,31 @@
+// https://github.com/openfaas/faas-provider/blob/master/types/read_config.go
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Response of `GET /system/info`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderInfo {
+    /// Name of the faas-provider
+    #[serde(rename = "provider")]
+    pub name: String,
+
+    /// Version of the faas-provider
+    pub version: VersionInfo,
+
+    /// Orchestration the provider is built on
+    pub orchestration: String,
+
+    /// Provider specific details, such as the runtime version, snapshotter or network
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionInfo {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commit_message: String,
+    pub sha: String,
+    pub release: String,
+}
@@ -0,0 +1,22 @@
+// https://github.com/openfaas/faas-provider/blob/master/logs/logs.go
+
+use serde::{Deserialize, Serialize};
+
+/// One line of function output, sent as newline-delimited JSON by `GET /system/logs`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogMessage {
+    /// Name of the function
+    pub name: String,
+
+    /// Namespace of the function
+    pub namespace: String,
+
+    /// Container instance of the function which wrote the line
+    pub instance: String,
+
+    /// RFC 3339 time the line was written
+    pub timestamp: String,
+
+    /// Content of the line, without the trailing newline
+    pub text: String,
+}
@@ -1,2 +1,9 @@
 pub mod config;
 pub mod function;
+pub mod gc;
+pub mod health;
+pub mod info;
+pub mod logs;
+pub mod namespace;
+pub mod secret;
+pub mod upstream;
@@ -0,0 +1,19 @@
+// https://github.com/openfaas/faas-provider/blob/master/types/model.go
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Namespace functions can be deployed to, see `/system/namespace/{name}`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionNamespace {
+    /// Name of the namespace
+    pub name: String,
+
+    /// Labels are metadata for namespaces which may be used by the faas-provider
+    pub labels: Option<HashMap<String, String>>,
+
+    /// Annotations are metadata for namespaces which may be used by the faas-provider
+    pub annotations: Option<HashMap<String, String>>,
+}
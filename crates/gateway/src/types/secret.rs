@@ -0,0 +1,58 @@
+// https://github.com/openfaas/faas-provider/blob/master/types/secret.go
+
+use serde::{Deserialize, Serialize};
+
+/// Secret made available to functions at `/var/openfaas/secrets/<name>`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Secret {
+    /// Name of the secret, also the file name inside the function
+    pub name: String,
+
+    /// Namespace of the secret, the functions of this namespace can use it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// Value of the secret, never returned by the list endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+
+    /// Base64 encoded binary value, used instead of `value` when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_value: Option<String>,
+}
+
+impl Secret {
+    /// Content written to the secret file
+    pub fn content(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        match (&self.raw_value, &self.value) {
+            (Some(raw), _) => base64::decode(raw),
+            (None, Some(value)) => Ok(value.clone().into_bytes()),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_content() {
+        let secret: Secret =
+            serde_json::from_str(r#"{"name":"db-password","rawValue":"aHVudGVyMg=="}"#).unwrap();
+        assert_eq!(secret.content().unwrap(), b"hunter2");
+
+        let secret: Secret =
+            serde_json::from_str(r#"{"name":"db-password","value":"s3cr3t"}"#).unwrap();
+        assert_eq!(secret.content().unwrap(), b"s3cr3t");
+        assert_eq!(
+            serde_json::to_string(&Secret {
+                name: "db-password".to_string(),
+                ..Default::default()
+            })
+            .unwrap(),
+            r#"{"name":"db-password"}"#
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use super::function::{FunctionTimeouts, Protocol};
+
+/// Instance picked by `Provider::resolve` to serve one invocation
+#[derive(Debug)]
+pub struct Upstream {
+    /// Scheme and authority of the instance, the path is filled in by the proxy
+    pub uri: actix_http::uri::Builder,
+    /// Kept alive by the proxy until the response body has been streamed
+    pub inflight: Option<InflightGuard>,
+    /// Enforced by the proxy on this invocation
+    pub timeouts: FunctionTimeouts,
+    /// How the proxy talks to the instance
+    pub protocol: Protocol,
+}
+
+impl Upstream {
+    pub fn new(uri: actix_http::uri::Builder) -> Self {
+        Upstream {
+            uri,
+            inflight: None,
+            timeouts: FunctionTimeouts::default(),
+            protocol: Protocol::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: FunctionTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Count this invocation as outstanding on `counter` until the guard is dropped
+    pub fn with_inflight(mut self, counter: Arc<AtomicUsize>) -> Self {
+        self.inflight = Some(InflightGuard::new(counter));
+        self
+    }
+}
+
+/// Outstanding request on an instance, released on drop
+#[derive(Debug)]
+pub struct InflightGuard(Arc<AtomicUsize>);
+
+impl InflightGuard {
+    pub fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::AcqRel);
+        InflightGuard(counter)
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inflight_guard() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let upstream = Upstream::new(actix_http::Uri::builder()).with_inflight(counter.clone());
+        assert_eq!(counter.load(Ordering::Acquire), 1);
+        drop(upstream);
+        assert_eq!(counter.load(Ordering::Acquire), 0);
+    }
+}